@@ -0,0 +1,414 @@
+// A standalone server that speaks the Redis protocol (RESP) over TCP and
+// answers CL.THROTTLE from memory, so that services can be tested against the
+// rate limiter without a Redis server with the module loaded.
+//
+// Only a handful of commands are implemented: CL.THROTTLE in its basic form,
+// along with PING, DEL, TTL, and QUIT. Every connection gets a thread of its
+// own, and all of them share a single store, which removes keys once they've
+// expired. Nothing is ever persisted.
+
+extern crate redis_cell;
+extern crate time;
+
+use redis_cell::cell;
+use redis_cell::cell::store::{ConcurrentMemoryStore, Store};
+use redis_cell::error::CellError;
+use std::env;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::thread;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:6379";
+
+// Most arguments that a single command can have, and the longest that any one
+// of them can be, so that a bad client can't have the server allocate an
+// arbitrary amount of memory.
+const MAX_ARGS: usize = 1024;
+const MAX_ARG_LEN: usize = 512 * 1024 * 1024;
+
+fn main() {
+    let address = env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+    let listener = match TcpListener::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Couldn't listen on {address}: {e}");
+            process::exit(1);
+        }
+    };
+    println!("redis-cell-server listening on {address}");
+
+    let store = ConcurrentMemoryStore::new();
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let store = store.clone();
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream, store) {
+                        eprintln!("Connection error: {e}");
+                    }
+                });
+            }
+            Err(e) => eprintln!("Couldn't accept connection: {e}"),
+        }
+    }
+}
+
+// Reads commands from a connection and writes back their replies until the
+// client hangs up.
+fn serve(stream: TcpStream, mut store: ConcurrentMemoryStore) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    loop {
+        let args = match read_command(&mut reader) {
+            Ok(Some(args)) => args,
+            Ok(None) => return Ok(()),
+
+            // The rest of the stream can't be made sense of after a protocol
+            // error, so the connection's closed after reporting it.
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                Reply::Error(format!("ERR Protocol error: {e}")).write_to(&mut writer)?;
+                return writer.flush();
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Blank lines sent by inline clients are skipped without a reply.
+        if args.is_empty() {
+            continue;
+        }
+
+        let quit = args[0].eq_ignore_ascii_case("quit");
+        execute(&mut store, &args).write_to(&mut writer)?;
+
+        // Replies are flushed once a client's pipelined commands have all
+        // been read so that they go out together.
+        if quit || reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+        if quit {
+            return Ok(());
+        }
+    }
+}
+
+// `Reply` is a RESP value sent back to a client.
+#[derive(Debug, Eq, PartialEq)]
+enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<String>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        match self {
+            Reply::Simple(s) => write!(w, "+{s}\r\n"),
+            Reply::Error(s) => write!(w, "-{s}\r\n"),
+            Reply::Integer(n) => write!(w, ":{n}\r\n"),
+            Reply::Bulk(None) => write!(w, "$-1\r\n"),
+            Reply::Bulk(Some(s)) => write!(w, "${}\r\n{s}\r\n", s.len()),
+            Reply::Array(items) => {
+                write!(w, "*{}\r\n", items.len())?;
+                items.iter().try_for_each(|item| item.write_to(w))
+            }
+        }
+    }
+}
+
+// Reads the next command from a client, either as an array of bulk strings or
+// as an inline command of words separated by spaces. Returns `None` once the
+// client has hung up.
+fn read_command(r: &mut impl BufRead) -> io::Result<Option<Vec<String>>> {
+    let Some(line) = read_line(r)? else {
+        return Ok(None);
+    };
+
+    let Some(count) = line.strip_prefix('*') else {
+        return Ok(Some(line.split_whitespace().map(String::from).collect()));
+    };
+    let count = parse_length(count, MAX_ARGS)?;
+
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        let line = read_line(r)?.ok_or_else(unexpected_eof)?;
+        let Some(len) = line.strip_prefix('$') else {
+            return Err(invalid_data(format!("expected '$', got '{line}'")));
+        };
+        let len = parse_length(len, MAX_ARG_LEN)?;
+
+        let mut buf = vec![0; len + 2];
+        r.read_exact(&mut buf)?;
+        if !buf.ends_with(b"\r\n") {
+            return Err(invalid_data("bulk string isn't terminated by CRLF"));
+        }
+        buf.truncate(len);
+        args.push(String::from_utf8(buf).map_err(invalid_data)?);
+    }
+    Ok(Some(args))
+}
+
+// Reads a line without its trailing CRLF, or `None` at the end of the stream.
+fn read_line(r: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if r.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') {
+        return Err(unexpected_eof());
+    }
+    line.truncate(line.trim_end_matches(['\r', '\n']).len());
+    Ok(Some(line))
+}
+
+fn parse_length(s: &str, max: usize) -> io::Result<usize> {
+    match s.parse::<usize>() {
+        Ok(n) if n <= max => Ok(n),
+        _ => Err(invalid_data(format!("invalid length '{s}'"))),
+    }
+}
+
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::from(io::ErrorKind::UnexpectedEof)
+}
+
+// Runs a single command against the store.
+fn execute(store: &mut ConcurrentMemoryStore, args: &[String]) -> Reply {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[0].to_ascii_lowercase().as_str() {
+        // Errors from the rate limiter are reported the same way as the
+        // module reports them.
+        "cl.throttle" => throttle(store, &args[1..])
+            .unwrap_or_else(|e| Reply::Error(format!("Cell error: {e}"))),
+        "del" => del(store, &args[1..]),
+        "ping" => ping(&args[1..]),
+        "quit" => Reply::Simple("OK".to_string()),
+        "ttl" => ttl(store, &args[1..]),
+        _ => Reply::Error(format!("ERR unknown command '{}'", args[0])),
+    }
+}
+
+fn del(store: &mut ConcurrentMemoryStore, args: &[&str]) -> Reply {
+    if args.is_empty() {
+        return wrong_arity("del");
+    }
+
+    let mut deleted = 0;
+    for key in args {
+        // Deleting from memory can't fail.
+        if store.delete(key).unwrap_or(false) {
+            deleted += 1;
+        }
+    }
+    Reply::Integer(deleted)
+}
+
+fn ping(args: &[&str]) -> Reply {
+    match args {
+        [] => Reply::Simple("PONG".to_string()),
+        [message] => Reply::Bulk(Some(message.to_string())),
+        _ => wrong_arity("ping"),
+    }
+}
+
+// Replies like the module's CL.THROTTLE does to a RESP2 client, with times in
+// seconds.
+fn throttle(
+    store: &mut ConcurrentMemoryStore,
+    args: &[&str],
+) -> Result<Reply, CellError> {
+    if args.len() != 4 && args.len() != 5 {
+        return Err(CellError::generic(
+            "Usage: cl.throttle <key> <max_burst> <count per period> <period> \
+             [<quantity>]",
+        ));
+    }
+
+    let key = args[0];
+    let max_burst = args[1].parse::<i64>()?;
+    let count = args[2].parse::<i64>()?;
+    let period = args[3].parse::<i64>()?;
+    let quantity = match args.get(4) {
+        Some(quantity) => quantity.parse::<i64>()?,
+        None => 1,
+    };
+
+    let quota = cell::RateQuota {
+        max_burst,
+        max_rate: cell::Rate::per_period(count, time::Duration::seconds(period)),
+    };
+    let mut limiter = cell::RateLimiter::new(store, &quota);
+    let (throttled, result) = limiter.rate_limit(key, quantity)?;
+
+    Ok(Reply::Array(vec![
+        Reply::Integer(if throttled { 1 } else { 0 }),
+        Reply::Integer(result.limit),
+        Reply::Integer(result.remaining),
+        Reply::Integer(round_up_seconds(result.retry_after)),
+        Reply::Integer(round_up_seconds(result.reset_after)),
+    ]))
+}
+
+// Replies like Redis does: -2 for a key that doesn't exist, -1 for one that
+// never expires, and otherwise the seconds left, rounded to the nearest one.
+fn ttl(store: &ConcurrentMemoryStore, args: &[&str]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("ttl");
+    };
+
+    match store.ttl(key) {
+        Ok(None) => Reply::Integer(-2),
+        Ok(Some(None)) => Reply::Integer(-1),
+        Ok(Some(Some(ttl))) => {
+            Reply::Integer((ttl.whole_milliseconds() as i64 + 500) / 1000)
+        }
+        Err(e) => Reply::Error(format!("Cell error: {e}")),
+    }
+}
+
+fn wrong_arity(command: &str) -> Reply {
+    Reply::Error(format!(
+        "ERR wrong number of arguments for '{command}' command"
+    ))
+}
+
+// If a time has a partial component, put it up to the next full second because
+// otherwise a fast-paced caller could try again too early. A negative time is
+// a -1 sentinel and stays that way.
+fn round_up_seconds(duration: time::Duration) -> i64 {
+    if duration < time::Duration::ZERO {
+        return -1;
+    }
+
+    let mut seconds = duration.whole_seconds();
+    if duration.subsec_nanoseconds() > 0 {
+        seconds += 1
+    }
+    seconds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(store: &mut ConcurrentMemoryStore, command: &str) -> Reply {
+        let args: Vec<String> = command.split_whitespace().map(String::from).collect();
+        execute(store, &args)
+    }
+
+    fn write(reply: Reply) -> String {
+        let mut buf = Vec::new();
+        reply.write_to(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn it_reads_commands() {
+        let mut input: &[u8] =
+            b"*2\r\n$4\r\nPING\r\n$5\r\nhello\r\nttl  foo\r\n\r\n*1\r\n$0\r\n\r\n";
+        assert_eq!(
+            Some(vec!["PING".to_string(), "hello".to_string()]),
+            read_command(&mut input).unwrap()
+        );
+        assert_eq!(
+            Some(vec!["ttl".to_string(), "foo".to_string()]),
+            read_command(&mut input).unwrap()
+        );
+        assert_eq!(Some(vec![]), read_command(&mut input).unwrap());
+        assert_eq!(Some(vec![String::new()]), read_command(&mut input).unwrap());
+        assert_eq!(None, read_command(&mut input).unwrap());
+    }
+
+    #[test]
+    fn it_rejects_malformed_commands() {
+        for input in [
+            &b"*1\r\n:1\r\n"[..],
+            b"*1\r\n$3\r\nfooo\r\n",
+            b"*x\r\n",
+            b"*1\r\n$2\r\n",
+            b"ping",
+        ] {
+            assert!(read_command(&mut &input[..]).is_err());
+        }
+    }
+
+    #[test]
+    fn it_writes_replies() {
+        assert_eq!("+PONG\r\n", write(Reply::Simple("PONG".to_string())));
+        assert_eq!("-ERR nope\r\n", write(Reply::Error("ERR nope".to_string())));
+        assert_eq!("$-1\r\n", write(Reply::Bulk(None)));
+        assert_eq!(
+            "*2\r\n:-1\r\n$3\r\nfoo\r\n",
+            write(Reply::Array(vec![
+                Reply::Integer(-1),
+                Reply::Bulk(Some("foo".to_string()))
+            ]))
+        );
+    }
+
+    #[test]
+    fn it_pings() {
+        let mut store = ConcurrentMemoryStore::new();
+        assert_eq!(Reply::Simple("PONG".to_string()), run(&mut store, "PING"));
+        assert_eq!(
+            Reply::Bulk(Some("hello".to_string())),
+            run(&mut store, "ping hello")
+        );
+    }
+
+    #[test]
+    fn it_throttles() {
+        let mut store = ConcurrentMemoryStore::new();
+        let integers = |values: [i64; 5]| Reply::Array(values.map(Reply::Integer).into());
+
+        assert_eq!(
+            integers([0, 2, 1, -1, 60]),
+            run(&mut store, "CL.THROTTLE foo 1 1 60")
+        );
+        assert_eq!(
+            integers([0, 2, 0, -1, 120]),
+            run(&mut store, "cl.throttle foo 1 1 60")
+        );
+        assert_eq!(
+            integers([1, 2, 0, 60, 120]),
+            run(&mut store, "cl.throttle foo 1 1 60")
+        );
+
+        // Keys expire once their limiters have drained, which TTL reports.
+        assert_eq!(Reply::Integer(120), run(&mut store, "TTL foo"));
+        assert_eq!(Reply::Integer(-2), run(&mut store, "TTL bar"));
+
+        assert_eq!(Reply::Integer(1), run(&mut store, "DEL foo bar"));
+        assert_eq!(
+            integers([0, 2, 1, -1, 60]),
+            run(&mut store, "cl.throttle foo 1 1 60")
+        );
+    }
+
+    #[test]
+    fn it_rejects_bad_commands() {
+        let mut store = ConcurrentMemoryStore::new();
+        for command in [
+            "flushall",
+            "cl.throttle foo 1 1",
+            "cl.throttle foo 1 1 x",
+            "del",
+            "ttl",
+        ] {
+            assert!(
+                matches!(run(&mut store, command), Reply::Error(_)),
+                "{command} should be rejected"
+            );
+        }
+    }
+}
@@ -0,0 +1,296 @@
+// Asynchronous versions of the store and GCRA rate limiter, for programs that
+// run on an async executor like tokio and can't block it on network I/O.
+
+use crate::cell::store::{ConcurrentMemoryStore, MemoryStore, Store};
+use crate::cell::{Evaluation, Gcra, RateLimitResult, RateQuota, retry_cas};
+use crate::error::CellError;
+use std::future::Future;
+
+/// `AsyncStore` exposes the same atomic data store operations as
+/// `store::Store` that the GCRA rate limiter needs, but as futures.
+///
+/// Futures have to be `Send` so that a limiter can be used from tasks that
+/// move between threads, and mustn't block the thread that polls them.
+///
+/// The in-memory stores implement this by running their synchronous
+/// operations right away, which only holds if their clock doesn't block
+/// either. Give them a clock like `SystemClock` or `ManualClock` rather than
+/// a `RedisClock`, which waits on a server.
+pub trait AsyncStore {
+    /// Compares the value at the given key with a known old value and swaps it
+    /// for a new value if and only if they're equal. Also sets the key's TTL
+    /// until it expires.
+    fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: u64,
+        new: u64,
+        ttl: time::Duration,
+    ) -> impl Future<Output = Result<bool, CellError>> + Send;
+
+    /// Gets the given key's value and the current time as dictated by the
+    /// store's clock. `None` is returned if the key was unset.
+    fn get_with_time(
+        &self,
+        key: &str,
+    ) -> impl Future<Output = Result<(Option<u64>, time::OffsetDateTime), CellError>> + Send;
+
+    /// Logs a debug message to the data store.
+    fn log_debug(&self, message: &str);
+
+    /// Sets the given key to the given value if and only if it doesn't already
+    /// exist. Also sets the key's TTL until it expires.
+    fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: u64,
+        ttl: time::Duration,
+    ) -> impl Future<Output = Result<bool, CellError>> + Send;
+}
+
+// Implement the `AsyncStore` trait for a mutable reference, for the same
+// reason as `Store` is: so that limiters can borrow a store rather than own it.
+impl<T: AsyncStore + Send + Sync> AsyncStore for &mut T {
+    fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: u64,
+        new: u64,
+        ttl: time::Duration,
+    ) -> impl Future<Output = Result<bool, CellError>> + Send {
+        (**self).compare_and_swap_with_ttl(key, old, new, ttl)
+    }
+
+    fn get_with_time(
+        &self,
+        key: &str,
+    ) -> impl Future<Output = Result<(Option<u64>, time::OffsetDateTime), CellError>> + Send
+    {
+        (**self).get_with_time(key)
+    }
+
+    fn log_debug(&self, message: &str) {
+        (**self).log_debug(message)
+    }
+
+    fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: u64,
+        ttl: time::Duration,
+    ) -> impl Future<Output = Result<bool, CellError>> + Send {
+        (**self).set_if_not_exists_with_ttl(key, value, ttl)
+    }
+}
+
+// The in-memory stores never wait on anything but a short-lived lock, so
+// their operations complete right away as long as their clocks do (see
+// above).
+impl AsyncStore for MemoryStore {
+    async fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: u64,
+        new: u64,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        Store::compare_and_swap_with_ttl(self, key, old, new, ttl)
+    }
+
+    async fn get_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<u64>, time::OffsetDateTime), CellError> {
+        Store::get_with_time(self, key)
+    }
+
+    fn log_debug(&self, message: &str) {
+        Store::log_debug(self, message)
+    }
+
+    async fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: u64,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        Store::set_if_not_exists_with_ttl(self, key, value, ttl)
+    }
+}
+
+impl AsyncStore for ConcurrentMemoryStore {
+    async fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: u64,
+        new: u64,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        Store::compare_and_swap_with_ttl(self, key, old, new, ttl)
+    }
+
+    async fn get_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<u64>, time::OffsetDateTime), CellError> {
+        Store::get_with_time(self, key)
+    }
+
+    fn log_debug(&self, message: &str) {
+        Store::log_debug(self, message)
+    }
+
+    async fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: u64,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        Store::set_if_not_exists_with_ttl(self, key, value, ttl)
+    }
+}
+
+/// `AsyncRateLimiter` is the asynchronous counterpart of `RateLimiter`. It
+/// runs exactly the same GCRA, but waits on its store instead of blocking.
+pub struct AsyncRateLimiter<T> {
+    pub store: T,
+
+    gcra: Gcra,
+}
+
+impl<T: AsyncStore> AsyncRateLimiter<T> {
+    pub fn new(store: T, quota: &RateQuota) -> Self {
+        AsyncRateLimiter {
+            gcra: Gcra::new(quota),
+            store,
+        }
+    }
+
+    /// Peek evaluates whether a request of the given quantity against a
+    /// particular key would be rate limited, but without persisting anything
+    /// to the underlying store. See `RateLimiter::peek`.
+    pub async fn peek(
+        &self,
+        key: &str,
+        quantity: i64,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let increment = self.gcra.increment(quantity)?;
+        self.gcra
+            .log_start(&|m| self.store.log_debug(m), key, quantity, increment);
+
+        let (tat_val, now) = self.store.get_with_time(key).await?;
+        let evaluation = self.evaluate(tat_val, now, increment);
+
+        self.gcra
+            .log_end(&|m| self.store.log_debug(m), &evaluation.result);
+        Ok((evaluation.limited, evaluation.result))
+    }
+
+    /// RateLimit checks whether a particular key has exceeded a rate limit,
+    /// taking the given quantity from it if it hasn't. See
+    /// `RateLimiter::rate_limit`.
+    pub async fn rate_limit(
+        &mut self,
+        key: &str,
+        quantity: i64,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let increment = self.gcra.increment(quantity)?;
+        self.gcra
+            .log_start(&|m| self.store.log_debug(m), key, quantity, increment);
+
+        // Retries when another limiter changes the key first, just like
+        // `RateLimiter` does.
+        let mut i = 0;
+        let evaluation = loop {
+            log_debug!(self.store, "iteration = {}", i);
+
+            let (tat_val, now) = self.store.get_with_time(key).await?;
+            let evaluation = self.evaluate(tat_val, now, increment);
+
+            let Some(new_tat_ns) = evaluation.tat_to_commit(tat_val, now) else {
+                break evaluation;
+            };
+            let updated = if let Some(some_tat_val) = tat_val {
+                self.store
+                    .compare_and_swap_with_ttl(
+                        key,
+                        some_tat_val,
+                        new_tat_ns,
+                        evaluation.ttl,
+                    )
+                    .await?
+            } else {
+                self.store
+                    .set_if_not_exists_with_ttl(key, new_tat_ns, evaluation.ttl)
+                    .await?
+            };
+
+            if updated {
+                break evaluation;
+            }
+
+            retry_cas(&mut i, "update rate limit")?;
+        };
+
+        self.gcra
+            .log_end(&|m| self.store.log_debug(m), &evaluation.result);
+        Ok((evaluation.limited, evaluation.result))
+    }
+
+    fn evaluate(
+        &self,
+        tat_val: Option<u64>,
+        now: time::OffsetDateTime,
+        increment: time::Duration,
+    ) -> Evaluation {
+        self.gcra
+            .evaluate(&|m| self.store.log_debug(m), tat_val, now, increment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::aio::*;
+    use crate::cell::clock::ManualClock;
+    use crate::cell::{Rate, RateLimiter};
+
+    #[tokio::test]
+    async fn it_rate_limits_like_the_synchronous_limiter() {
+        let quota = RateQuota {
+            max_burst: 2,
+            max_rate: Rate::per_second(1),
+        };
+        let clock = ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut sync_store = MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut async_store = MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut sync_limiter = RateLimiter::new(&mut sync_store, &quota);
+        let mut async_limiter = AsyncRateLimiter::new(&mut async_store, &quota);
+
+        for (quantity, advance) in [(1, 0), (2, 0), (1, 0), (1, 1500), (0, 0), (4, 0)] {
+            clock.advance(time::Duration::milliseconds(advance));
+            assert_eq!(
+                sync_limiter.peek("foo", quantity).unwrap(),
+                async_limiter.peek("foo", quantity).await.unwrap()
+            );
+            assert_eq!(
+                sync_limiter.rate_limit("foo", quantity).unwrap(),
+                async_limiter.rate_limit("foo", quantity).await.unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn it_can_be_sent_between_threads() {
+        fn assert_send<F: Future + Send>(f: F) -> F {
+            f
+        }
+
+        let quota = RateQuota {
+            max_burst: 1,
+            max_rate: Rate::per_second(1),
+        };
+        let mut store = ConcurrentMemoryStore::new();
+        let mut limiter = AsyncRateLimiter::new(&mut store, &quota);
+        drop(assert_send(limiter.rate_limit("foo", 1)));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::error::CellError;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// `Clock` is where a store gets the current time from.
+///
+/// Stores take the time from a clock rather than from wherever the rate
+/// limiter happens to run, so that limiters on many nodes sharing a store all
+/// see the same one. Swapping in a `ManualClock` lets tests and simulations
+/// drive time themselves.
+pub trait Clock {
+    /// Gets the current time.
+    fn now(&self) -> Result<time::OffsetDateTime, CellError>;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Result<time::OffsetDateTime, CellError> {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Result<time::OffsetDateTime, CellError> {
+        (**self).now()
+    }
+}
+
+/// `SystemClock` is the clock of the machine that it's running on.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Result<time::OffsetDateTime, CellError> {
+        Ok(time::OffsetDateTime::now_utc())
+    }
+}
+
+/// `ManualClock` is a clock that only moves when it's told to. Clones share
+/// the same time, so a test can keep one to move the time of a store that it
+/// gave another to.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<time::OffsetDateTime>>,
+}
+
+impl ManualClock {
+    pub fn new(now: time::OffsetDateTime) -> ManualClock {
+        ManualClock {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Moves the clock forward by the given duration, or back if it's
+    /// negative.
+    pub fn advance(&self, duration: time::Duration) {
+        *self.lock() += duration;
+    }
+
+    /// Sets the clock to the given time.
+    pub fn set(&self, now: time::OffsetDateTime) {
+        *self.lock() = now;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, time::OffsetDateTime> {
+        self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Result<time::OffsetDateTime, CellError> {
+        Ok(*self.lock())
+    }
+}
+
+/// `RedisClock` is the clock of a Redis server, as told by its `TIME` command.
+/// Nodes that share a Redis server can use it to share a clock even when they
+/// keep their limiters somewhere else.
+#[cfg(feature = "remote-store")]
+pub struct RedisClock {
+    conn: Mutex<redis::Connection>,
+}
+
+#[cfg(feature = "remote-store")]
+impl RedisClock {
+    pub fn new(conn: redis::Connection) -> RedisClock {
+        RedisClock {
+            conn: Mutex::new(conn),
+        }
+    }
+}
+
+#[cfg(feature = "remote-store")]
+impl Clock for RedisClock {
+    fn now(&self) -> Result<time::OffsetDateTime, CellError> {
+        let mut conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let (seconds, microseconds): (i64, i64) = redis::cmd("TIME").query(&mut *conn)?;
+        from_server_time(seconds, microseconds)
+    }
+}
+
+/// Converts the seconds and microseconds replied by Redis' `TIME` into a time.
+#[cfg(feature = "remote-store")]
+pub(crate) fn from_server_time(
+    seconds: i64,
+    microseconds: i64,
+) -> Result<time::OffsetDateTime, CellError> {
+    let now = time::OffsetDateTime::from_unix_timestamp(seconds)
+        .map_err(|e| error!("Invalid server time: {}", e))?;
+    Ok(now + time::Duration::microseconds(microseconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::clock::*;
+
+    #[test]
+    fn it_moves_manual_clocks() {
+        let start = time::OffsetDateTime::UNIX_EPOCH;
+        let clock = ManualClock::new(start);
+        let clone = clock.clone();
+        assert_eq!(start, clone.now().unwrap());
+
+        // Clones move together.
+        clock.advance(time::Duration::seconds(2));
+        assert_eq!(start + time::Duration::seconds(2), clone.now().unwrap());
+
+        clone.set(start);
+        assert_eq!(start, clock.now().unwrap());
+    }
+}
@@ -0,0 +1,237 @@
+extern crate time;
+
+use crate::cell::store;
+use crate::cell::{from_nanoseconds, nanoseconds, retry_cas};
+use crate::error::CellError;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct AcquireResult {
+    /// ID of the acquired lease, which is needed to release it again. `None`
+    /// if every slot was already taken.
+    pub lease_id: Option<u64>,
+
+    /// Maximum number of leases that can be held at once.
+    pub limit: i64,
+
+    /// Number of leases that could still be acquired after this one.
+    pub remaining: i64,
+
+    /// How long until the soonest held lease expires if every slot was
+    /// taken, or -1 if a lease was acquired. Leases are usually released well
+    /// before they expire, so this is an upper bound on the wait.
+    pub retry_after: time::Duration,
+}
+
+/// `ConcurrencyLimiter` limits how many requests can be in flight for a key at
+/// once, like a semaphore. Each request acquires a lease before it starts and
+/// releases it once it's done.
+///
+/// Leases expire after a TTL given when they're acquired, so that a slot held
+/// by a worker that crashed before releasing it comes back on its own.
+pub struct ConcurrencyLimiter<T> {
+    pub store: T,
+}
+
+impl<T: store::LeaseStore> ConcurrencyLimiter<T> {
+    pub fn new(store: T) -> Self {
+        ConcurrencyLimiter { store }
+    }
+
+    /// Acquires a lease on the given key if fewer than `max` are currently
+    /// held. The lease is released automatically after `lease_ttl`.
+    pub fn acquire(
+        &mut self,
+        key: &str,
+        max: i64,
+        lease_ttl: time::Duration,
+    ) -> Result<(bool, AcquireResult), CellError> {
+        if max < 1 {
+            return Err(error!("Maximum leases must be at least 1: {}", max));
+        }
+        if lease_ttl <= time::Duration::ZERO {
+            return Err(error!("Lease TTL must be positive"));
+        }
+
+        log_debug!(
+            self.store,
+            "acquire key = {} max = {} lease_ttl = {}",
+            key,
+            max,
+            lease_ttl
+        );
+
+        let mut i = 0;
+        loop {
+            log_debug!(self.store, "iteration = {}", i);
+
+            let (old, now) = self.store.get_leases_with_time(key)?;
+            let mut new = held(old.as_ref(), now);
+
+            if new.leases.len() as i64 >= max {
+                let soonest = new
+                    .leases
+                    .iter()
+                    .map(|(_, expires_at)| *expires_at)
+                    .min()
+                    .map(from_nanoseconds)
+                    .unwrap_or(now);
+                return Ok((
+                    true,
+                    AcquireResult {
+                        lease_id: None,
+                        limit: max,
+                        remaining: 0,
+                        retry_after: soonest - now,
+                    },
+                ));
+            }
+
+            // Lease IDs are taken from the clock so that they keep going up
+            // even if the key expires and is created again, but they have to
+            // go up even if the clock hasn't.
+            let lease_id = nanoseconds(now).max(new.last_id + 1);
+            new.last_id = lease_id;
+            new.leases.push((lease_id, nanoseconds(now + lease_ttl)));
+
+            if self
+                .store
+                .compare_and_swap_leases(key, old.as_ref(), &new)?
+            {
+                log_debug!(self.store, "acquired lease_id = {}", lease_id);
+                return Ok((
+                    false,
+                    AcquireResult {
+                        lease_id: Some(lease_id),
+                        limit: max,
+                        remaining: max - new.leases.len() as i64,
+                        retry_after: time::Duration::seconds(-1),
+                    },
+                ));
+            }
+
+            retry_cas(&mut i, "acquire lease")?;
+        }
+    }
+
+    /// Releases a lease acquired with `acquire`, freeing its slot. Returns
+    /// false if the lease wasn't held, either because it was already released
+    /// or because it expired.
+    pub fn release(&mut self, key: &str, lease_id: u64) -> Result<bool, CellError> {
+        log_debug!(self.store, "release key = {} lease_id = {}", key, lease_id);
+
+        let mut i = 0;
+        loop {
+            let (old, now) = self.store.get_leases_with_time(key)?;
+            let mut new = held(old.as_ref(), now);
+
+            let Some(position) = new.leases.iter().position(|(id, _)| *id == lease_id)
+            else {
+                return Ok(false);
+            };
+            new.leases.remove(position);
+
+            if self
+                .store
+                .compare_and_swap_leases(key, old.as_ref(), &new)?
+            {
+                return Ok(true);
+            }
+
+            retry_cas(&mut i, "release lease")?;
+        }
+    }
+}
+
+// Drops any leases that have expired by now.
+fn held(leases: Option<&store::Leases>, now: time::OffsetDateTime) -> store::Leases {
+    let now_ns = nanoseconds(now);
+    let mut leases = leases.cloned().unwrap_or_default();
+    leases.leases.retain(|(_, expires_at)| *expires_at > now_ns);
+    leases
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate time;
+
+    use crate::cell::clock::ManualClock;
+    use crate::cell::concurrency::*;
+    use crate::cell::store;
+
+    #[test]
+    fn it_limits_concurrency() {
+        let clock = ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut memory_store =
+            store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = ConcurrencyLimiter::new(&mut memory_store);
+        let ttl = time::Duration::minutes(1);
+
+        let (limited, first) = limiter.acquire("foo", 2, ttl).unwrap();
+        assert!(!limited);
+        assert_eq!(1, first.remaining);
+        assert_eq!(time::Duration::seconds(-1), first.retry_after);
+
+        let (limited, second) = limiter.acquire("foo", 2, ttl).unwrap();
+        assert!(!limited);
+        assert_eq!(0, second.remaining);
+        assert!(second.lease_id > first.lease_id);
+
+        // Every slot's taken until one of the leases expires ...
+        let (limited, result) = limiter.acquire("foo", 2, ttl).unwrap();
+        assert!(limited);
+        assert_eq!(None, result.lease_id);
+        assert_eq!(ttl, result.retry_after);
+        clock.advance(time::Duration::seconds(15));
+        let (limited, result) = limiter.acquire("foo", 2, ttl).unwrap();
+        assert!(limited);
+        assert_eq!(time::Duration::seconds(45), result.retry_after);
+
+        // ... or is released.
+        assert!(limiter.release("foo", first.lease_id.unwrap()).unwrap());
+        assert!(!limiter.release("foo", first.lease_id.unwrap()).unwrap());
+        let (limited, _) = limiter.acquire("foo", 2, ttl).unwrap();
+        assert!(!limited);
+    }
+
+    #[test]
+    fn it_expires_leases() {
+        let clock = ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut memory_store =
+            store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = ConcurrencyLimiter::new(&mut memory_store);
+
+        let (_, crashed) = limiter
+            .acquire("foo", 1, time::Duration::seconds(10))
+            .unwrap();
+
+        // The slot stays taken for right up until the lease expires.
+        clock.advance(time::Duration::seconds(10) - time::Duration::nanoseconds(1));
+        let (limited, result) = limiter
+            .acquire("foo", 1, time::Duration::minutes(1))
+            .unwrap();
+        assert!(limited);
+        assert_eq!(time::Duration::nanoseconds(1), result.retry_after);
+        clock.advance(time::Duration::nanoseconds(1));
+
+        // The first lease was never released, but it's expired so its slot
+        // can be taken again.
+        let (limited, _) = limiter
+            .acquire("foo", 1, time::Duration::minutes(1))
+            .unwrap();
+        assert!(!limited);
+        assert!(!limiter.release("foo", crashed.lease_id.unwrap()).unwrap());
+    }
+
+    #[test]
+    fn it_rejects_invalid_parameters() {
+        let mut memory_store = store::MemoryStore::new_verbose();
+        let mut limiter = ConcurrencyLimiter::new(&mut memory_store);
+
+        assert!(
+            limiter
+                .acquire("foo", 0, time::Duration::minutes(1))
+                .is_err()
+        );
+        assert!(limiter.acquire("foo", 1, time::Duration::ZERO).is_err());
+    }
+}
@@ -1,12 +1,53 @@
 extern crate time;
 
+#[cfg(feature = "async")]
+pub mod aio;
+pub mod clock;
+pub mod concurrency;
+pub mod multi;
 pub mod store;
+pub mod window;
 
 use crate::error::CellError;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
 
-// Maximum number of times to retry set_if_not_exists/compare_and_swap
-// operations before returning an error.
-const MAX_CAS_ATTEMPTS: i64 = 5;
+/// Default maximum number of times to retry set_if_not_exists/compare_and_swap
+/// operations before returning an error.
+pub const DEFAULT_MAX_CAS_ATTEMPTS: i64 = 5;
+
+static MAX_CAS_ATTEMPTS: AtomicI64 = AtomicI64::new(DEFAULT_MAX_CAS_ATTEMPTS);
+
+/// Number of times that any limiter has retried a set_if_not_exists or
+/// compare_and_swap operation because another got to the key first.
+pub static CAS_RETRIES: AtomicU64 = AtomicU64::new(0);
+
+/// Gets the maximum number of times that limiters retry set_if_not_exists or
+/// compare_and_swap operations before giving up with an error.
+pub fn max_cas_attempts() -> i64 {
+    MAX_CAS_ATTEMPTS.load(Ordering::Relaxed)
+}
+
+// Counts a retry of a set_if_not_exists or compare_and_swap operation that
+// another limiter got to first. Fails once the maximum number of attempts has
+// been exceeded, with an error saying what couldn't be done.
+fn retry_cas(attempts: &mut i64, action: &str) -> Result<(), CellError> {
+    CAS_RETRIES.fetch_add(1, Ordering::Relaxed);
+    *attempts += 1;
+    if *attempts > max_cas_attempts() {
+        return Err(error!(
+            "Failed to {} after {} attempts",
+            action,
+            max_cas_attempts()
+        ));
+    }
+    Ok(())
+}
+
+/// Sets the maximum number of times that limiters retry set_if_not_exists or
+/// compare_and_swap operations. It applies to every limiter.
+pub fn set_max_cas_attempts(attempts: i64) {
+    MAX_CAS_ATTEMPTS.store(attempts, Ordering::Relaxed);
+}
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct Rate {
@@ -56,34 +97,135 @@ pub struct RateLimitResult {
     pub retry_after: time::Duration,
 }
 
+/// Evaluation is the outcome of running the GCRA for a single key at a single
+/// point in time. It's computed without side effects so that callers can
+/// decide whether (and how) to commit it to a store.
+struct Evaluation {
+    limited: bool,
+
+    /// The TAT that should be persisted if the request is allowed.
+    new_tat: time::OffsetDateTime,
+
+    result: RateLimitResult,
+
+    /// How long until the key's state would return to empty. Used as the TTL
+    /// for the key when persisting new_tat.
+    ttl: time::Duration,
+}
+
+impl Evaluation {
+    // Gets the TAT in nanoseconds that has to be written for the evaluation of
+    // a key that had the given TAT (if any), if anything has to be written at
+    // all. A limited request writes nothing, and neither does one against a
+    // missing key that would be set to a TAT that's not in the future, since
+    // the key's already at full capacity.
+    fn tat_to_commit(
+        &self,
+        tat_val: Option<u64>,
+        now: time::OffsetDateTime,
+    ) -> Option<u64> {
+        if self.limited || (tat_val.is_none() && self.new_tat <= now) {
+            return None;
+        }
+        Some(nanoseconds(self.new_tat))
+    }
+
+    // Writes the evaluation of a key that had the given TAT (if any) to a
+    // store, unless nothing has to be written. Returns false only if another
+    // limiter changed the key first.
+    fn commit_to(
+        &self,
+        store: &mut impl store::Store,
+        key: &str,
+        tat_val: Option<u64>,
+        now: time::OffsetDateTime,
+    ) -> Result<bool, CellError> {
+        let Some(new_tat_ns) = self.tat_to_commit(tat_val, now) else {
+            return Ok(true);
+        };
+
+        // If the key was originally missing, set it if if doesn't exist. If it
+        // was there, try to compare and swap.
+        //
+        // Both of these cases are designed to work around the fact that
+        // another limiter could be running in parallel.
+        if let Some(some_tat_val) = tat_val {
+            store.compare_and_swap_with_ttl(key, some_tat_val, new_tat_ns, self.ttl)
+        } else {
+            store.set_if_not_exists_with_ttl(key, new_tat_ns, self.ttl)
+        }
+    }
+}
+
+/// `Algorithm` is a way of deciding whether requests against a key are within
+/// a rate limit. `RateLimiter` implements GCRA, and the `window` module
+/// implements sliding windows for limits that need to be counted exactly over
+/// a rolling period.
+pub trait Algorithm {
+    /// Checks whether a request of the given quantity against a particular
+    /// key would exceed the rate limit, consuming capacity from the limit if
+    /// it wouldn't.
+    fn rate_limit(
+        &mut self,
+        key: &str,
+        quantity: i64,
+    ) -> Result<(bool, RateLimitResult), CellError>;
+}
+
+/// `RateLimiter` limits requests with GCRA. It takes the current time from
+/// its store's `get_with_time`, which stores take from a `clock::Clock`, so
+/// that limiters sharing a store share a clock too. A limiter can be given a
+/// clock of its own with `with_clock` instead.
 pub struct RateLimiter<T> {
     pub store: T,
 
-    /// Think of the DVT as our flexibility: how far can you deviate from the
-    /// nominal equally spaced schedule? If you like leaky buckets, think about
-    /// it as the size of your bucket.
-    delay_variation_tolerance: time::Duration,
-
-    /// Think of the emission interval as the time between events in the
-    /// nominal equally spaced schedule. If you like leaky buckets, think of it
-    /// as how frequently the bucket leaks one unit.
-    emission_interval: time::Duration,
+    // Clock to use instead of the store's.
+    clock: Option<Box<dyn clock::Clock + Send + Sync>>,
 
-    limit: i64,
+    gcra: Gcra,
 }
 
 impl<T: store::Store> RateLimiter<T> {
     pub fn new(store: T, quota: &RateQuota) -> Self {
         RateLimiter {
-            delay_variation_tolerance: time::Duration::nanoseconds(
-                quota.max_rate.period.whole_nanoseconds() as i64 * (quota.max_burst + 1),
-            ),
-            emission_interval: quota.max_rate.period,
-            limit: quota.max_burst + 1,
+            clock: None,
+            gcra: Gcra::new(quota),
             store,
         }
     }
 
+    /// Has the limiter take the time from the given clock instead of its
+    /// store. Keys still expire by the store's clock, so the two should agree
+    /// closely enough for TTLs to hold.
+    pub fn with_clock(self, clock: impl clock::Clock + Send + Sync + 'static) -> Self {
+        RateLimiter {
+            clock: Some(Box::new(clock)),
+            ..self
+        }
+    }
+
+    /// Peek evaluates whether a request of the given quantity against a
+    /// particular key would be rate limited, but without persisting anything
+    /// to the underlying store. The returned RateLimitResult is identical to
+    /// the one that `rate_limit` would have produced for the same request.
+    ///
+    /// Unlike a call to `rate_limit` with a quantity of 0, this never writes
+    /// to the store, so it's suitable for read-only contexts.
+    pub fn peek(
+        &self,
+        key: &str,
+        quantity: i64,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let increment = self.increment(quantity)?;
+        self.log_start(key, quantity, increment);
+
+        let (tat_val, now) = self.get_with_time(key)?;
+        let evaluation = self.evaluate(tat_val, now, increment);
+
+        self.log_end(&evaluation.result);
+        Ok((evaluation.limited, evaluation.result))
+    }
+
     /// RateLimit checks whether a particular key has exceeded a rate limit. It
     /// also returns a RateLimitResult to provide additional information about
     /// the state of the RateLimiter.
@@ -99,185 +241,439 @@ impl<T: store::Store> RateLimiter<T> {
         key: &str,
         quantity: i64,
     ) -> Result<(bool, RateLimitResult), CellError> {
-        let mut rlc = RateLimitResult {
-            limit: self.limit,
-            remaining: 0,
-            retry_after: time::Duration::seconds(-1),
-            reset_after: time::Duration::seconds(-1),
-        };
+        let increment = self.increment(quantity)?;
+        self.log_start(key, quantity, increment);
 
-        if self.emission_interval == time::Duration::nanoseconds(0) {
-            return Err(error!("Zero rates are not supported"));
+        let evaluation = self.commit_with(key, |limiter, tat_val, now| {
+            limiter.evaluate(tat_val, now, increment)
+        })?;
+
+        self.log_end(&evaluation.result);
+        Ok((evaluation.limited, evaluation.result))
+    }
+
+    /// RateLimitPartial is like `rate_limit`, but rather than rejecting a
+    /// request that's larger than the capacity that's left, it grants as much
+    /// of it as it can: the smaller of the given quantity and the number of
+    /// requests remaining. Only the granted amount is taken from the limit.
+    ///
+    /// Returns the number of units granted along with the usual result. The
+    /// request is only limited if nothing at all could be granted, in which
+    /// case `retry_after` is how long until a single unit would be.
+    pub fn rate_limit_partial(
+        &mut self,
+        key: &str,
+        quantity: i64,
+    ) -> Result<(bool, i64, RateLimitResult), CellError> {
+        if quantity < 0 {
+            return Err(error!("Quantity can't be negative"));
         }
+        let increment = self.increment(quantity)?;
+        self.log_start(key, quantity, increment);
 
-        let increment = time::Duration::nanoseconds(
-            self.emission_interval.whole_nanoseconds() as i64 * quantity,
-        );
+        let granted = std::cell::Cell::new(0);
+        let evaluation = self.commit_with(key, |limiter, tat_val, now| {
+            let available = limiter
+                .evaluate(tat_val, now, time::Duration::ZERO)
+                .result
+                .remaining;
+            granted.set(quantity.min(available.max(0)));
+            log_debug!(limiter.store, "granted = {}", granted.get());
+
+            // With nothing left to give, evaluate a single unit so that the
+            // caller is told how long until there will be.
+            let wanted = if granted.get() == 0 && quantity > 0 {
+                1
+            } else {
+                granted.get()
+            };
+            limiter.evaluate(
+                tat_val,
+                now,
+                time::Duration::nanoseconds(
+                    limiter.gcra.emission_interval.whole_nanoseconds() as i64 * wanted,
+                ),
+            )
+        })?;
+
+        self.log_end(&evaluation.result);
+        Ok((evaluation.limited, granted.get(), evaluation.result))
+    }
+
+    /// Reserve is like `rate_limit`, but instead of rejecting a request that
+    /// exceeds the rate limit, it schedules it for later. The key's TAT is
+    /// always advanced (even past the burst tolerance) so that subsequent
+    /// requests queue up behind this one, and the result's `retry_after` is
+    /// how long the caller must wait before proceeding, which is zero if it
+    /// can go right away.
+    ///
+    /// If the wait would exceed the given maximum, nothing is reserved and
+    /// the request is reported as limited. `retry_after` is then the wait
+    /// that would have been needed.
+    pub fn reserve(
+        &mut self,
+        key: &str,
+        quantity: i64,
+        max_wait: Option<time::Duration>,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let increment = self.increment(quantity)?;
         self.log_start(key, quantity, increment);
 
-        // Rust actually detects that this variable can only ever be assigned
-        // once despite our loops and conditions so it doesn't have to be
-        // mutable. Amazing.
-        let limited: bool;
+        let evaluation = self.commit_with(key, |limiter, tat_val, now| {
+            let mut evaluation = limiter.evaluate(tat_val, now, increment);
+
+            // The request may go ahead once the new TAT is back within the
+            // tolerance.
+            let wait =
+                (evaluation.new_tat - limiter.gcra.delay_variation_tolerance - now)
+                    .max(time::Duration::ZERO);
+            log_debug!(limiter.store, "wait = {}ms", wait.whole_milliseconds());
+
+            evaluation.limited = max_wait.is_some_and(|max_wait| wait > max_wait);
+            if !evaluation.limited {
+                // The new TAT is committed even if it's past the tolerance,
+                // so the key lives until then.
+                evaluation.ttl = evaluation.new_tat - now;
+                evaluation.result.remaining = limiter.remaining(evaluation.ttl);
+                evaluation.result.reset_after = evaluation.ttl;
+            }
+            evaluation.result.retry_after = wait;
 
-        let mut ttl: time::Duration;
+            evaluation
+        })?;
 
-        // Looping here is not about retrying communication failures, it's
-        // about retrying contention. While we're performing our calculations
-        // it's possible for another limiter to be doing its own simultaneously
-        // and beat us to the punch. In that case only one limiter should win.
-        //
-        // Note that when running with our internal Redis store (i.e. the
-        // normal case for the redis-cell project) this is actually *not* true
-        // because our entire operation will execute atomically.
-        let mut i = 0;
-        loop {
-            log_debug!(self.store, "iteration = {}", i);
+        self.log_end(&evaluation.result);
+        Ok((evaluation.limited, evaluation.result))
+    }
 
-            // tat refers to the theoretical arrival time that would be expected
-            // from equally spaced requests at exactly the rate limit.
-            let (tat_val, now) = self.store.get_with_time(key)?;
+    /// Refund gives back capacity that was taken by an earlier request but
+    /// went unused, like when a request charged for more than it ended up
+    /// needing. The key's TAT is moved back by the given quantity's worth of
+    /// emission intervals, but never to before the current time: capacity
+    /// that's already been regained can't be given back twice.
+    ///
+    /// The returned RateLimitResult describes the key's state after the
+    /// refund. A refund is never limited, and a refund to a key that doesn't
+    /// exist doesn't write anything: it's already at full capacity.
+    pub fn refund(
+        &mut self,
+        key: &str,
+        quantity: i64,
+    ) -> Result<RateLimitResult, CellError> {
+        if quantity < 0 {
+            return Err(error!("Quantity to refund can't be negative"));
+        }
+        let increment = self.increment(quantity)?;
+        self.log_start(key, quantity, increment);
 
+        let evaluation = self.commit_with(key, |limiter, tat_val, now| {
             let tat = match tat_val {
                 None => now,
                 Some(v) => from_nanoseconds(v),
             };
+            let new_tat = (tat - increment).max(now);
+            let ttl = new_tat - now;
             log_debug!(
-                self.store,
-                "tat = {} (from store = {})",
-                tat.format(&time::format_description::well_known::Rfc3339)
-                    .unwrap(),
-                tat_val.unwrap_or(0)
+                limiter.store,
+                "refunded ttl = {}ms",
+                ttl.whole_milliseconds()
             );
 
-            let new_tat = if now > tat {
-                now + increment
-            } else {
-                tat + increment
-            };
-            log_debug!(
-                self.store,
-                "new_tat = {}",
-                new_tat
-                    .format(&time::format_description::well_known::Rfc3339)
-                    .unwrap()
-            );
+            Evaluation {
+                limited: false,
+                new_tat,
+                result: RateLimitResult {
+                    limit: limiter.gcra.limit,
+                    remaining: limiter.remaining(ttl),
+                    reset_after: ttl,
+                    retry_after: time::Duration::seconds(-1),
+                },
+                ttl,
+            }
+        })?;
 
-            // Block the request if the next permitted time is in the future.
-            let allow_at = new_tat - self.delay_variation_tolerance;
-            let diff = now - allow_at;
-            log_debug!(
-                self.store,
-                "diff = {}ms (now - allow_at)",
-                diff.whole_milliseconds()
-            );
+        self.log_end(&evaluation.result);
+        Ok(evaluation.result)
+    }
 
-            if diff < time::Duration::ZERO {
-                log_debug!(
-                    self.store,
-                    "BLOCKED retry_after = {}ms",
-                    -diff.whole_milliseconds()
-                );
+    // Evaluates a request against a key with the given function and persists
+    // the resulting TAT unless it was limited.
+    //
+    // Looping here is not about retrying communication failures, it's about
+    // retrying contention. While we're performing our calculations it's
+    // possible for another limiter to be doing its own simultaneously and
+    // beat us to the punch. In that case only one limiter should win.
+    //
+    // Note that when running with our internal Redis store (i.e. the normal
+    // case for the redis-cell project) this is actually *not* true because
+    // our entire operation will execute atomically.
+    fn commit_with(
+        &mut self,
+        key: &str,
+        evaluate: impl Fn(&Self, Option<u64>, time::OffsetDateTime) -> Evaluation,
+    ) -> Result<Evaluation, CellError> {
+        let mut i = 0;
+        loop {
+            log_debug!(self.store, "iteration = {}", i);
 
-                if increment <= self.delay_variation_tolerance {
-                    rlc.retry_after = -diff;
-                }
+            let (tat_val, now) = self.get_with_time(key)?;
+            let evaluation = evaluate(self, tat_val, now);
 
-                limited = true;
-                ttl = tat - now;
-                break;
+            if evaluation.commit_to(&mut self.store, key, tat_val, now)? {
+                return Ok(evaluation);
             }
 
-            let new_tat_ns = nanoseconds(new_tat);
-            ttl = new_tat - now;
-            log_debug!(self.store, "ALLOWED");
+            retry_cas(&mut i, "update rate limit")?;
+        }
+    }
 
-            // If the key was originally missing, set it if if doesn't exist.
-            // If it was there, try to compare and swap.
-            //
-            // Both of these cases are designed to work around the fact that
-            // another limiter could be running in parallel.
-            let updated = if let Some(some_tat_val) = tat_val {
-                self.store.compare_and_swap_with_ttl(
-                    key,
-                    some_tat_val,
-                    new_tat_ns,
-                    ttl,
-                )?
-            } else {
-                self.store
-                    .set_if_not_exists_with_ttl(key, new_tat_ns, ttl)?
-            };
+    /// Reset clears any state stored for a particular key, restoring the
+    /// limiter to its full capacity for it. Returns true if there was state to
+    /// clear.
+    pub fn reset(&mut self, key: &str) -> Result<bool, CellError> {
+        log_debug!(self.store, "reset key = {}", key);
+        self.store.delete(key)
+    }
 
-            if updated {
-                limited = false;
-                break;
-            }
+    fn evaluate(
+        &self,
+        tat_val: Option<u64>,
+        now: time::OffsetDateTime,
+        increment: time::Duration,
+    ) -> Evaluation {
+        self.gcra
+            .evaluate(&|m| self.store.log_debug(m), tat_val, now, increment)
+    }
+
+    // Gets the key's TAT along with the time, which comes from the limiter's
+    // own clock if it has one.
+    fn get_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<u64>, time::OffsetDateTime), CellError> {
+        let (tat_val, now) = self.store.get_with_time(key)?;
+        match &self.clock {
+            Some(clock) => Ok((tat_val, clock.now()?)),
+            None => Ok((tat_val, now)),
+        }
+    }
+
+    fn remaining(&self, ttl: time::Duration) -> i64 {
+        self.gcra.remaining(ttl)
+    }
+
+    fn increment(&self, quantity: i64) -> Result<time::Duration, CellError> {
+        self.gcra.increment(quantity)
+    }
+
+    fn log_end(&self, rlc: &RateLimitResult) {
+        self.gcra.log_end(&|m| self.store.log_debug(m), rlc)
+    }
+
+    fn log_start(&self, key: &str, quantity: i64, increment: time::Duration) {
+        self.gcra
+            .log_start(&|m| self.store.log_debug(m), key, quantity, increment)
+    }
+}
+
+impl<T: store::Store> Algorithm for RateLimiter<T> {
+    fn rate_limit(
+        &mut self,
+        key: &str,
+        quantity: i64,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        RateLimiter::rate_limit(self, key, quantity)
+    }
+}
+
+/// `Gcra` is the math of the generic cell rate algorithm for a single quota,
+/// which the synchronous and asynchronous limiters share. It logs through
+/// whichever store the limiter running it has.
+struct Gcra {
+    /// Think of the DVT as our flexibility: how far can you deviate from the
+    /// nominal equally spaced schedule? If you like leaky buckets, think about
+    /// it as the size of your bucket.
+    delay_variation_tolerance: time::Duration,
+
+    /// Think of the emission interval as the time between events in the
+    /// nominal equally spaced schedule. If you like leaky buckets, think of it
+    /// as how frequently the bucket leaks one unit.
+    emission_interval: time::Duration,
+
+    limit: i64,
+}
+
+impl Gcra {
+    fn new(quota: &RateQuota) -> Gcra {
+        Gcra {
+            delay_variation_tolerance: time::Duration::nanoseconds(
+                quota.max_rate.period.whole_nanoseconds() as i64 * (quota.max_burst + 1),
+            ),
+            emission_interval: quota.max_rate.period,
+            limit: quota.max_burst + 1,
+        }
+    }
+
+    /// Runs the GCRA against a key's stored TAT (if there was one) and the
+    /// store's current time, producing a decision along with the state that
+    /// would need to be persisted if the request is allowed. Nothing is
+    /// written to the store.
+    fn evaluate(
+        &self,
+        log: &dyn Fn(&str),
+        tat_val: Option<u64>,
+        now: time::OffsetDateTime,
+        increment: time::Duration,
+    ) -> Evaluation {
+        let mut rlc = RateLimitResult {
+            limit: self.limit,
+            remaining: 0,
+            retry_after: time::Duration::seconds(-1),
+            reset_after: time::Duration::seconds(-1),
+        };
+
+        // tat refers to the theoretical arrival time that would be expected
+        // from equally spaced requests at exactly the rate limit.
+        let tat = match tat_val {
+            None => now,
+            Some(v) => from_nanoseconds(v),
+        };
+        log_debug!(
+            log,
+            "tat = {} (from store = {})",
+            tat.format(&time::format_description::well_known::Rfc3339)
+                .unwrap(),
+            tat_val.unwrap_or(0)
+        );
+
+        let new_tat = if now > tat {
+            now + increment
+        } else {
+            tat + increment
+        };
+        log_debug!(
+            log,
+            "new_tat = {}",
+            new_tat
+                .format(&time::format_description::well_known::Rfc3339)
+                .unwrap()
+        );
+
+        // Block the request if the next permitted time is in the future.
+        let allow_at = new_tat - self.delay_variation_tolerance;
+        let diff = now - allow_at;
+        log_debug!(
+            log,
+            "diff = {}ms (now - allow_at)",
+            diff.whole_milliseconds()
+        );
+
+        let limited = diff < time::Duration::ZERO;
+        let ttl = if limited {
+            log_debug!(
+                log,
+                "BLOCKED retry_after = {}ms",
+                -diff.whole_milliseconds()
+            );
 
-            i += 1;
-            if i > MAX_CAS_ATTEMPTS {
-                return Err(error!(
-                    "Failed to update rate limit after \
-                     {} attempts",
-                    MAX_CAS_ATTEMPTS
-                ));
+            if increment <= self.delay_variation_tolerance {
+                rlc.retry_after = -diff;
             }
+
+            tat - now
+        } else {
+            log_debug!(log, "ALLOWED");
+            new_tat - now
+        };
+
+        rlc.remaining = self.remaining(ttl);
+        rlc.reset_after = ttl;
+
+        Evaluation {
+            limited,
+            new_tat,
+            result: rlc,
+            ttl,
         }
+    }
 
+    /// Calculates how many more requests a key could take right now, given
+    /// how long until its state would return to empty.
+    fn remaining(&self, ttl: time::Duration) -> i64 {
         let next = self.delay_variation_tolerance - ttl;
         if next > -self.emission_interval {
-            rlc.remaining = (next.whole_microseconds() as f64
-                / self.emission_interval.whole_microseconds() as f64)
-                as i64;
+            (next.whole_nanoseconds() as f64
+                / self.emission_interval.whole_nanoseconds() as f64) as i64
+        } else {
+            0
+        }
+    }
+
+    /// Calculates how far a request of the given quantity pushes a key's TAT.
+    fn increment(&self, quantity: i64) -> Result<time::Duration, CellError> {
+        if self.emission_interval == time::Duration::nanoseconds(0) {
+            return Err(error!("Zero rates are not supported"));
         }
-        rlc.reset_after = ttl;
 
-        self.log_end(&rlc);
-        Ok((limited, rlc))
+        Ok(time::Duration::nanoseconds(
+            self.emission_interval.whole_nanoseconds() as i64 * quantity,
+        ))
     }
 
-    fn log_end(&self, rlc: &RateLimitResult) {
+    fn log_end(&self, log: &dyn Fn(&str), rlc: &RateLimitResult) {
+        log_debug!(log, "limit = {} remaining = {}", self.limit, rlc.remaining);
         log_debug!(
-            self.store,
-            "limit = {} remaining = {}",
-            self.limit,
-            rlc.remaining
-        );
-        log_debug!(
-            self.store,
+            log,
             "retry_after = {}ms",
             rlc.retry_after.whole_microseconds()
         );
         log_debug!(
-            self.store,
+            log,
             "reset_after = {}ms (ttl)",
             rlc.reset_after.whole_microseconds()
         );
     }
 
-    fn log_start(&self, key: &str, quantity: i64, increment: time::Duration) {
-        log_debug!(self.store, "");
-        log_debug!(self.store, "-----");
-        log_debug!(self.store, "key = {}", key);
-        log_debug!(self.store, "quantity = {}", quantity);
+    fn log_start(
+        &self,
+        log: &dyn Fn(&str),
+        key: &str,
+        quantity: i64,
+        increment: time::Duration,
+    ) {
+        log_debug!(log, "");
+        log_debug!(log, "-----");
+        log_debug!(log, "key = {}", key);
+        log_debug!(log, "quantity = {}", quantity);
         log_debug!(
-            self.store,
+            log,
             "delay_variation_tolerance = {}ms",
             self.delay_variation_tolerance.whole_microseconds()
         );
         log_debug!(
-            self.store,
+            log,
             "emission_interval = {}ms",
             self.emission_interval.whole_microseconds()
         );
         log_debug!(
-            self.store,
+            log,
             "tat_increment = {}ms (emission_interval * quantity)",
             increment.whole_microseconds()
         );
     }
 }
 
+// Lets the GCRA's math log debug messages through a function.
+trait DebugLog {
+    fn log_debug(&self, message: &str);
+}
+
+impl DebugLog for dyn Fn(&str) + '_ {
+    fn log_debug(&self, message: &str) {
+        self(message)
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct RateQuota {
     pub max_burst: i64,
@@ -296,9 +692,11 @@ fn nanoseconds(x: time::OffsetDateTime) -> u64 {
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_return)]
 mod tests {
     extern crate time;
 
+    use crate::cell::store::Store;
     use crate::cell::*;
     use crate::error::CellError;
 
@@ -388,9 +786,9 @@ mod tests {
             max_rate: Rate::per_second(1),
         };
         let start = time::OffsetDateTime::now_utc();
-        let mut memory_store = store::MemoryStore::new_verbose();
-        let mut test_store = TestStore::new(&mut memory_store);
-        let mut limiter = RateLimiter::new(&mut test_store, &quota);
+        let clock = clock::ManualClock::new(start);
+        let mut memory_store = store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = RateLimiter::new(&mut memory_store, &quota);
 
         let cases = [
             //
@@ -443,7 +841,7 @@ mod tests {
             println!("starting test case = {:?}", case.num);
             println!("{:?}", case);
 
-            limiter.store.clock = case.now;
+            clock.set(case.now);
             let (limited, results) = limiter.rate_limit("foo", case.volume).unwrap();
 
             println!("limited = {:?}", limited);
@@ -458,6 +856,237 @@ mod tests {
         }
     }
 
+    #[test]
+    fn it_rate_limits_by_its_own_clock() {
+        let quota = RateQuota {
+            max_burst: 1,
+            max_rate: Rate::per_second(1),
+        };
+        let clock = clock::ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut memory_store = store::MemoryStore::new_verbose();
+        let mut limiter =
+            RateLimiter::new(&mut memory_store, &quota).with_clock(clock.clone());
+
+        let (limited, results) = limiter.rate_limit("foo", 2).unwrap();
+        assert!(!limited);
+        assert_eq!(0, results.remaining);
+
+        let (limited, results) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(limited);
+        assert_eq!(time::Duration::seconds(1), results.retry_after);
+
+        // Only the limiter's clock has moved, not the store's.
+        clock.advance(time::Duration::seconds(1));
+        let (limited, results) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(0, results.remaining);
+    }
+
+    #[test]
+    fn it_peeks_without_updating_the_store() {
+        let quota = RateQuota {
+            max_burst: 4,
+            max_rate: Rate::per_second(1),
+        };
+        let clock = clock::ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut memory_store =
+            store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = RateLimiter::new(&mut memory_store, &quota);
+
+        // Peeking reports the result of a hypothetical request, but doesn't
+        // consume anything, so it's stable across invocations.
+        for _ in 0..3 {
+            let (limited, results) = limiter.peek("foo", 1).unwrap();
+            assert!(!limited);
+            assert_eq!(4, results.remaining);
+            assert_eq!(time::Duration::seconds(1), results.reset_after);
+        }
+        assert_eq!(None, limiter.store.get_with_time("foo").unwrap().0);
+
+        let (limited, results) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(4, results.remaining);
+
+        let (limited, results) = limiter.peek("foo", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(3, results.remaining);
+        assert_eq!(time::Duration::seconds(2), results.reset_after);
+
+        // A peek that would be limited carries a retry_after like a real
+        // request would.
+        let (limited, results) = limiter.peek("foo", 5).unwrap();
+        assert!(limited);
+        assert_eq!(time::Duration::seconds(1), results.retry_after);
+    }
+
+    #[test]
+    fn it_reserves() {
+        let quota = RateQuota {
+            max_burst: 1,
+            max_rate: Rate::per_second(1),
+        };
+        let clock = clock::ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut memory_store =
+            store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = RateLimiter::new(&mut memory_store, &quota);
+        let max_wait = Some(time::Duration::seconds(2));
+
+        // The burst can go right away.
+        for remaining in [1, 0] {
+            let (limited, results) = limiter.reserve("foo", 1, max_wait).unwrap();
+            assert!(!limited);
+            assert_eq!(time::Duration::ZERO, results.retry_after);
+            assert_eq!(remaining, results.remaining);
+        }
+
+        // After that, each request is scheduled one emission interval after
+        // the one before it rather than being rejected.
+        for wait in [1, 2] {
+            let (limited, results) = limiter.reserve("foo", 1, max_wait).unwrap();
+            assert!(!limited);
+            assert_eq!(time::Duration::seconds(wait), results.retry_after);
+            assert_eq!(0, results.remaining);
+            assert_eq!(time::Duration::seconds(wait + 2), results.reset_after);
+        }
+
+        // Until the wait would be longer than the maximum, at which point
+        // nothing is reserved.
+        let (limited, results) = limiter.reserve("foo", 1, max_wait).unwrap();
+        assert!(limited);
+        assert_eq!(time::Duration::seconds(3), results.retry_after);
+        let (limited, results) = limiter.reserve("foo", 1, None).unwrap();
+        assert!(!limited);
+        assert_eq!(time::Duration::seconds(3), results.retry_after);
+    }
+
+    #[test]
+    fn it_rate_limits_partially() {
+        let quota = RateQuota {
+            max_burst: 4,
+            max_rate: Rate::per_second(1),
+        };
+        let clock = clock::ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut memory_store =
+            store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = RateLimiter::new(&mut memory_store, &quota);
+
+        // Only part of a request larger than what's left is granted ...
+        let (_, results) = limiter.rate_limit("foo", 3).unwrap();
+        assert_eq!(2, results.remaining);
+        let (limited, granted, results) = limiter.rate_limit_partial("foo", 10).unwrap();
+        assert!(!limited);
+        assert_eq!(2, granted);
+        assert_eq!(0, results.remaining);
+        assert_eq!(time::Duration::seconds(5), results.reset_after);
+
+        // ... a smaller one is granted in full ...
+        clock.advance(time::Duration::seconds(2));
+        let (limited, granted, results) = limiter.rate_limit_partial("foo", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(1, granted);
+        assert_eq!(1, results.remaining);
+
+        // ... and nothing's granted once there's no capacity at all.
+        let (_, granted, _) = limiter.rate_limit_partial("foo", 5).unwrap();
+        assert_eq!(1, granted);
+        let (limited, granted, results) = limiter.rate_limit_partial("foo", 5).unwrap();
+        assert!(limited);
+        assert_eq!(0, granted);
+        assert_eq!(0, results.remaining);
+        assert_eq!(time::Duration::seconds(1), results.retry_after);
+
+        assert!(limiter.rate_limit_partial("foo", -1).is_err());
+    }
+
+    #[test]
+    fn it_refunds() {
+        let quota = RateQuota {
+            max_burst: 4,
+            max_rate: Rate::per_second(1),
+        };
+        let clock = clock::ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut memory_store =
+            store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = RateLimiter::new(&mut memory_store, &quota);
+
+        // Charge for 4 up front, then give back the 3 that went unused.
+        let (_, results) = limiter.rate_limit("foo", 4).unwrap();
+        assert_eq!(1, results.remaining);
+        let results = limiter.refund("foo", 3).unwrap();
+        assert_eq!(4, results.remaining);
+        assert_eq!(time::Duration::seconds(1), results.reset_after);
+
+        // Refunds never go past full capacity.
+        let results = limiter.refund("foo", 10).unwrap();
+        assert_eq!(5, results.remaining);
+        assert_eq!(time::Duration::ZERO, results.reset_after);
+        let (limited, results) = limiter.rate_limit("foo", 5).unwrap();
+        assert!(!limited);
+        assert_eq!(0, results.remaining);
+
+        assert!(limiter.refund("foo", -1).is_err());
+
+        // A refund to a key that doesn't exist leaves it that way.
+        let results = limiter.refund("bar", 3).unwrap();
+        assert_eq!(5, results.remaining);
+        assert_eq!(time::Duration::ZERO, results.reset_after);
+        assert_eq!(None, limiter.store.get_with_time("bar").unwrap().0);
+    }
+
+    #[test]
+    fn it_resets() {
+        let quota = RateQuota {
+            max_burst: 1,
+            max_rate: Rate::per_minute(1),
+        };
+        let mut memory_store = store::MemoryStore::new_verbose();
+        let mut limiter = RateLimiter::new(&mut memory_store, &quota);
+
+        // Nothing to reset yet.
+        assert!(!limiter.reset("foo").unwrap());
+
+        // Use up the limit entirely.
+        assert!(!limiter.rate_limit("foo", 2).unwrap().0);
+        assert!(limiter.rate_limit("foo", 1).unwrap().0);
+
+        // After a reset the full limit is available again.
+        assert!(limiter.reset("foo").unwrap());
+        let (limited, results) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(1, results.remaining);
+    }
+
+    #[test]
+    fn it_rate_limits_with_sub_microsecond_intervals() {
+        // 2000 per millisecond is an emission interval of 500 ns.
+        let quota = RateQuota {
+            max_burst: 10,
+            max_rate: Rate::per_period(2000, time::Duration::milliseconds(1)),
+        };
+        let clock = clock::ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut memory_store =
+            store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = RateLimiter::new(&mut memory_store, &quota);
+
+        let (limited, results) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(10, results.remaining);
+        assert_eq!(time::Duration::nanoseconds(500), results.reset_after);
+
+        let (limited, results) = limiter.rate_limit("foo", 10).unwrap();
+        assert!(!limited);
+        assert_eq!(0, results.remaining);
+
+        let (limited, results) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(limited);
+        assert_eq!(time::Duration::nanoseconds(500), results.retry_after);
+
+        clock.advance(time::Duration::nanoseconds(500));
+        let (limited, results) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(0, results.remaining);
+    }
+
     #[test]
     fn it_does_not_support_zero_rates() {
         let quota = RateQuota {
@@ -488,7 +1117,12 @@ mod tests {
 
         let mut limiter = RateLimiter::new(&mut test_store, &quota);
 
-        let err = error!("Failed to update rate limit after 5 attempts");
+        // The number of attempts is configurable, so it's whatever it's set to
+        // rather than the default.
+        let err = error!(
+            "Failed to update rate limit after {} attempts",
+            max_cas_attempts()
+        );
 
         assert_eq!(
             err.to_string(),
@@ -530,18 +1164,16 @@ mod tests {
     }
 
     /// TestStore is a Store implementation that wraps a MemoryStore and allows
-    /// us to tweak certain behavior, like for example setting the effective
-    /// system clock.
-    struct TestStore<'a> {
-        clock: time::OffsetDateTime,
-        fail_updates: bool,
+    /// us to tweak certain behavior, like for example making every update
+    /// fail. Other limiters' tests use it too.
+    pub(super) struct TestStore<'a> {
+        pub(super) fail_updates: bool,
         store: &'a mut store::MemoryStore,
     }
 
     impl<'a> TestStore<'a> {
-        fn new(store: &'a mut store::MemoryStore) -> TestStore<'a> {
+        pub(super) fn new(store: &'a mut store::MemoryStore) -> TestStore<'a> {
             TestStore {
-                clock: time::OffsetDateTime::now_utc(),
                 fail_updates: false,
                 store,
             }
@@ -563,12 +1195,15 @@ mod tests {
             }
         }
 
+        fn delete(&mut self, key: &str) -> Result<bool, CellError> {
+            self.store.delete(key)
+        }
+
         fn get_with_time(
             &self,
             key: &str,
         ) -> Result<(Option<u64>, time::OffsetDateTime), CellError> {
-            let tup = self.store.get_with_time(key)?;
-            Ok((tup.0, self.clock))
+            self.store.get_with_time(key)
         }
 
         fn log_debug(&self, message: &str) {
@@ -0,0 +1,331 @@
+extern crate time;
+
+use crate::cell::store;
+use crate::cell::{
+    Evaluation, RateLimitResult, RateLimiter, RateQuota, from_nanoseconds, nanoseconds,
+    retry_cas,
+};
+use crate::error::CellError;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct MultiRateLimitResult {
+    /// Index of the limit that determined the outcome. If the request was
+    /// limited, it's the limit that will take the longest to allow it. If it
+    /// was allowed, it's the limit with the least remaining capacity.
+    pub binding: usize,
+
+    /// How long until the request would be allowed by every limit, or -1 if
+    /// the request was allowed (or can never be allowed because it's larger
+    /// than one of the limits' maximum burst).
+    pub retry_after: time::Duration,
+
+    /// Results for each individual limit, in the same order that the limits
+    /// were given in.
+    pub results: Vec<RateLimitResult>,
+}
+
+/// `MultiRateLimiter` applies a request against several rate limits at once,
+/// only consuming capacity from any of them if all of them allow it.
+///
+/// This is useful for enforcing a combination of limits like 10 requests per
+/// second *and* 1000 requests per day. Running two separate limiters would
+/// leak capacity because the first could consume before the second denies.
+pub struct MultiRateLimiter<T> {
+    pub store: T,
+}
+
+impl<T: store::Store> MultiRateLimiter<T> {
+    pub fn new(store: T) -> Self {
+        MultiRateLimiter { store }
+    }
+
+    /// Checks whether a request of the given quantity would exceed any of the
+    /// given (key, quota) limits. The new TAT of every limit is persisted if
+    /// and only if all of them allow the request.
+    pub fn rate_limit(
+        &mut self,
+        limits: &[(&str, &RateQuota)],
+        quantity: i64,
+    ) -> Result<(bool, MultiRateLimitResult), CellError> {
+        if limits.is_empty() {
+            return Err(error!("At least one limit is required"));
+        }
+
+        // The same key appearing twice would have its second evaluation based
+        // on state that the first is about to replace, which we'd never be
+        // able to commit.
+        for (i, (key, _)) in limits.iter().enumerate() {
+            if limits[..i].iter().any(|(other, _)| other == key) {
+                return Err(error!("Duplicate key: {}", key));
+            }
+        }
+
+        let mut i = 0;
+        let evaluations = loop {
+            log_debug!(self.store, "iteration = {}", i);
+
+            let mut evaluations = Vec::with_capacity(limits.len());
+            for (key, quota) in limits {
+                let limiter = RateLimiter::new(&mut self.store, quota);
+                let increment = limiter.increment(quantity)?;
+                limiter.log_start(key, quantity, increment);
+
+                let (tat_val, now) = limiter.store.get_with_time(key)?;
+                let evaluation = limiter.evaluate(tat_val, now, increment);
+                limiter.log_end(&evaluation.result);
+
+                evaluations.push((tat_val, now, evaluation));
+            }
+
+            if evaluations.iter().any(|(_, _, e)| e.limited) {
+                break evaluations;
+            }
+
+            if self.commit(limits, &evaluations)? {
+                break evaluations;
+            }
+
+            retry_cas(&mut i, "update rate limit")?;
+        };
+
+        let limited = evaluations.iter().any(|(_, _, e)| e.limited);
+        let (binding, retry_after) = if limited {
+            binding_limited(&evaluations)
+        } else {
+            (binding_allowed(&evaluations), time::Duration::seconds(-1))
+        };
+
+        let results: Vec<RateLimitResult> =
+            evaluations.into_iter().map(|(_, _, e)| e.result).collect();
+
+        Ok((
+            limited,
+            MultiRateLimitResult {
+                binding,
+                retry_after,
+                results,
+            },
+        ))
+    }
+
+    // Persists the new TAT of every limit. If any of the updates lose a race
+    // with another limiter, the updates that had already been made are rolled
+    // back so that a retry doesn't charge those limits twice. Returns whether
+    // all updates were made.
+    fn commit(
+        &mut self,
+        limits: &[(&str, &RateQuota)],
+        evaluations: &[(Option<u64>, time::OffsetDateTime, Evaluation)],
+    ) -> Result<bool, CellError> {
+        for (i, ((key, _), (tat_val, now, evaluation))) in
+            limits.iter().zip(evaluations).enumerate()
+        {
+            if !evaluation.commit_to(&mut self.store, key, *tat_val, *now)? {
+                log_debug!(self.store, "rolling back {} update(s)", i);
+                self.rollback(&limits[..i], &evaluations[..i])?;
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn rollback(
+        &mut self,
+        limits: &[(&str, &RateQuota)],
+        evaluations: &[(Option<u64>, time::OffsetDateTime, Evaluation)],
+    ) -> Result<(), CellError> {
+        for ((key, _), (tat_val, now, evaluation)) in limits.iter().zip(evaluations) {
+            // Limits that didn't need anything written weren't changed.
+            if evaluation.tat_to_commit(*tat_val, *now).is_none() {
+                continue;
+            }
+
+            match tat_val {
+                Some(some_tat_val) => {
+                    let ttl = from_nanoseconds(*some_tat_val) - *now;
+                    self.store.compare_and_swap_with_ttl(
+                        key,
+                        nanoseconds(evaluation.new_tat),
+                        *some_tat_val,
+                        ttl.max(time::Duration::ZERO),
+                    )?;
+                }
+                None => {
+                    self.store.delete(key)?;
+                }
+            };
+        }
+
+        Ok(())
+    }
+}
+
+// Picks the limit that will take the longest to allow a limited request along
+// with the combined retry_after. A limit that can never allow the request
+// (because the quantity exceeds its maximum burst) wins outright with a
+// retry_after of -1.
+fn binding_limited(
+    evaluations: &[(Option<u64>, time::OffsetDateTime, Evaluation)],
+) -> (usize, time::Duration) {
+    let mut binding = 0;
+    let mut retry_after = time::Duration::ZERO;
+
+    for (i, (_, _, evaluation)) in evaluations.iter().enumerate() {
+        if !evaluation.limited {
+            continue;
+        }
+
+        if evaluation.result.retry_after < time::Duration::ZERO {
+            return (i, time::Duration::seconds(-1));
+        }
+
+        if evaluation.result.retry_after > retry_after {
+            binding = i;
+            retry_after = evaluation.result.retry_after;
+        }
+    }
+
+    (binding, retry_after)
+}
+
+// Picks the limit with the least remaining capacity for an allowed request.
+fn binding_allowed(
+    evaluations: &[(Option<u64>, time::OffsetDateTime, Evaluation)],
+) -> usize {
+    let mut binding = 0;
+    for (i, (_, _, evaluation)) in evaluations.iter().enumerate() {
+        if evaluation.result.remaining < evaluations[binding].2.result.remaining {
+            binding = i;
+        }
+    }
+    binding
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate time;
+
+    use crate::cell::clock::ManualClock;
+    use crate::cell::multi::*;
+    use crate::cell::tests::TestStore;
+    use crate::cell::{Rate, store};
+
+    #[test]
+    fn it_rate_limits_across_multiple_limits() {
+        let burst = RateQuota {
+            max_burst: 1,
+            max_rate: Rate::per_minute(1),
+        };
+        let quota = RateQuota {
+            max_burst: 2,
+            max_rate: Rate::per_day(1),
+        };
+        let clock = ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut memory_store =
+            store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = MultiRateLimiter::new(&mut memory_store);
+        let limits = [("burst", &burst), ("quota", &quota)];
+
+        // Allowed by both. The burst limit has less room left so it's the
+        // binding one.
+        let (limited, results) = limiter.rate_limit(&limits, 2).unwrap();
+        assert!(!limited);
+        assert_eq!(0, results.binding);
+        assert_eq!(time::Duration::seconds(-1), results.retry_after);
+        assert_eq!(0, results.results[0].remaining);
+        assert_eq!(1, results.results[1].remaining);
+
+        // Denied by the burst limit, which means that the daily quota must
+        // not have been charged either.
+        let (limited, results) = limiter.rate_limit(&limits, 1).unwrap();
+        assert!(limited);
+        assert_eq!(0, results.binding);
+        assert_eq!(time::Duration::seconds(60), results.retry_after);
+        assert!(!limited_by(&results, 1));
+
+        // Checking against the daily quota alone shows that it still has
+        // room for exactly one more.
+        let (limited, results) = limiter.rate_limit(&limits[1..], 1).unwrap();
+        assert!(!limited);
+        assert_eq!(0, results.results[0].remaining);
+
+        // Once the burst limit has room again, it's the daily quota that
+        // denies the request.
+        clock.advance(time::Duration::seconds(60));
+        let (limited, results) = limiter.rate_limit(&limits, 1).unwrap();
+        assert!(limited);
+        assert_eq!(1, results.binding);
+        assert_eq!(
+            time::Duration::days(1) - time::Duration::seconds(60),
+            results.retry_after
+        );
+        assert!(!limited_by(&results, 0));
+    }
+
+    // Whether the limit at the given index was one that denied the request.
+    fn limited_by(results: &MultiRateLimitResult, i: usize) -> bool {
+        results.results[i].retry_after >= time::Duration::ZERO
+    }
+
+    #[test]
+    fn it_reports_requests_that_can_never_be_allowed() {
+        let small = RateQuota {
+            max_burst: 0,
+            max_rate: Rate::per_minute(1),
+        };
+        let large = RateQuota {
+            max_burst: 10,
+            max_rate: Rate::per_minute(1),
+        };
+        let mut memory_store = store::MemoryStore::new_verbose();
+        let mut limiter = MultiRateLimiter::new(&mut memory_store);
+
+        let (limited, results) = limiter
+            .rate_limit(&[("large", &large), ("small", &small)], 2)
+            .unwrap();
+        assert!(limited);
+        assert_eq!(1, results.binding);
+        assert_eq!(time::Duration::seconds(-1), results.retry_after);
+    }
+
+    #[test]
+    fn it_does_not_write_limits_that_are_at_full_capacity() {
+        let quota = RateQuota {
+            max_burst: 1,
+            max_rate: Rate::per_minute(1),
+        };
+        let mut memory_store = store::MemoryStore::new_verbose();
+        let mut test_store = TestStore::new(&mut memory_store);
+        test_store.fail_updates = true;
+        let mut limiter = MultiRateLimiter::new(&mut test_store);
+
+        // A quantity of 0 against limits that don't exist yet leaves them at
+        // full capacity, so nothing is written for them and the failing
+        // updates are never tried.
+        let (limited, results) = limiter
+            .rate_limit(&[("foo", &quota), ("bar", &quota)], 0)
+            .unwrap();
+        assert!(!limited);
+        assert_eq!(2, results.results[0].remaining);
+        assert_eq!(2, results.results[1].remaining);
+    }
+
+    #[test]
+    fn it_rejects_duplicate_keys() {
+        let quota = RateQuota {
+            max_burst: 1,
+            max_rate: Rate::per_minute(1),
+        };
+        let mut memory_store = store::MemoryStore::new_verbose();
+        let mut limiter = MultiRateLimiter::new(&mut memory_store);
+
+        assert_eq!(
+            "Store error: Duplicate key: foo",
+            limiter
+                .rate_limit(&[("foo", &quota), ("foo", &quota)], 1)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
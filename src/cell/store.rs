@@ -1,8 +1,24 @@
 extern crate time;
 
+use crate::cell;
+use crate::cell::RateQuota;
+use crate::cell::clock::{Clock, SystemClock};
+use crate::datatype;
 use crate::error::CellError;
 use crate::redis;
+use crate::redis::raw;
 use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+#[cfg(feature = "remote-store")]
+mod remote;
+
+#[cfg(all(feature = "remote-store", feature = "async"))]
+pub use self::remote::AsyncRemoteRedisStore;
+#[cfg(feature = "remote-store")]
+pub use self::remote::RemoteRedisStore;
 
 /// Store exposes the atomic data store operations that the GCRA rate limiter
 /// needs to function correctly.
@@ -24,10 +40,14 @@ pub trait Store {
         ttl: time::Duration,
     ) -> Result<bool, CellError>;
 
+    /// Deletes the given key. Returns true if the key existed and was
+    /// removed, or false if there was nothing to delete.
+    fn delete(&mut self, key: &str) -> Result<bool, CellError>;
+
     /// Gets the given key's value and the current time as dictated by the
-    /// store (this is done so that rate limiters running on a variety of
-    /// different nodes can operate with a consistent clock instead of using
-    /// their own). If the key was unset, -1 is returned.
+    /// store's clock (this is done so that rate limiters running on a variety
+    /// of different nodes can operate with a consistent clock instead of using
+    /// their own). `None` is returned if the key was unset.
     fn get_with_time(
         &self,
         key: &str,
@@ -37,7 +57,7 @@ pub trait Store {
     fn log_debug(&self, message: &str);
 
     /// Sets the given key to the given value if and only if it doesn't already
-    /// exit. Whether or not the key existed previously it's given a new TTL.
+    /// exist. Whether or not the key existed previously it's given a new TTL.
     fn set_if_not_exists_with_ttl(
         &mut self,
         key: &str,
@@ -46,6 +66,83 @@ pub trait Store {
     ) -> Result<bool, CellError>;
 }
 
+/// `Leases` is the state of a single concurrency limiter: the leases that are
+/// currently held on it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Leases {
+    /// ID of the most recently acquired lease. IDs only ever go up so that a
+    /// lease that's expired can't be mistaken for a newer one.
+    pub last_id: u64,
+
+    /// Held leases as pairs of their ID and the time that they expire at in
+    /// nanoseconds since the Unix epoch, in the order that they were acquired.
+    pub leases: Vec<(u64, u64)>,
+}
+
+impl Leases {
+    /// The time that the last of the leases expires at in nanoseconds since
+    /// the Unix epoch, after which there's no need to keep them around.
+    pub fn expires_at(&self) -> Option<u64> {
+        self.leases.iter().map(|(_, expires_at)| *expires_at).max()
+    }
+}
+
+/// `LeaseStore` exposes the atomic data store operations that the concurrency
+/// limiter needs on top of those needed by the rate limiter.
+pub trait LeaseStore: Store {
+    /// Compares the leases at the given key with a known old set (`None`
+    /// meaning that the key is unset) and swaps them for a new set if and only
+    /// if they're equal. The key expires along with the last of the new
+    /// leases, and is deleted if there aren't any.
+    fn compare_and_swap_leases(
+        &mut self,
+        key: &str,
+        old: Option<&Leases>,
+        new: &Leases,
+    ) -> Result<bool, CellError>;
+
+    /// Gets the leases at the given key along with the current time as
+    /// dictated by the store. `None` is returned if the key was unset.
+    fn get_leases_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<Leases>, time::OffsetDateTime), CellError>;
+}
+
+/// `Window` is the state of a single window limiter: counts of requests
+/// bucketed by time. A sliding log has a bucket for every request, while a
+/// sliding counter has one for each of the fixed windows that it weighs, and a
+/// calendar limiter one for the current calendar period.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Window {
+    /// Buckets as pairs of their start time in nanoseconds since the Unix
+    /// epoch and the quantity counted in them, from oldest to newest.
+    pub buckets: Vec<(u64, i64)>,
+}
+
+/// `WindowStore` exposes the atomic data store operations that the window
+/// limiters need on top of those needed by the rate limiter.
+pub trait WindowStore: Store {
+    /// Compares the window at the given key with a known old one (`None`
+    /// meaning that the key is unset) and swaps it for a new one if and only
+    /// if they're equal. Also sets the key's TTL until it expires. The key is
+    /// deleted if the new window has no buckets.
+    fn compare_and_swap_window_with_ttl(
+        &mut self,
+        key: &str,
+        old: Option<&Window>,
+        new: &Window,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError>;
+
+    /// Gets the window at the given key along with the current time as
+    /// dictated by the store. `None` is returned if the key was unset.
+    fn get_window_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<Window>, time::OffsetDateTime), CellError>;
+}
+
 // Implement the `Store` trait for a mutable reference. This is useful so that
 // we don't have to assign a lifetime (`'a`) to `RateLimiter`, thus simplifying
 // our code there by quite a bit.
@@ -60,6 +157,10 @@ impl<T: Store> Store for &mut T {
         (**self).compare_and_swap_with_ttl(key, old, new, ttl)
     }
 
+    fn delete(&mut self, key: &str) -> Result<bool, CellError> {
+        (**self).delete(key)
+    }
+
     fn get_with_time(
         &self,
         key: &str,
@@ -81,14 +182,62 @@ impl<T: Store> Store for &mut T {
     }
 }
 
+impl<T: LeaseStore> LeaseStore for &mut T {
+    fn compare_and_swap_leases(
+        &mut self,
+        key: &str,
+        old: Option<&Leases>,
+        new: &Leases,
+    ) -> Result<bool, CellError> {
+        (**self).compare_and_swap_leases(key, old, new)
+    }
+
+    fn get_leases_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<Leases>, time::OffsetDateTime), CellError> {
+        (**self).get_leases_with_time(key)
+    }
+}
+
+impl<T: WindowStore> WindowStore for &mut T {
+    fn compare_and_swap_window_with_ttl(
+        &mut self,
+        key: &str,
+        old: Option<&Window>,
+        new: &Window,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        (**self).compare_and_swap_window_with_ttl(key, old, new, ttl)
+    }
+
+    fn get_window_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<Window>, time::OffsetDateTime), CellError> {
+        (**self).get_window_with_time(key)
+    }
+}
+
 /// `MemoryStore` is a simple implementation of Store that persists data in an
 /// in-memory `HashMap`.
 ///
-/// Note that the implementation is currently not thread-safe and will need a
-/// mutex added if it's ever used for anything serious.
-#[derive(Default)]
+/// Keys expire along with their TTLs like they would in Redis. Expired keys
+/// are treated as unset and are removed the next time that they're accessed.
+/// Leases are the exception: they're kept until they're released, since the
+/// concurrency limiter already ignores those that have expired.
+///
+/// The store is thread-safe. Its data is kept behind a mutex that every clone
+/// shares, so each thread can hold a clone of its own and they'll all see the
+/// same keys. Keys that are never accessed again are never removed though, so
+/// anything long-lived should use `ConcurrentMemoryStore` instead.
+///
+/// Time is taken from the system clock unless the store's given another one
+/// with `with_clock`.
+#[derive(Clone)]
 pub struct MemoryStore {
-    map: HashMap<String, u64>,
+    clock: Arc<dyn Clock + Send + Sync>,
+    data: Arc<Mutex<MemoryData>>,
     verbose: bool,
 }
 
@@ -99,8 +248,34 @@ impl MemoryStore {
 
     pub fn new_verbose() -> MemoryStore {
         MemoryStore {
-            map: HashMap::new(),
             verbose: true,
+            ..Self::default()
+        }
+    }
+
+    /// Has the store take the time from the given clock, which both what it
+    /// reports to limiters and when its keys expire go by.
+    pub fn with_clock(self, clock: impl Clock + Send + Sync + 'static) -> MemoryStore {
+        MemoryStore {
+            clock: Arc::new(clock),
+            ..self
+        }
+    }
+
+    /// Gets the time left until the given key expires. The outer `None` means
+    /// that the key is unset, and the inner one that it never expires.
+    pub fn ttl(&self, key: &str) -> Result<Option<Option<time::Duration>>, CellError> {
+        let now = self.clock.now()?;
+        Ok(lock(&self.data).ttl(key, now))
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore {
+            clock: Arc::new(SystemClock),
+            data: Arc::default(),
+            verbose: false,
         }
     }
 }
@@ -111,22 +286,23 @@ impl Store for MemoryStore {
         key: &str,
         old: u64,
         new: u64,
-        _: time::Duration,
+        ttl: time::Duration,
     ) -> Result<bool, CellError> {
-        match self.map.get(key) {
-            Some(n) if *n != old => return Ok(false),
-            _ => (),
-        };
+        let now = self.clock.now()?;
+        Ok(lock(&self.data).compare_and_swap(key, old, new, ttl, now))
+    }
 
-        self.map.insert(String::from(key), new);
-        Ok(true)
+    fn delete(&mut self, key: &str) -> Result<bool, CellError> {
+        let now = self.clock.now()?;
+        Ok(lock(&self.data).delete(key, now))
     }
 
     fn get_with_time(
         &self,
         key: &str,
     ) -> Result<(Option<u64>, time::OffsetDateTime), CellError> {
-        Ok((self.map.get(key).copied(), time::OffsetDateTime::now_utc()))
+        let now = self.clock.now()?;
+        Ok((lock(&self.data).get(key, now), now))
     }
 
     fn log_debug(&self, message: &str) {
@@ -139,16 +315,464 @@ impl Store for MemoryStore {
         &mut self,
         key: &str,
         value: u64,
-        _: time::Duration,
+        ttl: time::Duration,
     ) -> Result<bool, CellError> {
-        match self.map.get(key) {
-            Some(_) => Ok(false),
-            None => {
-                self.map.insert(String::from(key), value);
-                Ok(true)
-            }
+        let now = self.clock.now()?;
+        Ok(lock(&self.data).set_if_not_exists(key, value, ttl, now))
+    }
+}
+
+impl LeaseStore for MemoryStore {
+    fn compare_and_swap_leases(
+        &mut self,
+        key: &str,
+        old: Option<&Leases>,
+        new: &Leases,
+    ) -> Result<bool, CellError> {
+        let now = self.clock.now()?;
+        Ok(lock(&self.data).compare_and_swap_leases(key, old, new, now))
+    }
+
+    fn get_leases_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<Leases>, time::OffsetDateTime), CellError> {
+        let now = self.clock.now()?;
+        Ok((lock(&self.data).get_leases(key, now), now))
+    }
+}
+
+impl WindowStore for MemoryStore {
+    fn compare_and_swap_window_with_ttl(
+        &mut self,
+        key: &str,
+        old: Option<&Window>,
+        new: &Window,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        let now = self.clock.now()?;
+        Ok(lock(&self.data).compare_and_swap_window(key, old, new, ttl, now))
+    }
+
+    fn get_window_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<Window>, time::OffsetDateTime), CellError> {
+        let now = self.clock.now()?;
+        Ok((lock(&self.data).get_window(key, now), now))
+    }
+}
+
+/// `ConcurrentMemoryStore` is an in-memory implementation of Store for
+/// embedding rate limiters directly in long-running, multi-threaded programs.
+///
+/// Keys are spread across a number of shards, each behind a mutex of its own,
+/// so that threads working on different keys rarely wait on each other.
+/// Clones share the same shards.
+///
+/// Keys expire along with their TTLs like they would in Redis. Expired keys
+/// are treated as unset as soon as they expire, and a background thread
+/// removes them periodically along with any leases that have all expired, so
+/// memory doesn't grow with keys that are never accessed again. The thread
+/// stops once every clone of the store has been dropped.
+///
+/// Time is taken from the system clock unless the store's created with
+/// another one by `with_options`.
+#[derive(Clone)]
+pub struct ConcurrentMemoryStore {
+    shards: Arc<Shards>,
+    verbose: bool,
+}
+
+struct Shards {
+    clock: Box<dyn Clock + Send + Sync>,
+    hasher: RandomState,
+    shards: Vec<Mutex<MemoryData>>,
+}
+
+impl Shards {
+    fn get(&self, key: &str) -> &Mutex<MemoryData> {
+        let hash = self.hasher.hash_one(key) as usize;
+        &self.shards[hash % self.shards.len()]
+    }
+}
+
+impl ConcurrentMemoryStore {
+    /// How often expired keys are removed by default.
+    pub const DEFAULT_EVICTION_INTERVAL: std::time::Duration =
+        std::time::Duration::from_secs(1);
+
+    /// The shortest interval that expired keys can be removed at, so that the
+    /// thread removing them doesn't spin.
+    pub const MIN_EVICTION_INTERVAL: std::time::Duration =
+        std::time::Duration::from_millis(1);
+
+    /// Creates a store with a few shards for each available CPU, which
+    /// evicts expired keys every `DEFAULT_EVICTION_INTERVAL`.
+    pub fn new() -> ConcurrentMemoryStore {
+        let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_options(cpus * 4, Self::DEFAULT_EVICTION_INTERVAL, SystemClock)
+    }
+
+    /// Creates a store with the given number of shards (at least one), which
+    /// evicts expired keys every `eviction_interval` (at least every
+    /// `MIN_EVICTION_INTERVAL`) and takes the time from the given clock.
+    pub fn with_options(
+        shards: usize,
+        eviction_interval: std::time::Duration,
+        clock: impl Clock + Send + Sync + 'static,
+    ) -> ConcurrentMemoryStore {
+        let shards = Arc::new(Shards {
+            clock: Box::new(clock),
+            hasher: RandomState::new(),
+            shards: (0..shards.max(1))
+                .map(|_| Mutex::new(MemoryData::default()))
+                .collect(),
+        });
+
+        // The thread only holds a weak reference so that it doesn't keep the
+        // store alive, and stops as soon as it finds it gone.
+        let weak = Arc::downgrade(&shards);
+        let eviction_interval = eviction_interval.max(Self::MIN_EVICTION_INTERVAL);
+        thread::Builder::new()
+            .name("redis-cell-eviction".to_string())
+            .spawn(move || {
+                loop {
+                    thread::sleep(eviction_interval);
+                    let Some(shards) = weak.upgrade() else {
+                        return;
+                    };
+
+                    // A clock that can't be read now will probably be read
+                    // fine next time.
+                    let Ok(now) = shards.clock.now() else {
+                        continue;
+                    };
+
+                    // Shards are locked one at a time so that the rest stay
+                    // available while each is swept.
+                    for shard in shards.shards.iter() {
+                        lock(shard).evict(now);
+                    }
+                }
+            })
+            .expect("failed to spawn eviction thread");
+
+        ConcurrentMemoryStore {
+            shards,
+            verbose: false,
+        }
+    }
+
+    pub fn new_verbose() -> ConcurrentMemoryStore {
+        ConcurrentMemoryStore {
+            verbose: true,
+            ..Self::new()
+        }
+    }
+
+    /// Gets the time left until the given key expires. The outer `None` means
+    /// that the key is unset, and the inner one that it never expires.
+    pub fn ttl(&self, key: &str) -> Result<Option<Option<time::Duration>>, CellError> {
+        let now = self.shards.clock.now()?;
+        Ok(self.lock(key).ttl(key, now))
+    }
+
+    /// Gets the number of keys that the store holds, counting any that have
+    /// expired but haven't been evicted yet.
+    pub fn len(&self) -> usize {
+        self.shards
+            .shards
+            .iter()
+            .map(|shard| lock(shard).len())
+            .sum()
+    }
+
+    /// Whether the store holds no keys at all, expired or not.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self, key: &str) -> MutexGuard<'_, MemoryData> {
+        lock(self.shards.get(key))
+    }
+}
+
+impl Default for ConcurrentMemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Store for ConcurrentMemoryStore {
+    fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: u64,
+        new: u64,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        let now = self.shards.clock.now()?;
+        Ok(self.lock(key).compare_and_swap(key, old, new, ttl, now))
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool, CellError> {
+        let now = self.shards.clock.now()?;
+        Ok(self.lock(key).delete(key, now))
+    }
+
+    fn get_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<u64>, time::OffsetDateTime), CellError> {
+        let now = self.shards.clock.now()?;
+        Ok((self.lock(key).get(key, now), now))
+    }
+
+    fn log_debug(&self, message: &str) {
+        if self.verbose {
+            println!("concurrent_memory_store: {message}");
+        }
+    }
+
+    fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: u64,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        let now = self.shards.clock.now()?;
+        Ok(self.lock(key).set_if_not_exists(key, value, ttl, now))
+    }
+}
+
+impl LeaseStore for ConcurrentMemoryStore {
+    fn compare_and_swap_leases(
+        &mut self,
+        key: &str,
+        old: Option<&Leases>,
+        new: &Leases,
+    ) -> Result<bool, CellError> {
+        let now = self.shards.clock.now()?;
+        Ok(self.lock(key).compare_and_swap_leases(key, old, new, now))
+    }
+
+    fn get_leases_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<Leases>, time::OffsetDateTime), CellError> {
+        let now = self.shards.clock.now()?;
+        Ok((self.lock(key).get_leases(key, now), now))
+    }
+}
+
+impl WindowStore for ConcurrentMemoryStore {
+    fn compare_and_swap_window_with_ttl(
+        &mut self,
+        key: &str,
+        old: Option<&Window>,
+        new: &Window,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        let now = self.shards.clock.now()?;
+        Ok(self
+            .lock(key)
+            .compare_and_swap_window(key, old, new, ttl, now))
+    }
+
+    fn get_window_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<Window>, time::OffsetDateTime), CellError> {
+        let now = self.shards.clock.now()?;
+        Ok((self.lock(key).get_window(key, now), now))
+    }
+}
+
+// Locks some of the in-memory stores' data. Every change to the data is made
+// in a single step, so a panic while the lock's held can't leave it
+// inconsistent and a poisoned lock is safe to keep using.
+fn lock(data: &Mutex<MemoryData>) -> MutexGuard<'_, MemoryData> {
+    data.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Keys of the in-memory stores along with the times that they expire at. Each
+// operation is given the current time, and treats keys that expired before it
+// as unset.
+#[derive(Default)]
+struct MemoryData {
+    map: HashMap<String, u64>,
+    leases: HashMap<String, Leases>,
+    windows: HashMap<String, Window>,
+
+    // Times that keys expire at. Keys without an entry never expire.
+    expires: HashMap<String, time::OffsetDateTime>,
+}
+
+impl MemoryData {
+    fn compare_and_swap(
+        &mut self,
+        key: &str,
+        old: u64,
+        new: u64,
+        ttl: time::Duration,
+        now: time::OffsetDateTime,
+    ) -> bool {
+        // Like the other stores, a key that's gone (say, because it was
+        // deleted or expired since it was read) isn't swapped.
+        self.expire(key, now);
+        if self.map.get(key) != Some(&old) {
+            return false;
+        }
+
+        self.map.insert(String::from(key), new);
+        self.set_ttl(key, ttl, now);
+        true
+    }
+
+    fn compare_and_swap_leases(
+        &mut self,
+        key: &str,
+        old: Option<&Leases>,
+        new: &Leases,
+        now: time::OffsetDateTime,
+    ) -> bool {
+        self.expire(key, now);
+        if self.leases.get(key) != old {
+            return false;
+        }
+
+        if new.leases.is_empty() {
+            self.remove(key);
+        } else {
+            self.leases.insert(String::from(key), new.clone());
+        }
+        true
+    }
+
+    fn compare_and_swap_window(
+        &mut self,
+        key: &str,
+        old: Option<&Window>,
+        new: &Window,
+        ttl: time::Duration,
+        now: time::OffsetDateTime,
+    ) -> bool {
+        self.expire(key, now);
+        if self.windows.get(key) != old {
+            return false;
+        }
+
+        if new.buckets.is_empty() {
+            self.remove(key);
+        } else {
+            self.windows.insert(String::from(key), new.clone());
+            self.set_ttl(key, ttl, now);
+        }
+        true
+    }
+
+    fn delete(&mut self, key: &str, now: time::OffsetDateTime) -> bool {
+        self.expire(key, now);
+        self.remove(key)
+    }
+
+    // Removes every key that's expired, along with any whose leases have all
+    // expired.
+    fn evict(&mut self, now: time::OffsetDateTime) {
+        let expired: Vec<String> = self
+            .expires
+            .iter()
+            .filter(|(_, expires_at)| **expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.remove(&key);
+        }
+
+        let now = cell::nanoseconds(now);
+        self.leases
+            .retain(|_, leases| leases.expires_at().is_some_and(|at| at > now));
+    }
+
+    // Removes the given key if it's expired.
+    fn expire(&mut self, key: &str, now: time::OffsetDateTime) {
+        if self
+            .expires
+            .get(key)
+            .is_some_and(|expires_at| *expires_at <= now)
+        {
+            self.remove(key);
+        }
+    }
+
+    fn get(&mut self, key: &str, now: time::OffsetDateTime) -> Option<u64> {
+        self.expire(key, now);
+        self.map.get(key).copied()
+    }
+
+    fn get_leases(&mut self, key: &str, now: time::OffsetDateTime) -> Option<Leases> {
+        self.expire(key, now);
+        self.leases.get(key).cloned()
+    }
+
+    fn get_window(&mut self, key: &str, now: time::OffsetDateTime) -> Option<Window> {
+        self.expire(key, now);
+        self.windows.get(key).cloned()
+    }
+
+    fn remove(&mut self, key: &str) -> bool {
+        self.expires.remove(key);
+        let tat = self.map.remove(key);
+        let leases = self.leases.remove(key);
+        let window = self.windows.remove(key);
+        tat.is_some() || leases.is_some() || window.is_some()
+    }
+
+    fn set_if_not_exists(
+        &mut self,
+        key: &str,
+        value: u64,
+        ttl: time::Duration,
+        now: time::OffsetDateTime,
+    ) -> bool {
+        self.expire(key, now);
+        let created = !self.map.contains_key(key);
+        if created {
+            self.map.insert(String::from(key), value);
+        }
+        self.set_ttl(key, ttl, now);
+        created
+    }
+
+    // Sets the given key's TTL. Like in Redis, a key given a TTL that isn't
+    // positive has already expired, so it's removed.
+    fn set_ttl(&mut self, key: &str, ttl: time::Duration, now: time::OffsetDateTime) {
+        if ttl.is_positive() {
+            self.expires.insert(String::from(key), now + ttl);
+        } else {
+            self.remove(key);
         }
     }
+
+    fn len(&self) -> usize {
+        self.map.len() + self.leases.len() + self.windows.len()
+    }
+
+    fn ttl(
+        &mut self,
+        key: &str,
+        now: time::OffsetDateTime,
+    ) -> Option<Option<time::Duration>> {
+        self.expire(key, now);
+        if !self.map.contains_key(key)
+            && !self.leases.contains_key(key)
+            && !self.windows.contains_key(key)
+        {
+            return None;
+        }
+        Some(self.expires.get(key).map(|expires_at| *expires_at - now))
+    }
 }
 
 /// `InternalRedisStore` is a store implementation for Redis.
@@ -156,13 +780,102 @@ impl Store for MemoryStore {
 /// It uses Redis' modules APIs in that it's designed to run from within a Redis
 /// runtime. This allows us to cut some corners around atomicity because we can
 /// safety assume that all operations will be atomic.
+///
+/// TATs are stored as the module's native data type along with the quota that
+/// they were written with. Keys holding a decimal string (as written by older
+/// versions of the module) are still read, and are converted to the native
+/// type the next time that they're written.
+///
+/// Every change that the store makes is replicated as its effect (the exact
+/// TAT that was written, or a key deletion) rather than by the command that
+/// caused it, so that replicas and AOF replays don't need to recompute
+/// anything with their own clocks.
+///
+/// Time is taken from the system clock, which, since the module runs inside
+/// the server, is the server's own, unless the store's given another one with
+/// `with_clock`.
 pub struct InternalRedisStore<'a> {
+    // Clock to take the time from, which expiry times are also set by.
+    clock: Box<dyn Clock>,
+
     r: &'a redis::Redis,
+
+    // Quotas to store alongside the TATs of the keys that they're for.
+    quotas: HashMap<String, (i64, i64)>,
 }
 
 impl<'a> InternalRedisStore<'a> {
     pub fn new(r: &'a redis::Redis) -> InternalRedisStore<'a> {
-        InternalRedisStore { r }
+        InternalRedisStore {
+            clock: Box::new(SystemClock),
+            r,
+            quotas: HashMap::new(),
+        }
+    }
+
+    /// Has the store take the time from the given clock instead of the
+    /// system's. Expiry times are set by it as well.
+    pub fn with_clock(self, clock: impl Clock + 'static) -> InternalRedisStore<'a> {
+        InternalRedisStore {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+
+    /// Sets the quota that's stored along with the TAT of the given key when
+    /// it's written. A key without a quota is written with a zeroed one.
+    pub fn set_quota(&mut self, key: &str, quota: &RateQuota) {
+        self.quotas.insert(
+            key.to_string(),
+            (
+                quota.max_burst,
+                quota.max_rate.period.whole_nanoseconds() as i64,
+            ),
+        );
+    }
+
+    // The absolute time in milliseconds since the Unix epoch that a key given
+    // the TTL now expires at.
+    fn expires_at_ms(&self, ttl: time::Duration) -> Result<i64, CellError> {
+        let expires_at = self.clock.now()? + ttl;
+        Ok((expires_at.unix_timestamp_nanos() / 1_000_000) as i64)
+    }
+
+    fn write(
+        &self,
+        key: &redis::RedisKeyWritable,
+        name: &str,
+        tat: u64,
+        ttl: time::Duration,
+    ) -> Result<(), CellError> {
+        let (max_burst, emission_interval) =
+            self.quotas.get(name).copied().unwrap_or_default();
+        key.set_value(
+            datatype::tat_type(),
+            datatype::TatValue {
+                tat,
+                max_burst,
+                emission_interval,
+            },
+        )?;
+
+        // Setting a value clears the key's expiry, so it's always set again
+        // afterwards. It's set as an absolute time so that exactly the same
+        // one can be given to replicas.
+        let expires_at_ms = self.expires_at_ms(ttl)?;
+        key.set_expire_at(expires_at_ms)?;
+
+        self.r.replicate(
+            datatype::SET_TAT_COMMAND,
+            &[
+                name,
+                tat.to_string().as_str(),
+                max_burst.to_string().as_str(),
+                emission_interval.to_string().as_str(),
+                "pxat",
+                expires_at_ms.to_string().as_str(),
+            ],
+        )
     }
 }
 
@@ -174,41 +887,52 @@ impl Store for InternalRedisStore<'_> {
         new: u64,
         ttl: time::Duration,
     ) -> Result<bool, CellError> {
-        let key = self.r.open_key_writable(key);
-        match key.read()? {
-            Some(s) => {
-                // While we will usually have a value here to parse, it's possible that
-                // in the case of a very fast rate the key's already been
-                // expired even since the beginning of this operation.
-                // Check whether the value is empty to handle that possibility.
-                if !s.is_empty() && s.parse::<u64>()? == old {
-                    // Still the old value: perform the swap.
-                    key.write(new.to_string().as_str())?;
-                    key.set_expire(ttl)?;
-                    Ok(true)
-                } else {
-                    // Not the old value: something else must have set it. Take no
-                    // action.
-                    Ok(false)
-                }
-            }
+        let redis_key = self.r.open_key_writable(key);
+
+        // While we will usually have a value here, it's possible that in the
+        // case of a very fast rate the key's already been expired even since
+        // the beginning of this operation.
+        let current = match redis_key.key_type() {
+            raw::KeyType::String => parse_legacy_tat(redis_key.read()?)?,
+            _ => redis_key
+                .value::<datatype::TatValue>(datatype::tat_type())?
+                .map(|v| v.tat),
+        };
+
+        if current == Some(old) {
+            // Still the old value: perform the swap.
+            self.write(&redis_key, key, new, ttl)?;
+            Ok(true)
+        } else {
+            // Not the old value: something else must have set it. Take no
+            // action.
+            Ok(false)
+        }
+    }
 
-            // Value wasn't set.
-            None => Ok(false),
+    fn delete(&mut self, key: &str) -> Result<bool, CellError> {
+        let redis_key = self.r.open_key_writable(key);
+        if redis_key.is_empty()? {
+            return Ok(false);
         }
+
+        redis_key.delete()?;
+        self.r.replicate("del", &[key])?;
+        Ok(true)
     }
 
     fn get_with_time(
         &self,
         key: &str,
     ) -> Result<(Option<u64>, time::OffsetDateTime), CellError> {
-        // TODO: currently leveraging that CommandError and CellError are the
-        // same thing, but we should probably reconcile this.
         let key = self.r.open_key(key);
-        Ok((
-            key.read()?.map(|s| s.parse::<u64>().unwrap()),
-            time::OffsetDateTime::now_utc(),
-        ))
+        let tat = match key.key_type() {
+            raw::KeyType::String => parse_legacy_tat(key.read()?)?,
+            _ => key
+                .value::<datatype::TatValue>(datatype::tat_type())?
+                .map(|v| v.tat),
+        };
+        Ok((tat, self.clock.now()?))
     }
 
     fn log_debug(&self, message: &str) {
@@ -221,43 +945,382 @@ impl Store for InternalRedisStore<'_> {
         value: u64,
         ttl: time::Duration,
     ) -> Result<bool, CellError> {
-        let key = self.r.open_key_writable(key);
-        let res = if key.is_empty()? {
-            key.write(value.to_string().as_str())?;
-            Ok(true)
-        } else {
-            Ok(false)
+        let redis_key = self.r.open_key_writable(key);
+        if redis_key.is_empty()? {
+            self.write(&redis_key, key, value, ttl)?;
+            return Ok(true);
+        }
+
+        // The key's left alone, but still given a new TTL.
+        let expires_at_ms = self.expires_at_ms(ttl)?;
+        redis_key.set_expire_at(expires_at_ms)?;
+        self.r
+            .replicate("pexpireat", &[key, expires_at_ms.to_string().as_str()])?;
+        Ok(false)
+    }
+}
+
+impl LeaseStore for InternalRedisStore<'_> {
+    fn compare_and_swap_leases(
+        &mut self,
+        key: &str,
+        old: Option<&Leases>,
+        new: &Leases,
+    ) -> Result<bool, CellError> {
+        let redis_key = self.r.open_key_writable(key);
+        if redis_key.value::<Leases>(datatype::leases_type())? != old {
+            return Ok(false);
+        }
+
+        let Some(expires_at) = new.expires_at() else {
+            if old.is_some() {
+                redis_key.delete()?;
+                self.r.replicate("del", &[key])?;
+            }
+            return Ok(true);
         };
-        key.set_expire(ttl)?;
-        res
+
+        // As with TATs, the key's expiry is derived from the value so that
+        // replicas end up with exactly the same one.
+        redis_key.set_value(datatype::leases_type(), new.clone())?;
+        redis_key.set_expire_at(datatype::leases_expire_at_ms(expires_at))?;
+
+        let mut args = vec![key.to_string(), new.last_id.to_string()];
+        for (id, expires_at) in &new.leases {
+            args.push(id.to_string());
+            args.push(expires_at.to_string());
+        }
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        self.r.replicate(datatype::SET_LEASES_COMMAND, &args)?;
+        Ok(true)
+    }
+
+    fn get_leases_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<Leases>, time::OffsetDateTime), CellError> {
+        let key = self.r.open_key(key);
+        let leases = key.value::<Leases>(datatype::leases_type())?.cloned();
+        Ok((leases, self.clock.now()?))
+    }
+}
+
+impl WindowStore for InternalRedisStore<'_> {
+    fn compare_and_swap_window_with_ttl(
+        &mut self,
+        key: &str,
+        old: Option<&Window>,
+        new: &Window,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        let redis_key = self.r.open_key_writable(key);
+        let current = redis_key
+            .value::<datatype::WindowValue>(datatype::window_type())?
+            .map(|v| &v.window);
+        if current != old {
+            return Ok(false);
+        }
+
+        if new.buckets.is_empty() {
+            if old.is_some() {
+                redis_key.delete()?;
+                self.r.replicate("del", &[key])?;
+            }
+            return Ok(true);
+        }
+
+        // Unlike with leases, the expiry can't be derived from the buckets
+        // alone, so it's stored along with them for AOF rewrites.
+        let expires_at_ms = self.expires_at_ms(ttl)?;
+        redis_key.set_value(
+            datatype::window_type(),
+            datatype::WindowValue {
+                expires_at_ms,
+                window: new.clone(),
+            },
+        )?;
+        redis_key.set_expire_at(expires_at_ms)?;
+
+        let mut args = vec![key.to_string(), expires_at_ms.to_string()];
+        for (start, count) in &new.buckets {
+            args.push(start.to_string());
+            args.push(count.to_string());
+        }
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        self.r.replicate(datatype::SET_WINDOW_COMMAND, &args)?;
+        Ok(true)
+    }
+
+    fn get_window_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<Window>, time::OffsetDateTime), CellError> {
+        let key = self.r.open_key(key);
+        let window = key
+            .value::<datatype::WindowValue>(datatype::window_type())?
+            .map(|v| v.window.clone());
+        Ok((window, self.clock.now()?))
+    }
+}
+
+// Parses a TAT stored as a decimal string by an older version of the module.
+fn parse_legacy_tat(val: Option<String>) -> Result<Option<u64>, CellError> {
+    match val {
+        Some(s) if !s.is_empty() => Ok(Some(s.parse::<u64>()?)),
+        _ => Ok(None),
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     extern crate time;
 
+    use crate::cell::clock::ManualClock;
     use crate::cell::store::*;
 
     #[test]
     fn it_performs_compare_and_swap_with_ttl() {
         let mut store = MemoryStore::default();
 
+        // There's nothing to swap until the key's been set.
+        let ttl = time::Duration::minutes(1);
+        let res0 = store.compare_and_swap_with_ttl("foo", 123, 124, ttl);
+        assert_eq!(false, res0.unwrap());
+        let _ = store.set_if_not_exists_with_ttl("foo", 123, ttl).unwrap();
+
         // First attempt obviously works.
-        let res1 = store.compare_and_swap_with_ttl("foo", 123, 124, time::Duration::ZERO);
+        let res1 = store.compare_and_swap_with_ttl("foo", 123, 124, ttl);
         assert_eq!(true, res1.unwrap());
 
         // Second attempt succeeds: we use the value we just set combined with
         // a new value.
-        let res2 = store.compare_and_swap_with_ttl("foo", 124, 125, time::Duration::ZERO);
+        let res2 = store.compare_and_swap_with_ttl("foo", 124, 125, ttl);
         assert_eq!(true, res2.unwrap());
 
         // Third attempt fails: we try to overwrite using a value that is
         // incorrect.
-        let res2 = store.compare_and_swap_with_ttl("foo", 123, 126, time::Duration::ZERO);
+        let res2 = store.compare_and_swap_with_ttl("foo", 123, 126, ttl);
         assert_eq!(false, res2.unwrap());
     }
 
+    #[test]
+    fn it_performs_compare_and_swap_leases() {
+        let mut store = MemoryStore::default();
+        let leases = Leases {
+            last_id: 1,
+            leases: vec![(1, 123)],
+        };
+
+        // The key's unset, so only a swap from nothing works.
+        assert!(
+            !store
+                .compare_and_swap_leases("foo", Some(&leases), &leases)
+                .unwrap()
+        );
+        assert!(store.compare_and_swap_leases("foo", None, &leases).unwrap());
+        assert_eq!(
+            Some(leases.clone()),
+            store.get_leases_with_time("foo").unwrap().0
+        );
+
+        // Swapping in an empty set removes the key.
+        let empty = Leases {
+            last_id: 1,
+            leases: vec![],
+        };
+        assert!(!store.compare_and_swap_leases("foo", None, &empty).unwrap());
+        assert!(
+            store
+                .compare_and_swap_leases("foo", Some(&leases), &empty)
+                .unwrap()
+        );
+        assert!(store.get_leases_with_time("foo").unwrap().0.is_none());
+    }
+
+    #[test]
+    fn it_performs_delete() {
+        let mut store = MemoryStore::default();
+
+        // Nothing to delete yet.
+        assert!(!store.delete("foo").unwrap());
+
+        let _ = store
+            .set_if_not_exists_with_ttl("foo", 123, time::Duration::minutes(1))
+            .unwrap();
+
+        assert!(store.delete("foo").unwrap());
+        assert!(store.get_with_time("foo").unwrap().0.is_none());
+        assert!(!store.delete("foo").unwrap());
+    }
+
+    #[test]
+    fn it_expires_keys() {
+        let clock = ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut store = MemoryStore::default().with_clock(clock.clone());
+        let _ = store
+            .set_if_not_exists_with_ttl("foo", 123, time::Duration::milliseconds(1))
+            .unwrap();
+        let _ = store
+            .set_if_not_exists_with_ttl("bar", 123, time::Duration::minutes(1))
+            .unwrap();
+        let _ = store
+            .set_if_not_exists_with_ttl("baz", 123, time::Duration::ZERO)
+            .unwrap();
+
+        // Clones share the same keys.
+        let clone = store.clone();
+        assert_eq!(Some(123), clone.get_with_time("foo").unwrap().0);
+
+        clock.advance(time::Duration::milliseconds(2));
+
+        // Only the key with the shortest TTL is gone, and one given a TTL of
+        // zero was never there at all.
+        assert!(clone.get_with_time("foo").unwrap().0.is_none());
+        assert!(store.ttl("foo").unwrap().is_none());
+        assert_eq!(
+            Some(Some(
+                time::Duration::minutes(1) - time::Duration::milliseconds(2)
+            )),
+            store.ttl("bar").unwrap()
+        );
+        assert!(store.ttl("baz").unwrap().is_none());
+    }
+
+    #[test]
+    fn it_evicts_expired_keys_in_the_background() {
+        let clock = ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut store = ConcurrentMemoryStore::with_options(
+            4,
+            std::time::Duration::from_millis(1),
+            clock.clone(),
+        );
+        for i in 0..20 {
+            let key = format!("foo{i}");
+            let _ = store
+                .set_if_not_exists_with_ttl(&key, 123, time::Duration::milliseconds(1))
+                .unwrap();
+        }
+        let _ = store
+            .set_if_not_exists_with_ttl("bar", 123, time::Duration::minutes(1))
+            .unwrap();
+        let leases = Leases {
+            last_id: 1,
+            leases: vec![(1, 123)],
+        };
+        assert!(store.compare_and_swap_leases("baz", None, &leases).unwrap());
+
+        // A key written with a TTL of zero has already expired, so there's
+        // nothing left of it for the eviction thread to miss.
+        let _ = store
+            .set_if_not_exists_with_ttl("qux", 123, time::Duration::ZERO)
+            .unwrap();
+        assert_eq!(22, store.len());
+        assert!(store.ttl("qux").unwrap().is_none());
+
+        // Only the key that hasn't expired is left once the eviction thread
+        // has been around, without having touched any of the others.
+        clock.advance(time::Duration::seconds(1));
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while store.len() > 1 {
+            assert!(std::time::Instant::now() < deadline, "keys weren't evicted");
+            std::thread::yield_now();
+        }
+        let keys: Vec<String> = store
+            .shards
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                let data = lock(shard);
+                data.map
+                    .keys()
+                    .chain(data.leases.keys())
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(vec!["bar".to_string()], keys);
+        assert_eq!(
+            Some(Some(time::Duration::seconds(59))),
+            store.ttl("bar").unwrap()
+        );
+    }
+
+    #[test]
+    fn it_rate_limits_across_threads() {
+        let store = ConcurrentMemoryStore::new();
+        let quota = cell::RateQuota {
+            max_burst: 99,
+            max_rate: cell::Rate::per_period(1, time::Duration::hours(1)),
+        };
+
+        // Every thread takes what it can from the same limiter. Together they
+        // get exactly its burst, however they interleave.
+        let allowed: i64 = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let mut store = store.clone();
+                    let quota = &quota;
+                    s.spawn(move || {
+                        let mut limiter = cell::RateLimiter::new(&mut store, quota);
+                        (0..50)
+                            .filter(|_| !limiter.rate_limit("foo", 1).unwrap().0)
+                            .count() as i64
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(100, allowed);
+    }
+
+    #[test]
+    fn it_expires_keys_by_its_clock() {
+        let clock = ManualClock::new(time::OffsetDateTime::UNIX_EPOCH);
+        let mut store = MemoryStore::new().with_clock(clock.clone());
+        let _ = store
+            .set_if_not_exists_with_ttl("foo", 123, time::Duration::seconds(10))
+            .unwrap();
+        assert_eq!(
+            (Some(123), time::OffsetDateTime::UNIX_EPOCH),
+            store.get_with_time("foo").unwrap()
+        );
+
+        clock.advance(time::Duration::seconds(9));
+        assert_eq!(
+            Some(Some(time::Duration::seconds(1))),
+            store.ttl("foo").unwrap()
+        );
+
+        clock.advance(time::Duration::seconds(1));
+        assert!(store.get_with_time("foo").unwrap().0.is_none());
+    }
+
+    #[test]
+    fn it_expires_keys_refunded_to_full_capacity() {
+        let quota = cell::RateQuota {
+            max_burst: 1,
+            max_rate: cell::Rate::per_second(1),
+        };
+        let clock = ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut store = MemoryStore::new().with_clock(clock.clone());
+        let mut limiter = cell::RateLimiter::new(&mut store, &quota);
+
+        let (limited, _) = limiter.rate_limit("foo", 1).unwrap();
+        assert_eq!(false, limited);
+        assert_eq!(
+            Some(Some(time::Duration::seconds(1))),
+            limiter.store.ttl("foo").unwrap()
+        );
+
+        // The refund brings the key back to where it's at full capacity, which
+        // is a TTL of zero, so it's gone right away like it would be in Redis.
+        let result = limiter.refund("foo", 1).unwrap();
+        assert_eq!(2, result.remaining);
+        assert!(limiter.store.ttl("foo").unwrap().is_none());
+        assert!(store.get_with_time("foo").unwrap().0.is_none());
+    }
+
     #[test]
     fn it_performs_get_with_time() {
         let mut store = MemoryStore::default();
@@ -267,7 +1330,7 @@ mod tests {
 
         // Now try setting a value.
         let _ = store
-            .set_if_not_exists_with_ttl("foo", 123, time::Duration::ZERO)
+            .set_if_not_exists_with_ttl("foo", 123, time::Duration::minutes(1))
             .unwrap();
 
         let res2 = store.get_with_time("foo");
@@ -276,12 +1339,25 @@ mod tests {
 
     #[test]
     fn it_performs_set_if_not_exists_with_ttl() {
-        let mut store = MemoryStore::default();
+        let clock = ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut store = MemoryStore::default().with_clock(clock);
 
-        let res1 = store.set_if_not_exists_with_ttl("foo", 123, time::Duration::ZERO);
+        let res1 =
+            store.set_if_not_exists_with_ttl("foo", 123, time::Duration::seconds(10));
         assert_eq!(true, res1.unwrap());
 
-        let res2 = store.set_if_not_exists_with_ttl("foo", 123, time::Duration::ZERO);
+        let res2 =
+            store.set_if_not_exists_with_ttl("foo", 123, time::Duration::seconds(10));
         assert_eq!(false, res2.unwrap());
+
+        // The key's left alone, but given the new TTL all the same.
+        let res3 =
+            store.set_if_not_exists_with_ttl("foo", 124, time::Duration::minutes(1));
+        assert_eq!(false, res3.unwrap());
+        assert_eq!(Some(123), store.get_with_time("foo").unwrap().0);
+        assert_eq!(
+            Some(Some(time::Duration::minutes(1))),
+            store.ttl("foo").unwrap()
+        );
     }
 }
@@ -0,0 +1,296 @@
+#[cfg(feature = "async")]
+use crate::cell::aio::AsyncStore;
+use crate::cell::clock::{Clock, from_server_time};
+use crate::cell::store::{Store, parse_legacy_tat};
+use crate::error::CellError;
+use std::cell::RefCell;
+use std::sync::LazyLock;
+
+// Swaps a TAT for a new one if it's still the old one. Like the module, a key
+// that's gone (say, because it expired since it was read) isn't swapped.
+static COMPARE_AND_SWAP: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            redis.call('SET', KEYS[1], ARGV[2], 'PX', ARGV[3])
+            return 1
+        end
+        return 0
+        ",
+    )
+});
+
+// Gets a TAT along with the server's time, so that both come from the same
+// point in time.
+static GET_WITH_TIME: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r"
+        return {redis.call('GET', KEYS[1]), redis.call('TIME')}
+        ",
+    )
+});
+
+// Sets a TAT unless there's one already, giving the key a new TTL either way
+// like `Store::set_if_not_exists_with_ttl` says.
+static SET_IF_NOT_EXISTS: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r"
+        if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2]) then
+            return 1
+        end
+        redis.call('PEXPIRE', KEYS[1], ARGV[2])
+        return 0
+        ",
+    )
+});
+
+/// `RemoteRedisStore` is a store implementation for a Redis server that
+/// doesn't have the module loaded, which it talks to over a connection from
+/// the `redis` crate.
+///
+/// Every operation that has to be atomic runs as a Lua script, and the time is
+/// taken from the server's `TIME` so that every client of the server shares
+/// its clock, unless the store's been given a clock of its own. TATs are
+/// stored as decimal strings, which the module can read too, so a key can
+/// still be used once the module is loaded.
+pub struct RemoteRedisStore {
+    // Reads need the connection mutably too, even though the Store trait only
+    // gives them a shared reference to the store.
+    conn: RefCell<redis::Connection>,
+
+    // Clock to use instead of the server's.
+    clock: Option<Box<dyn Clock>>,
+
+    verbose: bool,
+}
+
+impl RemoteRedisStore {
+    pub fn new(conn: redis::Connection) -> RemoteRedisStore {
+        RemoteRedisStore {
+            conn: RefCell::new(conn),
+            clock: None,
+            verbose: false,
+        }
+    }
+
+    pub fn new_verbose(conn: redis::Connection) -> RemoteRedisStore {
+        RemoteRedisStore {
+            verbose: true,
+            ..Self::new(conn)
+        }
+    }
+
+    /// Has the store take the time from the given clock instead of the
+    /// server.
+    pub fn with_clock(self, clock: impl Clock + 'static) -> RemoteRedisStore {
+        RemoteRedisStore {
+            clock: Some(Box::new(clock)),
+            ..self
+        }
+    }
+
+    /// Gives back the store's connection.
+    pub fn into_inner(self) -> redis::Connection {
+        self.conn.into_inner()
+    }
+}
+
+impl Store for RemoteRedisStore {
+    fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: u64,
+        new: u64,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        let swapped: i64 = COMPARE_AND_SWAP
+            .key(key)
+            .arg(old)
+            .arg(new)
+            .arg(ttl_milliseconds(ttl))
+            .invoke(self.conn.get_mut())?;
+        Ok(swapped == 1)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool, CellError> {
+        let deleted: i64 = redis::cmd("DEL").arg(key).query(self.conn.get_mut())?;
+        Ok(deleted > 0)
+    }
+
+    fn get_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<u64>, time::OffsetDateTime), CellError> {
+        if let Some(clock) = &self.clock {
+            let tat: Option<String> = redis::cmd("GET")
+                .arg(key)
+                .query(&mut *self.conn.borrow_mut())?;
+            return Ok((parse_legacy_tat(tat)?, clock.now()?));
+        }
+
+        let (tat, (seconds, microseconds)): (Option<String>, (i64, i64)) = GET_WITH_TIME
+            .key(key)
+            .invoke(&mut *self.conn.borrow_mut())?;
+
+        Ok((
+            parse_legacy_tat(tat)?,
+            from_server_time(seconds, microseconds)?,
+        ))
+    }
+
+    fn log_debug(&self, message: &str) {
+        if self.verbose {
+            println!("remote_redis_store: {message}");
+        }
+    }
+
+    fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: u64,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        let set: i64 = SET_IF_NOT_EXISTS
+            .key(key)
+            .arg(value)
+            .arg(ttl_milliseconds(ttl))
+            .invoke(self.conn.get_mut())?;
+        Ok(set == 1)
+    }
+}
+
+/// `AsyncRemoteRedisStore` is the asynchronous counterpart of
+/// `RemoteRedisStore`, which runs the same scripts over a multiplexed
+/// connection so that it never blocks the executor it's waited on from.
+#[cfg(feature = "async")]
+pub struct AsyncRemoteRedisStore {
+    // Multiplexed connections are cheap to clone, which is how reads get a
+    // mutable one from a shared reference to the store.
+    conn: redis::aio::MultiplexedConnection,
+
+    // Clock to use instead of the server's.
+    clock: Option<Box<dyn Clock + Send + Sync>>,
+
+    verbose: bool,
+}
+
+#[cfg(feature = "async")]
+impl AsyncRemoteRedisStore {
+    pub fn new(conn: redis::aio::MultiplexedConnection) -> AsyncRemoteRedisStore {
+        AsyncRemoteRedisStore {
+            conn,
+            clock: None,
+            verbose: false,
+        }
+    }
+
+    pub fn new_verbose(conn: redis::aio::MultiplexedConnection) -> AsyncRemoteRedisStore {
+        AsyncRemoteRedisStore {
+            verbose: true,
+            ..Self::new(conn)
+        }
+    }
+
+    /// Has the store take the time from the given clock instead of the
+    /// server. The clock is called from async code, so it shouldn't block.
+    pub fn with_clock(
+        self,
+        clock: impl Clock + Send + Sync + 'static,
+    ) -> AsyncRemoteRedisStore {
+        AsyncRemoteRedisStore {
+            clock: Some(Box::new(clock)),
+            ..self
+        }
+    }
+
+    /// Gives back the store's connection.
+    pub fn into_inner(self) -> redis::aio::MultiplexedConnection {
+        self.conn
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncStore for AsyncRemoteRedisStore {
+    async fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: u64,
+        new: u64,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        let swapped: i64 = COMPARE_AND_SWAP
+            .key(key)
+            .arg(old)
+            .arg(new)
+            .arg(ttl_milliseconds(ttl))
+            .invoke_async(&mut self.conn)
+            .await?;
+        Ok(swapped == 1)
+    }
+
+    async fn get_with_time(
+        &self,
+        key: &str,
+    ) -> Result<(Option<u64>, time::OffsetDateTime), CellError> {
+        let mut conn = self.conn.clone();
+
+        if let Some(clock) = &self.clock {
+            let tat: Option<String> =
+                redis::cmd("GET").arg(key).query_async(&mut conn).await?;
+            return Ok((parse_legacy_tat(tat)?, clock.now()?));
+        }
+
+        let (tat, (seconds, microseconds)): (Option<String>, (i64, i64)) =
+            GET_WITH_TIME.key(key).invoke_async(&mut conn).await?;
+
+        Ok((
+            parse_legacy_tat(tat)?,
+            from_server_time(seconds, microseconds)?,
+        ))
+    }
+
+    fn log_debug(&self, message: &str) {
+        if self.verbose {
+            println!("async_remote_redis_store: {message}");
+        }
+    }
+
+    async fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: u64,
+        ttl: time::Duration,
+    ) -> Result<bool, CellError> {
+        let set: i64 = SET_IF_NOT_EXISTS
+            .key(key)
+            .arg(value)
+            .arg(ttl_milliseconds(ttl))
+            .invoke_async(&mut self.conn)
+            .await?;
+        Ok(set == 1)
+    }
+}
+
+// Redis only takes whole, positive TTLs, so a TTL's rounded up to the next
+// millisecond, and is at least one.
+fn ttl_milliseconds(ttl: time::Duration) -> i64 {
+    let nanoseconds = ttl.whole_nanoseconds().max(1) as u128;
+    nanoseconds.div_ceil(1_000_000) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::store::remote::*;
+
+    #[test]
+    fn it_rounds_ttls_up_to_milliseconds() {
+        assert_eq!(1, ttl_milliseconds(time::Duration::ZERO));
+        assert_eq!(1, ttl_milliseconds(time::Duration::seconds(-1)));
+        assert_eq!(1, ttl_milliseconds(time::Duration::nanoseconds(1)));
+        assert_eq!(1000, ttl_milliseconds(time::Duration::seconds(1)));
+        assert_eq!(
+            1001,
+            ttl_milliseconds(time::Duration::seconds(1) + time::Duration::nanoseconds(1))
+        );
+    }
+}
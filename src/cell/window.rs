@@ -0,0 +1,641 @@
+extern crate time;
+
+use crate::cell::store;
+use crate::cell::{Algorithm, RateLimitResult, nanoseconds, retry_cas};
+use crate::error::CellError;
+
+/// `WindowQuota` is a limit of some number of requests over a rolling window.
+#[derive(Debug, Eq, PartialEq)]
+pub struct WindowQuota {
+    pub limit: i64,
+    pub window: time::Duration,
+}
+
+/// WindowEvaluation is the outcome of running a sliding window algorithm for a
+/// single key at a single point in time, along with the window that should be
+/// persisted if the request is allowed.
+struct WindowEvaluation {
+    limited: bool,
+    new: store::Window,
+    result: RateLimitResult,
+}
+
+/// `SlidingLogLimiter` keeps a log of every request made within the window and
+/// allows a new one only if the total in the log leaves room for it. It
+/// matches "N per rolling period" exactly, at the cost of storing a bucket for
+/// every request in the window.
+pub struct SlidingLogLimiter<T> {
+    pub store: T,
+    quota: WindowQuota,
+}
+
+impl<T: store::WindowStore> SlidingLogLimiter<T> {
+    pub fn new(store: T, quota: WindowQuota) -> Self {
+        SlidingLogLimiter { store, quota }
+    }
+}
+
+impl<T: store::WindowStore> Algorithm for SlidingLogLimiter<T> {
+    fn rate_limit(
+        &mut self,
+        key: &str,
+        quantity: i64,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        validate(&self.quota, quantity)?;
+        log_debug!(
+            self.store,
+            "sliding log key = {} quantity = {}",
+            key,
+            quantity
+        );
+
+        let quota = &self.quota;
+        let evaluation = commit(&mut self.store, key, |window, now| {
+            evaluate_log(quota, window, now, quantity)
+        })?;
+        Ok((evaluation.limited, evaluation.result))
+    }
+}
+
+// Weighs a request against the log as of `now`.
+fn evaluate_log(
+    quota: &WindowQuota,
+    window: Option<&store::Window>,
+    now: time::OffsetDateTime,
+    quantity: i64,
+) -> WindowEvaluation {
+    let now_ns = nanoseconds(now);
+    let window_ns = quota.window.whole_nanoseconds() as u64;
+
+    // Requests that have slid out of the window no longer count.
+    let mut new = window.cloned().unwrap_or_default();
+    new.buckets.retain(|(at, _)| at + window_ns > now_ns);
+    let used: i64 = new.buckets.iter().map(|(_, count)| count).sum();
+
+    let limited = used + quantity > quota.limit;
+    let mut retry_after = time::Duration::seconds(-1);
+    if limited && quantity <= quota.limit {
+        // Wait for just enough of the oldest requests to slide out.
+        let mut freed = 0;
+        for (at, count) in &new.buckets {
+            freed += count;
+            if used - freed + quantity <= quota.limit {
+                retry_after =
+                    time::Duration::nanoseconds((at + window_ns - now_ns) as i64);
+                break;
+            }
+        }
+    } else if !limited && quantity > 0 {
+        match new.buckets.last_mut() {
+            Some((at, count)) if *at == now_ns => *count += quantity,
+            _ => new.buckets.push((now_ns, quantity)),
+        }
+    }
+
+    let reset_after = match new.buckets.last() {
+        Some((at, _)) => time::Duration::nanoseconds((at + window_ns - now_ns) as i64),
+        None => time::Duration::ZERO,
+    };
+    let used = if limited { used } else { used + quantity };
+
+    WindowEvaluation {
+        limited,
+        new,
+        result: RateLimitResult {
+            limit: quota.limit,
+            remaining: (quota.limit - used).max(0),
+            reset_after,
+            retry_after,
+        },
+    }
+}
+
+/// `SlidingCounterLimiter` counts requests in fixed windows, and estimates how
+/// many were made in the rolling window by weighing the previous fixed
+/// window's count by how much of it the rolling window still overlaps. It
+/// only needs to store two counts per key, but assumes that the previous
+/// window's requests were spread evenly across it.
+pub struct SlidingCounterLimiter<T> {
+    pub store: T,
+    quota: WindowQuota,
+}
+
+impl<T: store::WindowStore> SlidingCounterLimiter<T> {
+    pub fn new(store: T, quota: WindowQuota) -> Self {
+        SlidingCounterLimiter { store, quota }
+    }
+}
+
+impl<T: store::WindowStore> Algorithm for SlidingCounterLimiter<T> {
+    fn rate_limit(
+        &mut self,
+        key: &str,
+        quantity: i64,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        validate(&self.quota, quantity)?;
+        log_debug!(
+            self.store,
+            "sliding counter key = {} quantity = {}",
+            key,
+            quantity
+        );
+
+        let quota = &self.quota;
+        let evaluation = commit(&mut self.store, key, |window, now| {
+            evaluate_counter(quota, window, now, quantity)
+        })?;
+        Ok((evaluation.limited, evaluation.result))
+    }
+}
+
+// Weighs a request against the fixed windows' counts as of `now`.
+fn evaluate_counter(
+    quota: &WindowQuota,
+    window: Option<&store::Window>,
+    now: time::OffsetDateTime,
+    quantity: i64,
+) -> WindowEvaluation {
+    let now_ns = nanoseconds(now);
+    let window_ns = quota.window.whole_nanoseconds() as u64;
+
+    // Fixed windows are aligned to the epoch so that every node agrees on
+    // where they start.
+    let start = now_ns - now_ns % window_ns;
+    let elapsed = now_ns - start;
+    let count_at = |at: u64| -> i64 {
+        window
+            .and_then(|w| w.buckets.iter().find(|(start, _)| *start == at))
+            .map(|(_, count)| *count)
+            .unwrap_or(0)
+    };
+    let previous = count_at(start.wrapping_sub(window_ns));
+    let current = count_at(start);
+
+    let limit = quota.limit;
+    let used = weigh(previous, window_ns - elapsed, window_ns) + current;
+    let limited = used + quantity > limit;
+
+    let mut retry_after = time::Duration::seconds(-1);
+    if limited && quantity <= limit {
+        let wait_ns = if current + quantity <= limit {
+            // Wait for enough of the previous window to slide out.
+            overlap_until(previous, limit - current - quantity, window_ns) - elapsed
+        } else {
+            // The current window is too full, so wait for it to become
+            // the previous one and then for enough of it to slide out.
+            window_ns - elapsed + overlap_until(current, limit - quantity, window_ns)
+        };
+        retry_after = time::Duration::nanoseconds(wait_ns as i64);
+    }
+
+    let current = if limited { current } else { current + quantity };
+    let used = if limited { used } else { used + quantity };
+
+    // Counts are needed until they've slid all the way out of the window.
+    let reset_after = if current > 0 {
+        2 * window_ns - elapsed
+    } else if previous > 0 {
+        window_ns - elapsed
+    } else {
+        0
+    };
+
+    let new = store::Window {
+        buckets: [(start.wrapping_sub(window_ns), previous), (start, current)]
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .collect(),
+    };
+
+    WindowEvaluation {
+        limited,
+        new,
+        result: RateLimitResult {
+            limit,
+            remaining: (limit - used).max(0),
+            reset_after: time::Duration::nanoseconds(reset_after as i64),
+            retry_after,
+        },
+    }
+}
+
+/// `Calendar` is a calendar period that a quota's windows are aligned to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Calendar {
+    Day,
+
+    /// ISO weeks, which start on Monday.
+    Week,
+
+    Month,
+}
+
+/// `CalendarQuota` is a limit of some number of requests per calendar period,
+/// like a billing quota that resets at midnight or on the 1st of the month.
+#[derive(Debug, Eq, PartialEq)]
+pub struct CalendarQuota {
+    pub limit: i64,
+    pub calendar: Calendar,
+
+    /// Offset from UTC of the timezone that windows start at midnight in.
+    pub offset: time::UtcOffset,
+}
+
+/// `CalendarLimiter` counts requests in fixed windows aligned to calendar
+/// periods. Unlike the sliding windows, capacity isn't returned gradually: it
+/// all comes back at once at the start of the next period.
+pub struct CalendarLimiter<T> {
+    pub store: T,
+    quota: CalendarQuota,
+}
+
+impl<T: store::WindowStore> CalendarLimiter<T> {
+    pub fn new(store: T, quota: CalendarQuota) -> Self {
+        CalendarLimiter { store, quota }
+    }
+}
+
+impl<T: store::WindowStore> Algorithm for CalendarLimiter<T> {
+    fn rate_limit(
+        &mut self,
+        key: &str,
+        quantity: i64,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        if self.quota.limit < 0 || quantity < 0 {
+            return Err(error!("Limits and quantities can't be negative"));
+        }
+        log_debug!(self.store, "calendar key = {} quantity = {}", key, quantity);
+
+        let quota = &self.quota;
+        let evaluation = commit(&mut self.store, key, |window, now| {
+            evaluate_calendar(quota, window, now, quantity)
+        })?;
+        Ok((evaluation.limited, evaluation.result))
+    }
+}
+
+// Weighs a request against the count of the calendar period that `now` falls
+// in.
+fn evaluate_calendar(
+    quota: &CalendarQuota,
+    window: Option<&store::Window>,
+    now: time::OffsetDateTime,
+    quantity: i64,
+) -> WindowEvaluation {
+    let (start, end) = calendar_bounds(quota.calendar, quota.offset, now);
+    let start_ns = nanoseconds(start);
+
+    // Counts from earlier periods don't carry over.
+    let used = window
+        .and_then(|w| w.buckets.iter().find(|(start, _)| *start == start_ns))
+        .map(|(_, count)| *count)
+        .unwrap_or(0);
+
+    let limited = used + quantity > quota.limit;
+    let retry_after = if limited && quantity <= quota.limit {
+        end - now
+    } else {
+        time::Duration::seconds(-1)
+    };
+    let used = if limited { used } else { used + quantity };
+
+    WindowEvaluation {
+        limited,
+        new: store::Window {
+            buckets: if used > 0 {
+                vec![(start_ns, used)]
+            } else {
+                vec![]
+            },
+        },
+        result: RateLimitResult {
+            limit: quota.limit,
+            remaining: (quota.limit - used).max(0),
+            reset_after: end - now,
+            retry_after,
+        },
+    }
+}
+
+/// Gets the start and end of the calendar period that the given time falls in,
+/// where periods start at midnight at the given offset from UTC.
+pub fn calendar_bounds(
+    calendar: Calendar,
+    offset: time::UtcOffset,
+    now: time::OffsetDateTime,
+) -> (time::OffsetDateTime, time::OffsetDateTime) {
+    let date = now.to_offset(offset).date();
+    let (start, end) = match calendar {
+        Calendar::Day => (date, date.next_day().unwrap()),
+        Calendar::Week => {
+            let monday = date
+                - time::Duration::days(date.weekday().number_days_from_monday() as i64);
+            (monday, monday + time::Duration::weeks(1))
+        }
+        Calendar::Month => {
+            let first = date.replace_day(1).unwrap();
+            let next = match first.month() {
+                time::Month::December => first
+                    .replace_year(first.year() + 1)
+                    .unwrap()
+                    .replace_month(time::Month::January),
+                month => first.replace_month(month.next()),
+            };
+            (first, next.unwrap())
+        }
+    };
+
+    let midnight = |date: time::Date| date.midnight().assume_offset(offset);
+    (midnight(start), midnight(end))
+}
+
+// Persists the window produced by `evaluate` if the request was allowed,
+// retrying if the key changed underneath us.
+fn commit<T: store::WindowStore>(
+    store: &mut T,
+    key: &str,
+    evaluate: impl Fn(Option<&store::Window>, time::OffsetDateTime) -> WindowEvaluation,
+) -> Result<WindowEvaluation, CellError> {
+    let mut i = 0;
+    loop {
+        log_debug!(store, "iteration = {}", i);
+
+        let (old, now) = store.get_window_with_time(key)?;
+        let evaluation = evaluate(old.as_ref(), now);
+        log_debug!(
+            store,
+            "limited = {} remaining = {}",
+            evaluation.limited,
+            evaluation.result.remaining
+        );
+
+        if evaluation.limited
+            || store.compare_and_swap_window_with_ttl(
+                key,
+                old.as_ref(),
+                &evaluation.new,
+                evaluation.result.reset_after,
+            )?
+        {
+            return Ok(evaluation);
+        }
+
+        retry_cas(&mut i, "update rate limit")?;
+    }
+}
+
+fn validate(quota: &WindowQuota, quantity: i64) -> Result<(), CellError> {
+    if quota.window <= time::Duration::ZERO {
+        return Err(error!("Zero windows are not supported"));
+    }
+    if quota.limit < 0 || quantity < 0 {
+        return Err(error!("Limits and quantities can't be negative"));
+    }
+    Ok(())
+}
+
+// Weighs a count from a fixed window by how much of it (`overlap_ns` out of
+// `window_ns`) the rolling window still covers. Rounds up so that the estimate
+// errs on the side of limiting.
+fn weigh(count: i64, overlap_ns: u64, window_ns: u64) -> i64 {
+    let weighted = count as i128 * overlap_ns as i128;
+    ((weighted + window_ns as i128 - 1) / window_ns as i128) as i64
+}
+
+// How far into a fixed window the one before it (holding `count`) has slid out
+// enough that it weighs no more than `allowed`.
+fn overlap_until(count: i64, allowed: i64, window_ns: u64) -> u64 {
+    let overlap_ns = (allowed as i128 * window_ns as i128 / count as i128) as u64;
+    window_ns - overlap_ns.min(window_ns)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate time;
+
+    use crate::cell::clock::ManualClock;
+    use crate::cell::store;
+    use crate::cell::window::*;
+
+    #[test]
+    fn it_limits_with_a_sliding_log() {
+        let clock = ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut memory_store =
+            store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = SlidingLogLimiter::new(
+            &mut memory_store,
+            WindowQuota {
+                limit: 3,
+                window: time::Duration::minutes(1),
+            },
+        );
+
+        let (limited, result) = limiter.rate_limit("foo", 2).unwrap();
+        assert!(!limited);
+        assert_eq!(1, result.remaining);
+        assert_eq!(time::Duration::seconds(-1), result.retry_after);
+        assert_eq!(time::Duration::minutes(1), result.reset_after);
+
+        clock.advance(time::Duration::seconds(20));
+        let (limited, result) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(0, result.remaining);
+        assert_eq!(time::Duration::minutes(1), result.reset_after);
+
+        // Full until the first requests slide out of the window a minute
+        // after they were made.
+        let (limited, result) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(limited);
+        assert_eq!(0, result.remaining);
+        assert_eq!(time::Duration::seconds(40), result.retry_after);
+        assert_eq!(time::Duration::minutes(1), result.reset_after);
+
+        clock.advance(time::Duration::seconds(40) - time::Duration::nanoseconds(1));
+        let (limited, result) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(limited);
+        assert_eq!(time::Duration::nanoseconds(1), result.retry_after);
+
+        clock.advance(time::Duration::nanoseconds(1));
+        let (limited, result) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(1, result.remaining);
+        assert_eq!(time::Duration::minutes(1), result.reset_after);
+
+        // More than the limit can never be allowed.
+        let (limited, result) = limiter.rate_limit("bar", 4).unwrap();
+        assert!(limited);
+        assert_eq!(time::Duration::seconds(-1), result.retry_after);
+    }
+
+    #[test]
+    fn it_limits_with_a_sliding_counter() {
+        // Halfway through one of the fixed windows, which are aligned to the
+        // epoch.
+        let clock = ManualClock::new(
+            time::OffsetDateTime::UNIX_EPOCH + time::Duration::seconds(1_000_005),
+        );
+        let mut memory_store =
+            store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = SlidingCounterLimiter::new(
+            &mut memory_store,
+            WindowQuota {
+                limit: 3,
+                window: time::Duration::seconds(10),
+            },
+        );
+
+        // The counts are kept until they've slid all the way out of the
+        // following window.
+        let (limited, result) = limiter.rate_limit("foo", 3).unwrap();
+        assert!(!limited);
+        assert_eq!(0, result.remaining);
+        assert_eq!(time::Duration::seconds(15), result.reset_after);
+
+        // The current window is full, so one more has to wait until it's the
+        // previous window and a third of it has slid out: 5 + 3.33... seconds,
+        // rounded up to the nanosecond that it weighs no more than 2.
+        let (limited, result) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(limited);
+        assert_eq!(
+            time::Duration::nanoseconds(8_333_333_334),
+            result.retry_after
+        );
+
+        clock.advance(time::Duration::nanoseconds(8_333_333_333));
+        let (limited, result) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(limited);
+        assert_eq!(time::Duration::nanoseconds(1), result.retry_after);
+
+        clock.advance(time::Duration::nanoseconds(1));
+        let (limited, result) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(0, result.remaining);
+        assert_eq!(
+            time::Duration::nanoseconds(16_666_666_666),
+            result.reset_after
+        );
+    }
+
+    #[test]
+    fn it_limits_per_calendar_period() {
+        let clock = ManualClock::new(time::OffsetDateTime::new_utc(
+            time::Date::from_calendar_date(2025, time::Month::December, 31).unwrap(),
+            time::Time::from_hms(21, 0, 0).unwrap(),
+        ));
+        let mut memory_store =
+            store::MemoryStore::new_verbose().with_clock(clock.clone());
+        let mut limiter = CalendarLimiter::new(
+            &mut memory_store,
+            CalendarQuota {
+                limit: 2,
+                calendar: Calendar::Day,
+                offset: time::UtcOffset::UTC,
+            },
+        );
+
+        let (limited, result) = limiter.rate_limit("foo", 2).unwrap();
+        assert!(!limited);
+        assert_eq!(0, result.remaining);
+        assert_eq!(time::Duration::hours(3), result.reset_after);
+
+        // Full until midnight, when all of the capacity comes back at once.
+        let (limited, result) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(limited);
+        assert_eq!(time::Duration::hours(3), result.retry_after);
+        assert_eq!(time::Duration::hours(3), result.reset_after);
+
+        clock.advance(time::Duration::hours(3) - time::Duration::nanoseconds(1));
+        let (limited, result) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(limited);
+        assert_eq!(time::Duration::nanoseconds(1), result.retry_after);
+
+        clock.advance(time::Duration::nanoseconds(1));
+        let (limited, result) = limiter.rate_limit("foo", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(1, result.remaining);
+        assert_eq!(time::Duration::days(1), result.reset_after);
+    }
+
+    #[test]
+    fn it_finds_calendar_bounds() {
+        let offset = time::UtcOffset::from_hms(-5, 0, 0).unwrap();
+        let at = |date: time::Date, hour: u8| {
+            date.with_hms(hour, 0, 0).unwrap().assume_offset(offset)
+        };
+        let date = |year: i32, month: time::Month, day: u8| {
+            time::Date::from_calendar_date(year, month, day).unwrap()
+        };
+
+        // A Wednesday afternoon at UTC-5, which is already Thursday in UTC.
+        let now = at(date(2025, time::Month::December, 31), 21);
+        assert_eq!(
+            (
+                at(date(2025, time::Month::December, 31), 0),
+                at(date(2026, time::Month::January, 1), 0)
+            ),
+            calendar_bounds(Calendar::Day, offset, now)
+        );
+        assert_eq!(
+            (
+                at(date(2025, time::Month::December, 29), 0),
+                at(date(2026, time::Month::January, 5), 0)
+            ),
+            calendar_bounds(Calendar::Week, offset, now)
+        );
+        assert_eq!(
+            (
+                at(date(2025, time::Month::December, 1), 0),
+                at(date(2026, time::Month::January, 1), 0)
+            ),
+            calendar_bounds(Calendar::Month, offset, now)
+        );
+
+        // The same instant in UTC is in the next day, week, and month.
+        assert_eq!(
+            (
+                time::OffsetDateTime::new_utc(
+                    date(2026, time::Month::January, 1),
+                    time::Time::MIDNIGHT
+                ),
+                time::OffsetDateTime::new_utc(
+                    date(2026, time::Month::February, 1),
+                    time::Time::MIDNIGHT
+                )
+            ),
+            calendar_bounds(Calendar::Month, time::UtcOffset::UTC, now)
+        );
+    }
+
+    #[test]
+    fn it_weighs_the_previous_window() {
+        let quota = WindowQuota {
+            limit: 10,
+            window: time::Duration::seconds(10),
+        };
+
+        // Three quarters of the way through a window, a quarter of the
+        // previous window's 8 requests still count.
+        let start = time::OffsetDateTime::UNIX_EPOCH + time::Duration::seconds(1_000_000);
+        let window = store::Window {
+            buckets: vec![
+                (nanoseconds(start) - 10_000_000_000, 8),
+                (nanoseconds(start), 3),
+            ],
+        };
+        let now = start + time::Duration::milliseconds(7_500);
+
+        let evaluation = evaluate_counter(&quota, Some(&window), now, 5);
+        assert!(!evaluation.limited);
+        assert_eq!(0, evaluation.result.remaining);
+        assert_eq!(8, evaluation.new.buckets[1].1);
+
+        // One more would have to wait until only one of the previous
+        // window's requests counts, at 8.75 seconds in.
+        let evaluation = evaluate_counter(&quota, Some(&window), now, 6);
+        assert!(evaluation.limited);
+        assert_eq!(
+            time::Duration::milliseconds(1_250),
+            evaluation.result.retry_after
+        );
+    }
+}
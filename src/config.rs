@@ -0,0 +1,465 @@
+// Settings that tune how the module behaves.
+//
+// Each setting can be given as a `<name> <value>` pair of arguments when the
+// module is loaded. On servers with the module config API (Redis 7 and later,
+// and Valkey), they're also registered as configs so that they can be put in
+// the server's config file and read or changed at runtime with CONFIG GET and
+// CONFIG SET. The server names a module's configs after it, so they're
+// `redis-cell.<name>` there.
+
+use crate::Units;
+use crate::cell;
+use crate::error::CellError;
+use crate::redis;
+use crate::redis::raw;
+use libc::{c_int, c_longlong, c_void};
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicPtr, Ordering};
+
+const KEY_PREFIX: &str = "key-prefix";
+const UNITS: &str = "units";
+const MAX_CAS_ATTEMPTS: &str = "max-cas-attempts";
+const DEFAULT_QUANTITY: &str = "default-quantity";
+const DEBUG: &str = "debug";
+const THROTTLE_EVENTS: &str = "throttle-events";
+const THROTTLE_CHANNEL: &str = "throttle-channel";
+
+// Upper bound on max-cas-attempts. Retrying more than this many times means
+// that something's wrong, and it's better to return an error.
+const MAX_MAX_CAS_ATTEMPTS: i64 = 1_000;
+
+// Names of the values of the units setting, in the order of `UNITS_VALUES`.
+const UNITS_NAMES: [&std::ffi::CStr; 3] = [c"s", c"ms", c"us"];
+const UNITS_VALUES: [Units; 3] =
+    [Units::Seconds, Units::Milliseconds, Units::Microseconds];
+
+static KEY_PREFIX_VALUE: Mutex<String> = Mutex::new(String::new());
+static UNITS_VALUE: Mutex<Units> = Mutex::new(Units::Seconds);
+static DEFAULT_QUANTITY_VALUE: AtomicI64 = AtomicI64::new(1);
+static DEBUG_VALUE: AtomicBool = AtomicBool::new(cfg!(debug_assertions));
+static THROTTLE_EVENTS_VALUE: AtomicBool = AtomicBool::new(false);
+static THROTTLE_CHANNEL_VALUE: Mutex<String> = Mutex::new(String::new());
+
+// String settings as Redis strings for CONFIG GET, which doesn't take
+// ownership of them. Each is created when it's first asked for and freed when
+// the setting changes.
+static KEY_PREFIX_STRING: AtomicPtr<raw::RedisModuleString> =
+    AtomicPtr::new(ptr::null_mut());
+static THROTTLE_CHANNEL_STRING: AtomicPtr<raw::RedisModuleString> =
+    AtomicPtr::new(ptr::null_mut());
+
+/// Whether debug messages are logged. On by default in debug builds only.
+pub fn debug() -> bool {
+    DEBUG_VALUE.load(Ordering::Relaxed)
+}
+
+/// Gets the quantity that's used when a command isn't given one.
+pub fn default_quantity() -> i64 {
+    DEFAULT_QUANTITY_VALUE.load(Ordering::Relaxed)
+}
+
+/// Prepends the configured key prefix (which is empty by default) to the key
+/// of a limiter as given to a command.
+///
+/// A cluster routes commands by the key as given, so there a prefix that
+/// would move the key to another hash slot is an error. Keys have to carry
+/// their own hash tag for a prefix to be used with them.
+pub fn prefixed(key: &str) -> Result<String, CellError> {
+    let prefix = KEY_PREFIX_VALUE.lock().unwrap();
+    let prefixed = format!("{prefix}{key}");
+    if !prefix.is_empty()
+        && raw::get_context_flags(ptr::null_mut()) & raw::REDISMODULE_CTX_FLAGS_CLUSTER
+            != 0
+        && hash_slot(prefixed.as_bytes()) != hash_slot(key.as_bytes())
+    {
+        return Err(error!(
+            "Key prefix would move {} to another hash slot; give it a hash tag \
+             like {{{}}}",
+            key, key
+        ));
+    }
+    Ok(prefixed)
+}
+
+// Gets the cluster hash slot of a key. Only the part between the first pair of
+// braces is hashed if there's anything there, so that related keys can be
+// kept in the same slot.
+fn hash_slot(key: &[u8]) -> u16 {
+    let tag = key.iter().position(|&b| b == b'{').and_then(|start| {
+        let rest = &key[start + 1..];
+        rest.iter()
+            .position(|&b| b == b'}')
+            .filter(|&end| end > 0)
+            .map(|end| &rest[..end])
+    });
+    crc16(tag.unwrap_or(key)) % 16384
+}
+
+// The CRC16 (XMODEM) that cluster hash slots are taken from.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Gets the channel that throttled requests are published to, or None if
+/// they aren't.
+pub fn throttle_channel() -> Option<String> {
+    let channel = THROTTLE_CHANNEL_VALUE.lock().unwrap();
+    (!channel.is_empty()).then(|| channel.clone())
+}
+
+/// Whether a keyspace event is emitted on the key of a throttled request.
+pub fn throttle_events() -> bool {
+    THROTTLE_EVENTS_VALUE.load(Ordering::Relaxed)
+}
+
+/// Gets the units that times are replied with when a command isn't given any.
+pub fn units() -> Units {
+    *UNITS_VALUE.lock().unwrap()
+}
+
+/// Sets the named setting from a string, like one from the module's load
+/// arguments.
+pub fn set(name: &str, value: &str) -> Result<(), CellError> {
+    let name = name.to_ascii_lowercase();
+    let name = name.as_str();
+    match name {
+        KEY_PREFIX | THROTTLE_CHANNEL => {
+            let (setting, cached) = string_setting(name).unwrap();
+            set_string(setting, cached, value.to_string());
+        }
+        UNITS => *UNITS_VALUE.lock().unwrap() = Units::parse(value)?,
+        MAX_CAS_ATTEMPTS => {
+            let attempts = parse_i64(name, value)?;
+            if !(1..=MAX_MAX_CAS_ATTEMPTS).contains(&attempts) {
+                return Err(error!(
+                    "{} must be between 1 and {}: {}",
+                    name, MAX_MAX_CAS_ATTEMPTS, value
+                ));
+            }
+            cell::set_max_cas_attempts(attempts);
+        }
+        DEFAULT_QUANTITY => {
+            let quantity = parse_i64(name, value)?;
+            if quantity < 0 {
+                return Err(error!("{} can't be negative: {}", name, value));
+            }
+            DEFAULT_QUANTITY_VALUE.store(quantity, Ordering::Relaxed);
+        }
+        DEBUG | THROTTLE_EVENTS => {
+            let enabled = match value.to_ascii_lowercase().as_str() {
+                "yes" => true,
+                "no" => false,
+                _ => return Err(error!("{} must be yes or no: {}", name, value)),
+            };
+            bool_setting(name)
+                .unwrap()
+                .store(enabled, Ordering::Relaxed);
+        }
+        _ => return Err(error!("Unknown config: {}", name)),
+    }
+    Ok(())
+}
+
+/// Applies settings given as `<name> <value>` pairs of module load arguments.
+pub fn set_from_args(args: &[String]) -> Result<(), CellError> {
+    if !args.len().is_multiple_of(2) {
+        return Err(error!(
+            "Module arguments must be pairs of <name> <value>: {}",
+            args.join(" ")
+        ));
+    }
+    for pair in args.chunks(2) {
+        set(&pair[0], &pair[1])?;
+    }
+    Ok(())
+}
+
+/// Registers every setting with the module config API and loads their values
+/// from the server's config. Servers without the API skip this, leaving the
+/// settings at their defaults (or as given in the load arguments).
+pub fn register(ctx: *mut raw::RedisModuleCtx) -> raw::Status {
+    if !raw::has_config_api() {
+        return raw::Status::Ok;
+    }
+
+    let unit_names = UNITS_NAMES.map(|name| name.as_ptr() as *const u8);
+    let unit_values: [c_int; 3] = [0, 1, 2];
+    let registered = [
+        raw::register_string_config(
+            ctx,
+            format!("{KEY_PREFIX}\0").as_ptr(),
+            c"".as_ptr() as *const u8,
+            raw::REDISMODULE_CONFIG_DEFAULT,
+            get_string,
+            set_string_config,
+        ),
+        raw::register_enum_config(
+            ctx,
+            format!("{UNITS}\0").as_ptr(),
+            0,
+            raw::REDISMODULE_CONFIG_DEFAULT,
+            unit_names.as_ptr(),
+            unit_values.as_ptr(),
+            unit_values.len() as c_int,
+            get_units,
+            set_units,
+        ),
+        raw::register_numeric_config(
+            ctx,
+            format!("{MAX_CAS_ATTEMPTS}\0").as_ptr(),
+            cell::DEFAULT_MAX_CAS_ATTEMPTS as c_longlong,
+            raw::REDISMODULE_CONFIG_DEFAULT,
+            1,
+            MAX_MAX_CAS_ATTEMPTS as c_longlong,
+            get_numeric,
+            set_numeric,
+        ),
+        raw::register_numeric_config(
+            ctx,
+            format!("{DEFAULT_QUANTITY}\0").as_ptr(),
+            1,
+            raw::REDISMODULE_CONFIG_DEFAULT,
+            0,
+            c_longlong::MAX,
+            get_numeric,
+            set_numeric,
+        ),
+        raw::register_bool_config(
+            ctx,
+            format!("{DEBUG}\0").as_ptr(),
+            cfg!(debug_assertions) as c_int,
+            raw::REDISMODULE_CONFIG_DEFAULT,
+            get_bool,
+            set_bool,
+        ),
+        raw::register_bool_config(
+            ctx,
+            format!("{THROTTLE_EVENTS}\0").as_ptr(),
+            0,
+            raw::REDISMODULE_CONFIG_DEFAULT,
+            get_bool,
+            set_bool,
+        ),
+        raw::register_string_config(
+            ctx,
+            format!("{THROTTLE_CHANNEL}\0").as_ptr(),
+            c"".as_ptr() as *const u8,
+            raw::REDISMODULE_CONFIG_DEFAULT,
+            get_string,
+            set_string_config,
+        ),
+    ];
+    if registered.contains(&raw::Status::Err) {
+        return raw::Status::Err;
+    }
+
+    raw::load_configs(ctx)
+}
+
+fn parse_i64(name: &str, value: &str) -> Result<i64, CellError> {
+    value
+        .parse::<i64>()
+        .map_err(|_| error!("{} must be an integer: {}", name, value))
+}
+
+// Gets the value of a boolean setting by name.
+fn bool_setting(name: &str) -> Option<&'static AtomicBool> {
+    match name {
+        DEBUG => Some(&DEBUG_VALUE),
+        THROTTLE_EVENTS => Some(&THROTTLE_EVENTS_VALUE),
+        _ => None,
+    }
+}
+
+// Gets the value of a string setting by name, along with its cached Redis
+// string.
+fn string_setting(
+    name: &str,
+) -> Option<(
+    &'static Mutex<String>,
+    &'static AtomicPtr<raw::RedisModuleString>,
+)> {
+    match name {
+        KEY_PREFIX => Some((&KEY_PREFIX_VALUE, &KEY_PREFIX_STRING)),
+        THROTTLE_CHANNEL => Some((&THROTTLE_CHANNEL_VALUE, &THROTTLE_CHANNEL_STRING)),
+        _ => None,
+    }
+}
+
+fn set_string(
+    setting: &Mutex<String>,
+    cached: &AtomicPtr<raw::RedisModuleString>,
+    value: String,
+) {
+    *setting.lock().unwrap() = value;
+
+    let old = cached.swap(ptr::null_mut(), Ordering::Relaxed);
+    if !old.is_null() {
+        raw::free_string(ptr::null_mut(), old);
+    }
+}
+
+// Gets the name of a config that the server has passed to a callback, which
+// it gives without the module's name in front.
+fn config_name(name: *const u8) -> String {
+    unsafe { std::ffi::CStr::from_ptr(name as *const libc::c_char) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+// String configs share callbacks, which tell them apart by name.
+extern "C" fn get_string(
+    name: *const u8,
+    _privdata: *mut c_void,
+) -> *mut raw::RedisModuleString {
+    let Some((setting, cached)) = string_setting(&config_name(name)) else {
+        return ptr::null_mut();
+    };
+    let string = cached.load(Ordering::Relaxed);
+    if !string.is_null() {
+        return string;
+    }
+
+    let value = setting.lock().unwrap().clone();
+    let string =
+        raw::create_string(ptr::null_mut(), format!("{value}\0").as_ptr(), value.len());
+    cached.store(string, Ordering::Relaxed);
+    string
+}
+
+extern "C" fn set_string_config(
+    name: *const u8,
+    val: *mut raw::RedisModuleString,
+    _privdata: *mut c_void,
+    _err: *mut *mut raw::RedisModuleString,
+) -> raw::Status {
+    let Some((setting, cached)) = string_setting(&config_name(name)) else {
+        return raw::Status::Err;
+    };
+    match redis::manifest_redis_string(val) {
+        Ok(value) => {
+            set_string(setting, cached, value);
+            raw::Status::Ok
+        }
+        Err(_) => raw::Status::Err,
+    }
+}
+
+extern "C" fn get_units(_name: *const u8, _privdata: *mut c_void) -> c_int {
+    let units = units();
+    UNITS_VALUES
+        .iter()
+        .position(|value| *value == units)
+        .unwrap_or(0) as c_int
+}
+
+extern "C" fn set_units(
+    _name: *const u8,
+    val: c_int,
+    _privdata: *mut c_void,
+    _err: *mut *mut raw::RedisModuleString,
+) -> raw::Status {
+    match UNITS_VALUES.get(val as usize) {
+        Some(units) => {
+            *UNITS_VALUE.lock().unwrap() = *units;
+            raw::Status::Ok
+        }
+        None => raw::Status::Err,
+    }
+}
+
+// Numeric configs share callbacks, which tell them apart by name. The server
+// has already checked that the value is within the config's bounds.
+extern "C" fn get_numeric(name: *const u8, _privdata: *mut c_void) -> c_longlong {
+    match config_name(name).as_str() {
+        MAX_CAS_ATTEMPTS => cell::max_cas_attempts() as c_longlong,
+        DEFAULT_QUANTITY => default_quantity() as c_longlong,
+        _ => 0,
+    }
+}
+
+extern "C" fn set_numeric(
+    name: *const u8,
+    val: c_longlong,
+    _privdata: *mut c_void,
+    _err: *mut *mut raw::RedisModuleString,
+) -> raw::Status {
+    match config_name(name).as_str() {
+        MAX_CAS_ATTEMPTS => cell::set_max_cas_attempts(val),
+        DEFAULT_QUANTITY => DEFAULT_QUANTITY_VALUE.store(val, Ordering::Relaxed),
+        _ => return raw::Status::Err,
+    }
+    raw::Status::Ok
+}
+
+// Boolean configs share callbacks too.
+extern "C" fn get_bool(name: *const u8, _privdata: *mut c_void) -> c_int {
+    match bool_setting(&config_name(name)) {
+        Some(setting) => setting.load(Ordering::Relaxed) as c_int,
+        None => 0,
+    }
+}
+
+extern "C" fn set_bool(
+    name: *const u8,
+    val: c_int,
+    _privdata: *mut c_void,
+    _err: *mut *mut raw::RedisModuleString,
+) -> raw::Status {
+    match bool_setting(&config_name(name)) {
+        Some(setting) => {
+            setting.store(val != 0, Ordering::Relaxed);
+            raw::Status::Ok
+        }
+        None => raw::Status::Err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::*;
+
+    #[test]
+    fn it_rejects_invalid_settings() {
+        for (name, value) in [
+            ("units", "m"),
+            ("max-cas-attempts", "0"),
+            ("max-cas-attempts", "1001"),
+            ("default-quantity", "-1"),
+            ("default-quantity", "one"),
+            ("debug", "maybe"),
+            ("throttle-events", "1"),
+            ("unknown", "1"),
+        ] {
+            assert!(
+                set(name, value).is_err(),
+                "{name} {value} should be invalid"
+            );
+        }
+
+        assert!(set_from_args(&["units".to_string()]).is_err());
+    }
+
+    #[test]
+    fn it_calculates_hash_slots() {
+        assert_eq!(0x31c3, crc16(b"123456789"));
+        assert_eq!(11058, hash_slot(b"somekey"));
+        assert_eq!(2515, hash_slot(b"foo{hash_tag}"));
+        assert_eq!(hash_slot(b"hash_tag"), hash_slot(b"limits:{hash_tag}"));
+
+        // Empty hash tags don't count, so the whole key is hashed.
+        assert_eq!(crc16(b"foo{}bar") % 16384, hash_slot(b"foo{}bar"));
+    }
+}
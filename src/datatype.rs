@@ -0,0 +1,343 @@
+// Registers the native Redis data types that limiter state is stored as.
+//
+// Storing a limiter's TAT as a module type rather than a decimal string means
+// that it doesn't need to be parsed back out on every call, and that commands
+// like `TYPE`, `MEMORY USAGE`, and `DEBUG DIGEST` give sensible answers. The
+// leases held on a concurrency limiter and the buckets of a sliding window
+// limiter each get a type of their own.
+
+use crate::cell::store::{Leases, Window};
+use crate::policy;
+use crate::redis;
+use crate::redis::raw;
+use libc::{c_int, c_void, size_t};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+// Names of the data types as shown by `TYPE`. Redis requires that these are
+// exactly 9 characters long.
+const TAT_TYPE_NAME: &str = "cell-gcra";
+const LEASES_TYPE_NAME: &str = "cell-sema";
+const WINDOW_TYPE_NAME: &str = "cell-wndw";
+
+// Version of the RDB encoding. Bump this whenever the layout saved by either
+// type's `rdb_save` (or the policies saved as auxiliary data) changes, and
+// teach `rdb_load` to read the older versions.
+const ENCODING_VERSION: c_int = 0;
+
+/// Name of the internal command emitted during an AOF rewrite to recreate a
+/// TAT key's value.
+pub const SET_TAT_COMMAND: &str = "cl.settat";
+
+/// Name of the internal command emitted during an AOF rewrite to recreate a
+/// leases key's value.
+pub const SET_LEASES_COMMAND: &str = "cl.setleases";
+
+/// Name of the internal command emitted during an AOF rewrite to recreate a
+/// window key's value.
+pub const SET_WINDOW_COMMAND: &str = "cl.setwindow";
+
+// Handles to the types given back by Redis when they're registered, which are
+// needed to read and write keys of the types.
+static TAT_TYPE: AtomicPtr<raw::RedisModuleType> = AtomicPtr::new(ptr::null_mut());
+static LEASES_TYPE: AtomicPtr<raw::RedisModuleType> = AtomicPtr::new(ptr::null_mut());
+static WINDOW_TYPE: AtomicPtr<raw::RedisModuleType> = AtomicPtr::new(ptr::null_mut());
+
+/// `TatValue` is the state of a single limiter: its theoretical arrival time
+/// (TAT) along with the quota that it was last updated with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TatValue {
+    /// Theoretical arrival time in nanoseconds since the Unix epoch.
+    pub tat: u64,
+
+    pub max_burst: i64,
+
+    /// Time between individual requests at the quota's rate in nanoseconds.
+    pub emission_interval: i64,
+}
+
+/// `WindowValue` is the state of a sliding window limiter along with when its
+/// key expires, which can't be worked out from the window itself.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WindowValue {
+    /// Unix time in milliseconds that the key expires at.
+    pub expires_at_ms: i64,
+
+    pub window: Window,
+}
+
+/// Registers the data types with Redis. Must be called from `OnLoad`.
+pub fn create(ctx: *mut raw::RedisModuleCtx) -> raw::Status {
+    let tat_methods = raw::RedisModuleTypeMethods {
+        version: raw::REDISMODULE_TYPE_METHOD_VERSION,
+        rdb_load: Some(tat_rdb_load),
+        rdb_save: Some(tat_rdb_save),
+        aof_rewrite: Some(tat_aof_rewrite),
+        mem_usage: Some(tat_mem_usage),
+        digest: Some(tat_digest),
+        free: Some(tat_free),
+
+        // Policies aren't tied to any key, so they're saved along with the
+        // type instead.
+        aux_load: Some(policy::aux_load),
+        aux_save: Some(policy::aux_save),
+        aux_save_triggers: raw::REDISMODULE_AUX_BEFORE_RDB,
+    };
+    if register(ctx, TAT_TYPE_NAME, &tat_methods, &TAT_TYPE) == raw::Status::Err {
+        return raw::Status::Err;
+    }
+
+    let leases_methods = raw::RedisModuleTypeMethods {
+        version: raw::REDISMODULE_TYPE_METHOD_VERSION,
+        rdb_load: Some(leases_rdb_load),
+        rdb_save: Some(leases_rdb_save),
+        aof_rewrite: Some(leases_aof_rewrite),
+        mem_usage: Some(leases_mem_usage),
+        digest: Some(leases_digest),
+        free: Some(leases_free),
+        aux_load: None,
+        aux_save: None,
+        aux_save_triggers: 0,
+    };
+    if register(ctx, LEASES_TYPE_NAME, &leases_methods, &LEASES_TYPE) == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    let window_methods = raw::RedisModuleTypeMethods {
+        version: raw::REDISMODULE_TYPE_METHOD_VERSION,
+        rdb_load: Some(window_rdb_load),
+        rdb_save: Some(window_rdb_save),
+        aof_rewrite: Some(window_aof_rewrite),
+        mem_usage: Some(window_mem_usage),
+        digest: Some(window_digest),
+        free: Some(window_free),
+        aux_load: None,
+        aux_save: None,
+        aux_save_triggers: 0,
+    };
+    register(ctx, WINDOW_TYPE_NAME, &window_methods, &WINDOW_TYPE)
+}
+
+/// Gets the TAT type registered by `create`.
+pub fn tat_type() -> *mut raw::RedisModuleType {
+    TAT_TYPE.load(Ordering::Relaxed)
+}
+
+/// Gets the leases type registered by `create`.
+pub fn leases_type() -> *mut raw::RedisModuleType {
+    LEASES_TYPE.load(Ordering::Relaxed)
+}
+
+/// Gets the window type registered by `create`.
+pub fn window_type() -> *mut raw::RedisModuleType {
+    WINDOW_TYPE.load(Ordering::Relaxed)
+}
+
+/// Gets the Unix time in milliseconds that a leases key should expire at
+/// given when the last of its leases expires in nanoseconds, rounding up so
+/// that the key never goes before the lease does.
+pub fn leases_expire_at_ms(expires_at_ns: u64) -> i64 {
+    expires_at_ns.div_ceil(1_000_000) as i64
+}
+
+fn register(
+    ctx: *mut raw::RedisModuleCtx,
+    name: &str,
+    methods: &raw::RedisModuleTypeMethods,
+    handle: &AtomicPtr<raw::RedisModuleType>,
+) -> raw::Status {
+    let ty = raw::create_data_type(
+        ctx,
+        format!("{name}\0").as_ptr(),
+        ENCODING_VERSION,
+        methods,
+    );
+    if ty.is_null() {
+        return raw::Status::Err;
+    }
+
+    handle.store(ty, Ordering::Relaxed);
+    raw::Status::Ok
+}
+
+extern "C" fn tat_rdb_load(rdb: *mut raw::RedisModuleIO, encver: c_int) -> *mut c_void {
+    if encver != ENCODING_VERSION {
+        // Returning null tells Redis that the value couldn't be loaded.
+        return ptr::null_mut();
+    }
+
+    let io = redis::RedisIO::new(rdb);
+    let value = TatValue {
+        tat: io.load_unsigned(),
+        max_burst: io.load_signed(),
+        emission_interval: io.load_signed(),
+    };
+    Box::into_raw(Box::new(value)) as *mut c_void
+}
+
+extern "C" fn tat_rdb_save(rdb: *mut raw::RedisModuleIO, value: *mut c_void) {
+    let value = unsafe { &*(value as *const TatValue) };
+    let io = redis::RedisIO::new(rdb);
+    io.save_unsigned(value.tat);
+    io.save_signed(value.max_burst);
+    io.save_signed(value.emission_interval);
+}
+
+extern "C" fn tat_aof_rewrite(
+    aof: *mut raw::RedisModuleIO,
+    key: *mut raw::RedisModuleString,
+    value: *mut c_void,
+) {
+    let value = unsafe { &*(value as *const TatValue) };
+    redis::RedisIO::new(aof).emit_aof(
+        SET_TAT_COMMAND,
+        key,
+        &[value.tat as i64, value.max_burst, value.emission_interval],
+    );
+}
+
+extern "C" fn tat_mem_usage(_value: *const c_void) -> size_t {
+    std::mem::size_of::<TatValue>()
+}
+
+extern "C" fn tat_digest(md: *mut raw::RedisModuleDigest, value: *mut c_void) {
+    let value = unsafe { &*(value as *const TatValue) };
+    let digest = redis::RedisDigest::new(md);
+    digest.add_integer(value.tat as i64);
+    digest.add_integer(value.max_burst);
+    digest.add_integer(value.emission_interval);
+    digest.end_sequence();
+}
+
+extern "C" fn tat_free(value: *mut c_void) {
+    drop(unsafe { Box::from_raw(value as *mut TatValue) });
+}
+
+extern "C" fn leases_rdb_load(
+    rdb: *mut raw::RedisModuleIO,
+    encver: c_int,
+) -> *mut c_void {
+    if encver != ENCODING_VERSION {
+        return ptr::null_mut();
+    }
+
+    let io = redis::RedisIO::new(rdb);
+    let last_id = io.load_unsigned();
+    let leases = (0..io.load_unsigned())
+        .map(|_| (io.load_unsigned(), io.load_unsigned()))
+        .collect();
+    Box::into_raw(Box::new(Leases { last_id, leases })) as *mut c_void
+}
+
+extern "C" fn leases_rdb_save(rdb: *mut raw::RedisModuleIO, value: *mut c_void) {
+    let value = unsafe { &*(value as *const Leases) };
+    let io = redis::RedisIO::new(rdb);
+    io.save_unsigned(value.last_id);
+    io.save_unsigned(value.leases.len() as u64);
+    for (id, expires_at) in &value.leases {
+        io.save_unsigned(*id);
+        io.save_unsigned(*expires_at);
+    }
+}
+
+extern "C" fn leases_aof_rewrite(
+    aof: *mut raw::RedisModuleIO,
+    key: *mut raw::RedisModuleString,
+    value: *mut c_void,
+) {
+    let value = unsafe { &*(value as *const Leases) };
+    let mut args = vec![value.last_id as i64];
+    for (id, expires_at) in &value.leases {
+        args.push(*id as i64);
+        args.push(*expires_at as i64);
+    }
+    redis::RedisIO::new(aof).emit_aof(SET_LEASES_COMMAND, key, &args);
+}
+
+extern "C" fn leases_mem_usage(value: *const c_void) -> size_t {
+    let value = unsafe { &*(value as *const Leases) };
+    std::mem::size_of::<Leases>()
+        + value.leases.capacity() * std::mem::size_of::<(u64, u64)>()
+}
+
+extern "C" fn leases_digest(md: *mut raw::RedisModuleDigest, value: *mut c_void) {
+    let value = unsafe { &*(value as *const Leases) };
+    let digest = redis::RedisDigest::new(md);
+    digest.add_integer(value.last_id as i64);
+    for (id, expires_at) in &value.leases {
+        digest.add_integer(*id as i64);
+        digest.add_integer(*expires_at as i64);
+    }
+    digest.end_sequence();
+}
+
+extern "C" fn leases_free(value: *mut c_void) {
+    drop(unsafe { Box::from_raw(value as *mut Leases) });
+}
+
+extern "C" fn window_rdb_load(
+    rdb: *mut raw::RedisModuleIO,
+    encver: c_int,
+) -> *mut c_void {
+    if encver != ENCODING_VERSION {
+        return ptr::null_mut();
+    }
+
+    let io = redis::RedisIO::new(rdb);
+    let expires_at_ms = io.load_signed();
+    let buckets = (0..io.load_unsigned())
+        .map(|_| (io.load_unsigned(), io.load_signed()))
+        .collect();
+    let value = WindowValue {
+        expires_at_ms,
+        window: Window { buckets },
+    };
+    Box::into_raw(Box::new(value)) as *mut c_void
+}
+
+extern "C" fn window_rdb_save(rdb: *mut raw::RedisModuleIO, value: *mut c_void) {
+    let value = unsafe { &*(value as *const WindowValue) };
+    let io = redis::RedisIO::new(rdb);
+    io.save_signed(value.expires_at_ms);
+    io.save_unsigned(value.window.buckets.len() as u64);
+    for (start, count) in &value.window.buckets {
+        io.save_unsigned(*start);
+        io.save_signed(*count);
+    }
+}
+
+extern "C" fn window_aof_rewrite(
+    aof: *mut raw::RedisModuleIO,
+    key: *mut raw::RedisModuleString,
+    value: *mut c_void,
+) {
+    let value = unsafe { &*(value as *const WindowValue) };
+    let mut args = vec![value.expires_at_ms];
+    for (start, count) in &value.window.buckets {
+        args.push(*start as i64);
+        args.push(*count);
+    }
+    redis::RedisIO::new(aof).emit_aof(SET_WINDOW_COMMAND, key, &args);
+}
+
+extern "C" fn window_mem_usage(value: *const c_void) -> size_t {
+    let value = unsafe { &*(value as *const WindowValue) };
+    std::mem::size_of::<WindowValue>()
+        + value.window.buckets.capacity() * std::mem::size_of::<(u64, i64)>()
+}
+
+extern "C" fn window_digest(md: *mut raw::RedisModuleDigest, value: *mut c_void) {
+    let value = unsafe { &*(value as *const WindowValue) };
+    let digest = redis::RedisDigest::new(md);
+    digest.add_integer(value.expires_at_ms);
+    for (start, count) in &value.window.buckets {
+        digest.add_integer(*start as i64);
+        digest.add_integer(*count);
+    }
+    digest.end_sequence();
+}
+
+extern "C" fn window_free(value: *mut c_void) {
+    drop(unsafe { Box::from_raw(value as *mut WindowValue) });
+}
@@ -6,6 +6,8 @@ pub enum CellError {
     Generic(GenericError),
     FromUtf8(std::string::FromUtf8Error),
     ParseInt(std::num::ParseIntError),
+    #[cfg(feature = "remote-store")]
+    Redis(::redis::RedisError),
 }
 
 impl CellError {
@@ -26,6 +28,13 @@ impl From<std::num::ParseIntError> for CellError {
     }
 }
 
+#[cfg(feature = "remote-store")]
+impl From<::redis::RedisError> for CellError {
+    fn from(err: ::redis::RedisError) -> CellError {
+        CellError::Redis(err)
+    }
+}
+
 impl fmt::Display for CellError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -34,6 +43,8 @@ impl fmt::Display for CellError {
             CellError::Generic(ref err) => write!(f, "{err}"),
             CellError::FromUtf8(ref err) => write!(f, "{err}"),
             CellError::ParseInt(ref err) => write!(f, "{err}"),
+            #[cfg(feature = "remote-store")]
+            CellError::Redis(ref err) => write!(f, "{err}"),
         }
     }
 }
@@ -48,6 +59,8 @@ impl error::Error for CellError {
             CellError::Generic(ref err) => Some(err),
             CellError::FromUtf8(ref err) => Some(err),
             CellError::ParseInt(ref err) => Some(err),
+            #[cfg(feature = "remote-store")]
+            CellError::Redis(ref err) => Some(err),
         }
     }
 }
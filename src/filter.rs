@@ -0,0 +1,312 @@
+// Throttles clients' own commands before the server runs them.
+//
+// Rules limit how often the commands matching a pattern can be called by each
+// client, ACL user, or source address. A command filter checks every command
+// against the rules with a GCRA limiter over an in-memory store, and rewrites
+// one that's over a limit into CL.FILTER.REJECT, which replies with a throttle
+// error instead of running it. Rules and the state of their limiters belong
+// to each server, so they're neither replicated nor persisted.
+
+use crate::Units;
+use crate::cell;
+use crate::cell::clock::SystemClock;
+use crate::cell::store;
+use crate::error::CellError;
+use crate::policy;
+use crate::redis;
+use crate::redis::raw;
+use crate::stats;
+use std::collections::BTreeMap;
+use std::ptr;
+use std::sync::{LazyLock, Mutex};
+
+/// Name of the command that a throttled command is rewritten into.
+pub const REJECT_COMMAND: &str = "cl.filter.reject";
+
+static RULES: Mutex<BTreeMap<String, Rule>> = Mutex::new(BTreeMap::new());
+
+// State of the limiters of every rule. The filter runs before any command has
+// a context to open keys with, so it's kept in memory instead. Clients without
+// a name get limiters of their own, which nothing reads again once they've
+// disconnected, so expired limiters are evicted in the background rather than
+// when they're next read.
+static STORE: LazyLock<Mutex<store::ConcurrentMemoryStore>> =
+    LazyLock::new(|| Mutex::new(new_store()));
+
+/// `Scope` is what a rule tells callers apart by. Each one gets a limiter of
+/// its own.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Scope {
+    /// The name that a client has given itself with CLIENT SETNAME. Clients
+    /// without one are limited per connection.
+    Client,
+
+    /// The ACL user that a client is authenticated as.
+    User,
+
+    /// The address that a client connected from.
+    Ip,
+}
+
+impl Scope {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::Client => "client",
+            Scope::User => "user",
+            Scope::Ip => "ip",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Result<Scope, CellError> {
+        match scope.to_ascii_lowercase().as_str() {
+            "client" => Ok(Scope::Client),
+            "user" => Ok(Scope::User),
+            "ip" => Ok(Scope::Ip),
+            _ => Err(error!("Unknown scope: {} (use client, user, or ip)", scope)),
+        }
+    }
+}
+
+/// `Rule` limits calls to the commands whose names match a pattern.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rule {
+    /// Glob-style pattern matched against command names, where `*` matches
+    /// any number of characters and `?` matches one. Matching ignores case.
+    pub pattern: String,
+
+    pub scope: Scope,
+
+    pub policy: policy::Policy,
+}
+
+/// Removes the named rule. Returns whether it existed.
+pub fn delete(name: &str) -> bool {
+    let mut rules = RULES.lock().unwrap();
+    let deleted = rules.remove(name).is_some();
+
+    // Without any rules left, nothing will read the limiters' state again.
+    if rules.is_empty() {
+        *STORE.lock().unwrap() = new_store();
+    }
+    deleted
+}
+
+/// Gets every rule, ordered by name.
+pub fn list() -> Vec<(String, Rule)> {
+    RULES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, rule)| (name.clone(), rule.clone()))
+        .collect()
+}
+
+/// Adds the named rule, replacing any existing one by the same name.
+pub fn set(name: &str, rule: Rule) -> Result<(), CellError> {
+    if !raw::has_command_filter_api() {
+        return Err(error!("Command filters need Redis 7.2 or later"));
+    }
+
+    let rule = Rule {
+        pattern: rule.pattern.to_ascii_lowercase(),
+        ..rule
+    };
+    RULES.lock().unwrap().insert(name.to_string(), rule);
+    Ok(())
+}
+
+/// Registers the command filter. Servers older than Redis 7.2 can't tell a
+/// filter which client is running a command, so it isn't registered there
+/// and rules can't be added.
+pub fn register(ctx: *mut raw::RedisModuleCtx) {
+    if raw::has_command_filter_api() {
+        raw::register_command_filter(ctx, filter, raw::REDISMODULE_CMDFILTER_NOSELF);
+    }
+}
+
+extern "C" fn filter(fctx: *mut raw::RedisModuleCommandFilterCtx) {
+    // There's nobody to report an error to from here, so the command is left
+    // to run as if there were no rules.
+    let _ = check(&redis::CommandFilter::new(fctx));
+}
+
+// Checks a command against each rule that matches it in turn, rewriting it
+// into a rejection at the first one that throttles it.
+fn check(filter: &redis::CommandFilter) -> Result<(), CellError> {
+    let rules = RULES.lock().unwrap();
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    // Commands being loaded from disk or replicated from a primary were
+    // already accepted once, and a replica can't tell its primary's commands
+    // apart from its clients', so rules only apply to a primary.
+    let flags = raw::get_context_flags(ptr::null_mut());
+    if flags & (raw::REDISMODULE_CTX_FLAGS_LOADING | raw::REDISMODULE_CTX_FLAGS_REPLICA)
+        != 0
+    {
+        return Ok(());
+    }
+
+    // The filter's own commands are let through so that a rule can't lock
+    // out the commands that change it.
+    let command = filter.arg(0)?.to_ascii_lowercase();
+    if command.starts_with("cl.filter.") {
+        return Ok(());
+    }
+
+    for (name, rule) in rules.iter() {
+        if !glob_match(rule.pattern.as_bytes(), command.as_bytes()) {
+            continue;
+        }
+        let Some(caller) = identify(filter, rule.scope) else {
+            continue;
+        };
+
+        let mut store = STORE.lock().unwrap().clone();
+        let (throttled, result) = rate_limit(&mut store, name, rule, &caller)?;
+        stats::record_filter_result(throttled);
+
+        if throttled {
+            let retry_after = Units::Milliseconds.round_up(result.retry_after);
+            return filter.replace(&[
+                REJECT_COMMAND,
+                name,
+                &command,
+                &retry_after.to_string(),
+            ]);
+        }
+    }
+
+    Ok(())
+}
+
+// The filter only ever runs on the server's main thread, so its store doesn't
+// need more than one shard.
+fn new_store() -> store::ConcurrentMemoryStore {
+    store::ConcurrentMemoryStore::with_options(
+        1,
+        store::ConcurrentMemoryStore::DEFAULT_EVICTION_INTERVAL,
+        SystemClock,
+    )
+}
+
+// Takes a call by the given caller from its limiter for the named rule.
+fn rate_limit(
+    store: &mut store::ConcurrentMemoryStore,
+    name: &str,
+    rule: &Rule,
+    caller: &str,
+) -> Result<(bool, cell::RateLimitResult), CellError> {
+    let quota = rule.policy.quota();
+    let mut limiter = cell::RateLimiter::new(store, &quota);
+    limiter.rate_limit(&format!("{name}:{caller}"), 1)
+}
+
+// Gets who the client running a command is within a rule's scope, or None if
+// it can't be told.
+fn identify(filter: &redis::CommandFilter, scope: Scope) -> Option<String> {
+    match scope {
+        Scope::Client => Some(
+            filter
+                .client_name()
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| format!("id:{}", filter.client_id())),
+        ),
+        Scope::User => filter.client_user_name(),
+        Scope::Ip => filter.client_address(),
+    }
+}
+
+// Matches a glob-style pattern with `*` and `?` wildcards against a whole
+// name.
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| glob_match(rest, &name[i..])),
+        Some((b'?', rest)) => !name.is_empty() && glob_match(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && glob_match(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::clock::ManualClock;
+    use crate::filter::*;
+
+    #[test]
+    fn it_matches_patterns() {
+        for (pattern, name) in [
+            ("keys", "keys"),
+            ("*", "hgetall"),
+            ("h*", "hgetall"),
+            ("*all", "hgetall"),
+            ("h?et*", "hgetall"),
+            ("cl.*", "cl.throttle"),
+        ] {
+            assert!(
+                glob_match(pattern.as_bytes(), name.as_bytes()),
+                "{pattern} should match {name}"
+            );
+        }
+
+        for (pattern, name) in [
+            ("keys", "key"),
+            ("key", "keys"),
+            ("h*", "get"),
+            ("h?et", "hgetall"),
+            ("?", ""),
+        ] {
+            assert!(
+                !glob_match(pattern.as_bytes(), name.as_bytes()),
+                "{pattern} shouldn't match {name}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_evicts_limiters_that_expire() {
+        let clock = ManualClock::new(time::OffsetDateTime::now_utc());
+        let mut store = store::ConcurrentMemoryStore::with_options(
+            1,
+            std::time::Duration::from_millis(1),
+            clock.clone(),
+        );
+        let rule = Rule {
+            pattern: "keys".to_string(),
+            scope: Scope::Client,
+            policy: policy::Policy {
+                max_burst: 0,
+                count: 1,
+                period: time::Duration::minutes(1),
+            },
+        };
+
+        // Each connection without a name gets a limiter of its own ...
+        for id in 0..10 {
+            rate_limit(&mut store, "noisy-keys", &rule, &format!("id:{id}")).unwrap();
+        }
+        assert_eq!(10, store.len());
+
+        // ... which goes away once it's back to full capacity, even though
+        // nothing reads it again.
+        clock.advance(time::Duration::minutes(1));
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !store.is_empty() {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "limiters weren't evicted"
+            );
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn it_parses_scopes() {
+        for scope in [Scope::Client, Scope::User, Scope::Ip] {
+            assert_eq!(scope, Scope::parse(scope.name()).unwrap());
+        }
+        assert_eq!(Scope::Ip, Scope::parse("IP").unwrap());
+        assert!(Scope::parse("host").is_err());
+    }
+}
@@ -7,10 +7,17 @@ extern crate time;
 mod macros;
 
 pub mod cell;
+mod config;
+mod datatype;
 pub mod error;
+mod filter;
+mod policy;
 mod redis;
+mod stats;
+mod wait;
 
 use crate::cell::store;
+use crate::cell::store::Store;
 use crate::error::CellError;
 use crate::redis::Command;
 use crate::redis::raw;
@@ -30,66 +37,545 @@ impl Command for ThrottleCommand {
 
     // Run the command.
     fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
-        if args.len() != 5 && args.len() != 6 {
+        // the first argument is command name "cl.throttle" (ignore it)
+        let (args, options) = split_options(args);
+        let (Some((key, params, quantity)), Some(options)) = (
+            parse_limit(args)?,
+            parse_options(options, &["algo", "partial", "units", "window"])?,
+        ) else {
             return Err(error!(
-                "Usage: {} <key> <max_burst> <count per period> \
-                 <period> [<quantity>]",
+                "Usage: {} <key> (<max_burst> <count per period> <period> | \
+                 POLICY <name>) [<quantity>] [PARTIAL] \
+                 [ALGO gcra|sliding-log|sliding-counter] \
+                 [WINDOW day|week|month [OFFSET <+/-HH:MM>]] [UNITS s|ms|us]",
                 self.name()
             ));
-        }
-
-        // the first argument is command name "cl.throttle" (ignore it)
-        let key = args[1];
-        let max_burst = parse_i64(args[2])?;
-        let count = parse_i64(args[3])?;
-        let period = parse_i64(args[4])?;
-        let quantity = match args.get(5) {
-            Some(n) => parse_i64(n)?,
-            None => 1,
         };
 
         // We reinitialize a new store and rate limiter every time this command
         // is run, but these structures don't have a huge overhead to them so
         // it's not that big of a problem.
+        let Options {
+            algo,
+            calendar,
+            partial,
+            units,
+            ..
+        } = options;
         let mut store = store::InternalRedisStore::new(&r);
-        let rate = cell::Rate::per_period(count, time::Duration::seconds(period));
-        let mut limiter = cell::RateLimiter::new(
-            &mut store,
-            &cell::RateQuota {
-                max_burst,
-                max_rate: rate,
-            },
-        );
+        let quota = params.quota();
 
-        let (throttled, rate_limit_result) = limiter.rate_limit(key, quantity)?;
+        // A partial grant has to know how much capacity is left, which only
+        // GCRA keeps track of.
+        if partial {
+            if algo != Algo::Gcra || calendar.is_some() {
+                return Err(error!("PARTIAL is only supported by GCRA"));
+            }
+            store.set_quota(&key, &quota);
+            let mut limiter = cell::RateLimiter::new(&mut store, &quota);
+            let (throttled, granted, rate_limit_result) =
+                limiter.rate_limit_partial(&key, quantity)?;
+            stats::record_result(throttled);
 
-        // If either time had a partial component, but it up to the next full
-        // second because otherwise a fast-paced caller could try again too
-        // early.
-        let mut retry_after = rate_limit_result.retry_after.as_seconds_f64() as i64;
-        if rate_limit_result.retry_after.subsec_milliseconds() > 0 {
-            retry_after += 1
+            // A partial request is only throttled when nothing was granted,
+            // so it's announced with the quantity that was asked for.
+            if throttled {
+                notify_throttled(&r, &key, quantity, &rate_limit_result)?;
+            }
+            return reply_rate_limit_result(
+                &r,
+                throttled,
+                &rate_limit_result,
+                units,
+                Some(granted),
+            );
         }
-        let mut reset_after = rate_limit_result.reset_after.as_seconds_f64() as i64;
-        if rate_limit_result.reset_after.subsec_milliseconds() > 0 {
-            reset_after += 1
+
+        let mut limiter: Box<dyn cell::Algorithm + '_> = match (algo, calendar) {
+            // Calendar windows count requests per calendar period, so only
+            // the limit's count applies.
+            (Algo::Gcra, Some((calendar, offset))) => {
+                Box::new(cell::window::CalendarLimiter::new(
+                    &mut store,
+                    cell::window::CalendarQuota {
+                        limit: params.count,
+                        calendar,
+                        offset,
+                    },
+                ))
+            }
+            (_, Some(_)) => {
+                return Err(error!("WINDOW can't be combined with ALGO"));
+            }
+
+            (Algo::Gcra, None) => {
+                store.set_quota(&key, &quota);
+                Box::new(cell::RateLimiter::new(&mut store, &quota))
+            }
+
+            // Sliding windows count requests over the period exactly, so
+            // they take the limit's count and period as is. There's no burst
+            // on top of the count.
+            (Algo::SlidingLog, None) => Box::new(cell::window::SlidingLogLimiter::new(
+                &mut store,
+                params.window_quota(),
+            )),
+            (Algo::SlidingCounter, None) => {
+                Box::new(cell::window::SlidingCounterLimiter::new(
+                    &mut store,
+                    params.window_quota(),
+                ))
+            }
+        };
+
+        let (throttled, rate_limit_result) = limiter.rate_limit(&key, quantity)?;
+        stats::record_result(throttled);
+        if throttled {
+            notify_throttled(&r, &key, quantity, &rate_limit_result)?;
         }
+        reply_rate_limit_result(&r, throttled, &rate_limit_result, units, None)?;
 
-        // Reply with an array containing rate limiting results. Note that
-        // Redis' support for interesting data types is quite weak, so we have
-        // to jam a few square pegs into round holes. It's a little messy, but
-        // the interface comes out as pretty workable.
-        r.reply_array(5)?;
-        r.reply_integer(if throttled { 1 } else { 0 })?;
-        r.reply_integer(rate_limit_result.limit)?;
+        // There's no need to replicate the command itself. The store has
+        // already replicated the exact state that it wrote.
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// PeekCommand reports what the result of a CL.THROTTLE invocation would be
+// without actually consuming any capacity from the limiter.
+struct PeekCommand {}
+
+impl Command for PeekCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.peek"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        // the first argument is command name "cl.peek" (ignore it)
+        let (args, options) = split_options(args);
+        let (Some((key, params, quantity)), Some(Options { units, .. })) =
+            (parse_limit(args)?, parse_options(options, &["units"])?)
+        else {
+            return Err(error!(
+                "Usage: {} <key> (<max_burst> <count per period> <period> | \
+                 POLICY <name>) [<quantity>] [UNITS s|ms|us]",
+                self.name()
+            ));
+        };
+
+        let quota = params.quota();
+        let mut store = store::InternalRedisStore::new(&r);
+        let limiter = cell::RateLimiter::new(&mut store, &quota);
+
+        let (throttled, rate_limit_result) = limiter.peek(&key, quantity)?;
+        reply_rate_limit_result(&r, throttled, &rate_limit_result, units, None)?;
+
+        // Nothing was written, so there's nothing to replicate.
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "readonly"
+    }
+}
+
+// ReserveCommand schedules a request instead of rejecting it when it's over
+// the rate limit, replying with how long the caller has to wait before it can
+// proceed.
+struct ReserveCommand {}
+
+impl Command for ReserveCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.reserve"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        // the first argument is command name "cl.reserve" (ignore it)
+        let (args, options) = split_options(args);
+        let (
+            Some((key, params, quantity)),
+            Some(Options {
+                max_wait, units, ..
+            }),
+        ) = (
+            parse_limit(args)?,
+            parse_options(options, &["maxwait", "units"])?,
+        )
+        else {
+            return Err(error!(
+                "Usage: {} <key> (<max_burst> <count per period> <period> | \
+                 POLICY <name>) [<quantity>] [MAXWAIT <ms>] [UNITS s|ms|us]",
+                self.name()
+            ));
+        };
+
+        let quota = params.quota();
+        let mut store = store::InternalRedisStore::new(&r);
+        store.set_quota(&key, &quota);
+        let mut limiter = cell::RateLimiter::new(&mut store, &quota);
+
+        // Same reply as CL.THROTTLE, except that retry_after is how long the
+        // caller needs to wait before going ahead with a reserved request.
+        let (throttled, rate_limit_result) = limiter.reserve(&key, quantity, max_wait)?;
+        stats::record_result(throttled);
+        reply_rate_limit_result(&r, throttled, &rate_limit_result, units, None)?;
+
+        // The store has already replicated the exact state that it wrote.
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// WaitCommand is like CL.THROTTLE, except that instead of rejecting a request
+// that's over the limit, it blocks the client until the request can be
+// allowed or a timeout runs out.
+struct WaitCommand {}
+
+impl Command for WaitCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.wait"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        // the first argument is command name "cl.wait" (ignore it)
+        let (args, options) = split_options(args);
+        let (
+            Some((key, params, quantity)),
+            Some(Options {
+                timeout: Some(timeout),
+                units,
+                ..
+            }),
+        ) = (
+            parse_limit(args)?,
+            parse_options(options, &["timeout", "units"])?,
+        )
+        else {
+            return Err(error!(
+                "Usage: {} <key> (<max_burst> <count per period> <period> | \
+                 POLICY <name>) [<quantity>] TIMEOUT <ms> [UNITS s|ms|us]",
+                self.name()
+            ));
+        };
+
+        let quota = params.quota();
+        let mut store = store::InternalRedisStore::new(&r);
+        store.set_quota(&key, &quota);
+        let mut limiter = cell::RateLimiter::new(&mut store, &quota);
+        let (throttled, rate_limit_result) = limiter.rate_limit(&key, quantity)?;
+
+        // Reply straight away if the request was allowed, if it's for more
+        // than the limit could ever allow (retry_after is -1), or if the
+        // client can't be blocked because it's in a transaction or script.
+        if !throttled
+            || rate_limit_result.retry_after < time::Duration::ZERO
+            || !r.can_block()
+        {
+            stats::record_result(throttled);
+            return reply_rate_limit_result(
+                &r,
+                throttled,
+                &rate_limit_result,
+                units,
+                None,
+            );
+        }
+
+        let waiter = wait::Waiter {
+            key,
+            quota,
+            quantity,
+            units,
+            deadline: (timeout > time::Duration::ZERO)
+                .then(|| time::OffsetDateTime::now_utc() + timeout),
+            timeout,
+        };
+        wait::block(&r, waiter, rate_limit_result)
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// RefundCommand gives capacity taken by an earlier CL.THROTTLE back to a
+// limiter, for when a request was charged for more than it ended up using.
+struct RefundCommand {}
+
+impl Command for RefundCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.refund"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        // the first argument is command name "cl.refund" (ignore it)
+        //
+        // Unlike other commands the quantity is required, since a refund of
+        // the default quantity is far more likely to be a forgotten argument
+        // than what was meant.
+        let Some((key, params, quantity)) = parse_limit_with_default(args, None)? else {
+            return Err(error!(
+                "Usage: {} <key> (<max_burst> <count per period> <period> | \
+                 POLICY <name>) <quantity>",
+                self.name()
+            ));
+        };
+
+        let quota = params.quota();
+        let mut store = store::InternalRedisStore::new(&r);
+        store.set_quota(&key, &quota);
+        let mut limiter = cell::RateLimiter::new(&mut store, &quota);
+
+        let rate_limit_result = limiter.refund(&key, quantity)?;
         r.reply_integer(rate_limit_result.remaining)?;
+
+        // The store has already replicated the exact state that it wrote.
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// ThrottleMultiCommand applies a single request against several rate limits
+// atomically. Capacity is only consumed if every one of the limits allows the
+// request.
+struct ThrottleMultiCommand {}
+
+impl Command for ThrottleMultiCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.throttlemulti"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        // Limits come in groups of four, optionally followed by a quantity
+        // that applies to all of them.
+        let (args, options) = split_multi_options(args);
+        let limit_args = args.len() - 1 - (args.len() - 1) % 4;
+        let Some(Options { units, .. }) = parse_options(options, &["units"])?
+            .filter(|_| limit_args > 0 && (args.len() - 1) % 4 <= 1)
+        else {
+            return Err(error!(
+                "Usage: {} <key> <max_burst> <count per period> <period> \
+                 [<key> <max_burst> <count per period> <period> ...] [<quantity>] \
+                 [UNITS s|ms|us]",
+                self.name()
+            ));
+        };
+
+        let mut keys = Vec::with_capacity(limit_args / 4);
+        let mut quotas = Vec::with_capacity(limit_args / 4);
+        for chunk in args[1..=limit_args].chunks(4) {
+            keys.push(config::prefixed(chunk[0])?);
+            quotas.push(parse_params(&chunk[1..4])?.quota());
+        }
+        let quantity = match args.get(limit_args + 1) {
+            Some(n) => parse_i64(n)?,
+            None => config::default_quantity(),
+        };
+
+        let limits: Vec<(&str, &cell::RateQuota)> =
+            keys.iter().map(String::as_str).zip(quotas.iter()).collect();
+
+        let mut store = store::InternalRedisStore::new(&r);
+        for (key, quota) in &limits {
+            store.set_quota(key, quota);
+        }
+        let mut limiter = cell::multi::MultiRateLimiter::new(&mut store);
+
+        let (throttled, multi_result) = limiter.rate_limit(&limits, quantity)?;
+        stats::record_result(throttled);
+        let binding_result = &multi_result.results[multi_result.binding];
+
+        // Same as CL.THROTTLE's reply, but with the index of the binding limit
+        // inserted after the limited flag. The limit, remaining, and
+        // reset_after are those of the binding limit, while retry_after
+        // accounts for all of them.
+        let retry_after = units.round_up(multi_result.retry_after);
+        let reset_after = units.round_up(binding_result.reset_after);
+        if r.is_resp3() {
+            r.reply_map(6)?;
+            r.reply_simple_string("limited")?;
+            r.reply_bool(throttled)?;
+            r.reply_simple_string("binding")?;
+            r.reply_integer(multi_result.binding as i64)?;
+            r.reply_simple_string("limit")?;
+            r.reply_integer(binding_result.limit)?;
+            r.reply_simple_string("remaining")?;
+            r.reply_integer(binding_result.remaining)?;
+            r.reply_simple_string("retry_after")?;
+            r.reply_integer(retry_after)?;
+            r.reply_simple_string("reset_after")?;
+            r.reply_integer(reset_after)?;
+            return Ok(());
+        }
+
+        r.reply_array(6)?;
+        r.reply_integer(if throttled { 1 } else { 0 })?;
+        r.reply_integer(multi_result.binding as i64)?;
+        r.reply_integer(binding_result.limit)?;
+        r.reply_integer(binding_result.remaining)?;
         r.reply_integer(retry_after)?;
         r.reply_integer(reset_after)?;
 
-        // Tell Redis that it's okay to replicate the command with the same
-        // parameters out to replicas.
-        r.replicate_verbatim()?;
+        // The store has already replicated the exact state that it wrote.
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// ResetCommand clears the state of one or more limiters so that they're back
+// at full capacity.
+struct ResetCommand {}
+
+impl Command for ResetCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.reset"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() < 2 {
+            return Err(error!("Usage: {} <key> [<key> ...]", self.name()));
+        }
+
+        // A limiter's state doesn't depend on its quota, so there's no need
+        // to build a full RateLimiter just to throw it away. Go straight to
+        // the store instead.
+        let mut store = store::InternalRedisStore::new(&r);
+        let mut cleared = 0;
+        for key in &args[1..] {
+            if store.delete(&config::prefixed(key)?)? {
+                cleared += 1;
+            }
+        }
+
+        r.reply_integer(cleared)?;
+
+        // The store has already replicated a deletion for each cleared key.
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// AcquireCommand acquires a lease on a concurrency limiter, which limits how
+// many requests can be in flight at once rather than how often they can be
+// made.
+struct AcquireCommand {}
+
+impl Command for AcquireCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.acquire"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() != 4 {
+            return Err(error!("Usage: {} <key> <max> <lease_ttl>", self.name()));
+        }
+
+        let max = parse_i64(args[2])?;
+        let lease_ttl = parse_period(args[3])?;
+
+        let mut store = store::InternalRedisStore::new(&r);
+        let mut limiter = cell::concurrency::ConcurrencyLimiter::new(&mut store);
+
+        let (limited, acquire_result) =
+            limiter.acquire(&config::prefixed(args[1])?, max, lease_ttl)?;
+        stats::record_result(limited);
+
+        // Reply with the ID needed to release the lease, or a null if every
+        // slot is taken.
+        match acquire_result.lease_id {
+            Some(lease_id) => r.reply_integer(lease_id as i64)?,
+            None => r.reply_null()?,
+        }
+
+        // The store has already replicated the exact leases that it wrote.
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// ReleaseCommand releases a lease acquired with CL.ACQUIRE, freeing up its slot
+// for another request.
+struct ReleaseCommand {}
+
+impl Command for ReleaseCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.release"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() != 3 {
+            return Err(error!("Usage: {} <key> <lease_id>", self.name()));
+        }
+
+        let lease_id = parse_u64(args[2])?;
 
+        let mut store = store::InternalRedisStore::new(&r);
+        let mut limiter = cell::concurrency::ConcurrencyLimiter::new(&mut store);
+
+        // Reply 0 if the lease wasn't held (it may well have expired already).
+        let released = limiter.release(&config::prefixed(args[1])?, lease_id)?;
+        r.reply_integer(if released { 1 } else { 0 })?;
+
+        // The store has already replicated the exact leases that it wrote.
         Ok(())
     }
 
@@ -101,15 +587,730 @@ impl Command for ThrottleCommand {
     }
 }
 
+// StatsCommand replies with the module's statistics: how many commands it's
+// run, how its limits have decided, and how long each command has taken. With
+// RESET, it sets them all back to zero instead.
+struct StatsCommand {}
+
+impl Command for StatsCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.stats"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        match args {
+            [_] => {}
+            [_, reset] if reset.eq_ignore_ascii_case("reset") => {
+                stats::reset();
+                return r.reply_simple_string("OK");
+            }
+            _ => return Err(error!("Usage: {} [RESET]", self.name())),
+        }
+
+        let stats = stats::get();
+        r.reply_map(8)?;
+        r.reply_simple_string("calls")?;
+        r.reply_integer(stats.calls as i64)?;
+        r.reply_simple_string("allowed")?;
+        r.reply_integer(stats.allowed as i64)?;
+        r.reply_simple_string("throttled")?;
+        r.reply_integer(stats.throttled as i64)?;
+        r.reply_simple_string("filter_allowed")?;
+        r.reply_integer(stats.filter_allowed as i64)?;
+        r.reply_simple_string("filter_rejected")?;
+        r.reply_integer(stats.filter_rejected as i64)?;
+        r.reply_simple_string("cas_retries")?;
+        r.reply_integer(stats.cas_retries as i64)?;
+        r.reply_simple_string("errors")?;
+        r.reply_integer(stats.errors as i64)?;
+
+        // Latencies are a map of command names to their histograms, which are
+        // in turn maps of bucket labels to counts.
+        r.reply_simple_string("latency")?;
+        r.reply_map(stats.latencies.len() as i64)?;
+        for (command, histogram) in &stats.latencies {
+            r.reply_simple_string(command)?;
+            r.reply_map(stats::LATENCY_BOUNDS_US.len() as i64 + 3)?;
+            for (label, count) in histogram.labeled_buckets() {
+                r.reply_simple_string(&label)?;
+                r.reply_integer(count as i64)?;
+            }
+            r.reply_simple_string("count")?;
+            r.reply_integer(histogram.count as i64)?;
+            r.reply_simple_string("total_us")?;
+            r.reply_integer(histogram.total_us as i64)?;
+        }
+
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "readonly"
+    }
+}
+
+// SetTatCommand sets a limiter's state directly. It's internal to the module
+// and not meant to be called by users: it's the command that's replicated when
+// a limiter is updated, and that's emitted to recreate limiters when Redis
+// rewrites its AOF.
+struct SetTatCommand {}
+
+impl Command for SetTatCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        datatype::SET_TAT_COMMAND
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() != 5 && !(args.len() == 7 && args[5].eq_ignore_ascii_case("pxat")) {
+            return Err(error!(
+                "Usage: {} <key> <tat> <max_burst> <emission interval> \
+                 [PXAT <unix time in milliseconds>]",
+                self.name()
+            ));
+        }
+
+        ensure_replicated(&r, self.name())?;
+
+        let key = r.open_key_writable(args[1]);
+        let value = datatype::TatValue {
+            tat: args[2]
+                .parse::<u64>()
+                .map_err(|_| error!("Couldn't parse as integer: {}", args[2]))?,
+            max_burst: parse_i64(args[3])?,
+            emission_interval: parse_i64(args[4])?,
+        };
+
+        // Limiters expire at their TAT unless told otherwise, at which point
+        // they'd be back at full capacity anyway. The value is set even if its
+        // expiry has already passed, and left for the server to expire, so
+        // that the result doesn't depend on the clock of wherever it's run.
+        let expires_at_ms = match args.get(6) {
+            Some(n) => parse_i64(n)?,
+            None => (value.tat / 1_000_000) as i64,
+        };
+        key.set_value(datatype::tat_type(), value)?;
+        key.set_expire_at(expires_at_ms)?;
+
+        r.reply_simple_string("OK")?;
+
+        // The TAT and expiry are given explicitly, so the command gives the
+        // same result wherever it's run and can be replicated as is.
+        r.replicate_verbatim()?;
+
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// SetLeasesCommand sets the leases held on a concurrency limiter directly. Like
+// CL.SETTAT, it's internal to the module: it's what's replicated when leases
+// change, and what's emitted to recreate them when Redis rewrites its AOF.
+struct SetLeasesCommand {}
+
+impl Command for SetLeasesCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        datatype::SET_LEASES_COMMAND
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() < 3 || args.len() % 2 != 1 {
+            return Err(error!(
+                "Usage: {} <key> <last lease id> [<lease id> <expires at> ...]",
+                self.name()
+            ));
+        }
+
+        ensure_replicated(&r, self.name())?;
+
+        let key = r.open_key_writable(args[1]);
+        let value = cell::store::Leases {
+            last_id: parse_u64(args[2])?,
+            leases: args[3..]
+                .chunks(2)
+                .map(|lease| Ok((parse_u64(lease[0])?, parse_u64(lease[1])?)))
+                .collect::<Result<_, CellError>>()?,
+        };
+
+        // The key expires along with the last of its leases, so with none
+        // left there's nothing to set. Like CL.SETTAT, leases that have
+        // already expired are still set, and left for the server to expire.
+        match value.expires_at().map(datatype::leases_expire_at_ms) {
+            Some(expires_at_ms) => {
+                key.set_value(datatype::leases_type(), value)?;
+                key.set_expire_at(expires_at_ms)?;
+            }
+            None => {
+                if !key.is_empty()? {
+                    key.delete()?;
+                }
+            }
+        }
+
+        r.reply_simple_string("OK")?;
+
+        // Everything is given explicitly, so the command gives the same result
+        // wherever it's run and can be replicated as is.
+        r.replicate_verbatim()?;
+
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// SetWindowCommand sets the buckets of a sliding window limiter directly. Like
+// CL.SETTAT, it's internal to the module: it's what's replicated when a window
+// changes, and what's emitted to recreate windows when Redis rewrites its AOF.
+struct SetWindowCommand {}
+
+impl Command for SetWindowCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        datatype::SET_WINDOW_COMMAND
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() < 3 || args.len() % 2 != 1 {
+            return Err(error!(
+                "Usage: {} <key> <unix time in milliseconds to expire at> \
+                 [<bucket start> <count> ...]",
+                self.name()
+            ));
+        }
+
+        ensure_replicated(&r, self.name())?;
+
+        let key = r.open_key_writable(args[1]);
+        let value = datatype::WindowValue {
+            expires_at_ms: parse_i64(args[2])?,
+            window: cell::store::Window {
+                buckets: args[3..]
+                    .chunks(2)
+                    .map(|bucket| Ok((parse_u64(bucket[0])?, parse_i64(bucket[1])?)))
+                    .collect::<Result<_, CellError>>()?,
+            },
+        };
+
+        // Like CL.SETTAT, a window that's already expired is still set, and
+        // left for the server to expire.
+        if value.window.buckets.is_empty() {
+            if !key.is_empty()? {
+                key.delete()?;
+            }
+        } else {
+            let expires_at_ms = value.expires_at_ms;
+            key.set_value(datatype::window_type(), value)?;
+            key.set_expire_at(expires_at_ms)?;
+        }
+
+        r.reply_simple_string("OK")?;
+
+        // Everything is given explicitly, so the command gives the same result
+        // wherever it's run and can be replicated as is.
+        r.replicate_verbatim()?;
+
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// PolicySetCommand adds a named policy that CL.THROTTLE can refer to instead
+// of taking a limit's parameters inline. An existing policy by the same name
+// is replaced, which takes effect for all of its limiters immediately.
+struct PolicySetCommand {}
+
+impl Command for PolicySetCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.policy.set"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() != 5 {
+            return Err(error!(
+                "Usage: {} <name> <max_burst> <count per period> <period>",
+                self.name()
+            ));
+        }
+
+        let policy = policy::Policy {
+            max_burst: parse_i64(args[2])?,
+            count: parse_i64(args[3])?,
+            period: parse_period(args[4])?,
+        };
+        policy::set(args[1], policy);
+
+        r.reply_simple_string("OK")?;
+
+        // Policies don't live in the keyspace, so replicas only get them by
+        // running the same command.
+        r.replicate_verbatim()?;
+
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// PolicyGetCommand replies with the parameters of a named policy.
+struct PolicyGetCommand {}
+
+impl Command for PolicyGetCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.policy.get"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() != 2 {
+            return Err(error!("Usage: {} <name>", self.name()));
+        }
+
+        match policy::get(args[1]) {
+            Some(policy) => {
+                r.reply_array(3)?;
+                r.reply_integer(policy.max_burst)?;
+                r.reply_integer(policy.count)?;
+                r.reply_string(&format_period(policy.period))?;
+            }
+            None => r.reply_null()?,
+        }
+
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "readonly"
+    }
+}
+
+// PolicyDelCommand removes a named policy.
+struct PolicyDelCommand {}
+
+impl Command for PolicyDelCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.policy.del"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() != 2 {
+            return Err(error!("Usage: {} <name>", self.name()));
+        }
+
+        r.reply_integer(if policy::delete(args[1]) { 1 } else { 0 })?;
+
+        // Policies don't live in the keyspace, so replicas only get them by
+        // running the same command.
+        r.replicate_verbatim()?;
+
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "write"
+    }
+}
+
+// FilterSetCommand adds a rule that throttles calls to the commands matching a
+// pattern, by client name, ACL user, or address. An existing rule by the same
+// name is replaced.
+struct FilterSetCommand {}
+
+impl Command for FilterSetCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.filter.set"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() != 7 {
+            return Err(error!(
+                "Usage: {} <name> <pattern> CLIENT|USER|IP <max_burst> \
+                 <count per period> <period>",
+                self.name()
+            ));
+        }
+
+        let rule = filter::Rule {
+            pattern: args[2].to_string(),
+            scope: filter::Scope::parse(args[3])?,
+            policy: parse_params(&args[4..7])?,
+        };
+        filter::set(args[1], rule)?;
+
+        // Rules only apply to the server that they're set on, so the command
+        // isn't replicated.
+        r.reply_simple_string("OK")
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "admin"
+    }
+}
+
+// FilterDelCommand removes a command filter rule.
+struct FilterDelCommand {}
+
+impl Command for FilterDelCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.filter.del"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() != 2 {
+            return Err(error!("Usage: {} <name>", self.name()));
+        }
+
+        r.reply_integer(if filter::delete(args[1]) { 1 } else { 0 })
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "admin"
+    }
+}
+
+// FilterListCommand replies with every command filter rule along with its
+// parameters.
+struct FilterListCommand {}
+
+impl Command for FilterListCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        "cl.filter.list"
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        if args.len() != 1 {
+            return Err(error!("Usage: {}", self.name()));
+        }
+
+        let rules = filter::list();
+        r.reply_array(rules.len() as i64)?;
+        for (name, rule) in &rules {
+            r.reply_array(6)?;
+            r.reply_string(name)?;
+            r.reply_string(&rule.pattern)?;
+            r.reply_string(rule.scope.name())?;
+            r.reply_integer(rule.policy.max_burst)?;
+            r.reply_integer(rule.policy.count)?;
+            r.reply_string(&format_period(rule.policy.period))?;
+        }
+
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "admin"
+    }
+}
+
+// FilterRejectCommand is what the command filter rewrites a throttled command
+// into. It replies with a throttle error in place of running the command.
+struct FilterRejectCommand {}
+
+impl Command for FilterRejectCommand {
+    // Should return the name of the command to be registered.
+    fn name(&self) -> &'static str {
+        filter::REJECT_COMMAND
+    }
+
+    // Run the command.
+    fn run(&self, r: redis::Redis, args: &[&str]) -> Result<(), CellError> {
+        let [_, rule, command, retry_after] = args else {
+            return Err(error!(
+                "Usage: {} <rule> <command> <retry_after>",
+                self.name()
+            ));
+        };
+
+        r.reply_error(&format!(
+            "THROTTLED {command} is over the limit of rule {rule}, retry after \
+             {retry_after}ms"
+        ));
+        Ok(())
+    }
+
+    // Should return any flags to be registered with the name as a string
+    // separated list. See the Redis module API documentation for a complete
+    // list of the ones that are available.
+    fn str_flags(&self) -> &'static str {
+        "fast"
+    }
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn Throttle_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&ThrottleCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn Peek_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&PeekCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn Reserve_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&ReserveCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn Refund_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&RefundCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn ThrottleMulti_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&ThrottleMultiCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn Wait_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&WaitCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn Reset_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&ResetCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn Acquire_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&AcquireCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn Release_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&ReleaseCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn Stats_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&StatsCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn SetTat_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&SetTatCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn SetLeases_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&SetLeasesCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn SetWindow_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&SetWindowCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn PolicySet_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&PolicySetCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn PolicyGet_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&PolicyGetCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn PolicyDel_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&PolicyDelCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn FilterSet_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&FilterSetCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn FilterDel_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&FilterDelCommand {}, ctx, argv, argc)
+}
+
+#[allow(non_snake_case)]
+#[allow(unused_variables)]
+#[unsafe(no_mangle)]
+pub extern "C" fn FilterList_RedisCommand(
+    ctx: *mut raw::RedisModuleCtx,
+    argv: *mut *mut raw::RedisModuleString,
+    argc: c_int,
+) -> raw::Status {
+    <dyn Command>::harness(&FilterListCommand {}, ctx, argv, argc)
+}
+
 #[allow(non_snake_case)]
 #[allow(unused_variables)]
 #[unsafe(no_mangle)]
-pub extern "C" fn Throttle_RedisCommand(
+pub extern "C" fn FilterReject_RedisCommand(
     ctx: *mut raw::RedisModuleCtx,
     argv: *mut *mut raw::RedisModuleString,
     argc: c_int,
 ) -> raw::Status {
-    <dyn Command>::harness(&ThrottleCommand {}, ctx, argv, argc)
+    <dyn Command>::harness(&FilterRejectCommand {}, ctx, argv, argc)
 }
 
 #[allow(non_snake_case)]
@@ -130,15 +1331,159 @@ pub extern "C" fn RedisModule_OnLoad(
         return raw::Status::Err;
     }
 
-    let command = ThrottleCommand {};
-    if raw::create_command(
+    if datatype::create(ctx) == raw::Status::Err {
+        return raw::Status::Err;
+    }
+
+    stats::register(ctx);
+
+    if config::register(ctx) == raw::Status::Err {
+        return raw::Status::Err;
+    }
+
+    filter::register(ctx);
+
+    // Settings given as load arguments override any from the server's config.
+    let applied = redis::parse_args(argv, argc)
+        .map_err(CellError::from)
+        .and_then(|args| config::set_from_args(&args));
+    if let Err(e) = applied {
+        redis::Redis::new(ctx).log(redis::LogLevel::Warning, &format!("{e}"));
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &ThrottleCommand {}, Throttle_RedisCommand, 1, 1, 1)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &PeekCommand {}, Peek_RedisCommand, 1, 1, 1)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &ReserveCommand {}, Reserve_RedisCommand, 1, 1, 1)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &WaitCommand {}, Wait_RedisCommand, 1, 1, 1)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &RefundCommand {}, Refund_RedisCommand, 1, 1, 1)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    // Keys lead each group of four arguments. A negative lastkey is counted
+    // from the end, which skips over the last limit's parameters along with
+    // the optional quantity.
+    if create_command(
         ctx,
-        format!("{}\0", command.name()).as_ptr(),
-        Some(Throttle_RedisCommand),
-        format!("{}\0", command.str_flags()).as_ptr(),
-        1, // firstkey: first argument that's a key
-        1, // lastkey: last argument that's a key
-        1, // keystep: the step between first and last key
+        &ThrottleMultiCommand {},
+        ThrottleMulti_RedisCommand,
+        1,
+        -4,
+        4,
+    ) == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    // Every argument after the command name is a key.
+    if create_command(ctx, &ResetCommand {}, Reset_RedisCommand, 1, -1, 1)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &AcquireCommand {}, Acquire_RedisCommand, 1, 1, 1)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &ReleaseCommand {}, Release_RedisCommand, 1, 1, 1)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &StatsCommand {}, Stats_RedisCommand, 0, 0, 0)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &SetTatCommand {}, SetTat_RedisCommand, 1, 1, 1)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &SetLeasesCommand {}, SetLeases_RedisCommand, 1, 1, 1)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &SetWindowCommand {}, SetWindow_RedisCommand, 1, 1, 1)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    // Policies aren't keys, so none of their commands have any.
+    if create_command(ctx, &PolicySetCommand {}, PolicySet_RedisCommand, 0, 0, 0)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &PolicyGetCommand {}, PolicyGet_RedisCommand, 0, 0, 0)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &PolicyDelCommand {}, PolicyDel_RedisCommand, 0, 0, 0)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &FilterSetCommand {}, FilterSet_RedisCommand, 0, 0, 0)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &FilterDelCommand {}, FilterDel_RedisCommand, 0, 0, 0)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(ctx, &FilterListCommand {}, FilterList_RedisCommand, 0, 0, 0)
+        == raw::Status::Err
+    {
+        return raw::Status::Err;
+    }
+
+    if create_command(
+        ctx,
+        &FilterRejectCommand {},
+        FilterReject_RedisCommand,
+        0,
+        0,
+        0,
     ) == raw::Status::Err
     {
         return raw::Status::Err;
@@ -147,7 +1492,712 @@ pub extern "C" fn RedisModule_OnLoad(
     raw::Status::Ok
 }
 
+// Registers a command with Redis. The key specification arguments are passed
+// through as is:
+//
+// * firstkey: first argument that's a key
+// * lastkey: last argument that's a key
+// * keystep: the step between first and last key
+fn create_command(
+    ctx: *mut raw::RedisModuleCtx,
+    command: &dyn Command,
+    cmdfunc: raw::RedisModuleCmdFunc,
+    firstkey: c_int,
+    lastkey: c_int,
+    keystep: c_int,
+) -> raw::Status {
+    raw::create_command(
+        ctx,
+        format!("{}\0", command.name()).as_ptr(),
+        Some(cmdfunc),
+        format!("{}\0", command.str_flags()).as_ptr(),
+        firstkey,
+        lastkey,
+        keystep,
+    )
+}
+
 fn parse_i64(arg: &str) -> Result<i64, CellError> {
     arg.parse::<i64>()
         .map_err(|_| error!("Couldn't parse as integer: {}", arg))
 }
+
+// Rejects a call to one of the internal CL.SET* commands unless it came from a
+// primary or the AOF. They overwrite a limiter with whatever state they're
+// given, so a client calling one could corrupt it.
+fn ensure_replicated(r: &redis::Redis, name: &str) -> Result<(), CellError> {
+    if r.is_replicated() {
+        return Ok(());
+    }
+    Err(error!(
+        "{} is internal to the module and can't be called by clients",
+        name
+    ))
+}
+
+fn parse_u64(arg: &str) -> Result<u64, CellError> {
+    arg.parse::<u64>()
+        .map_err(|_| error!("Couldn't parse as integer: {}", arg))
+}
+
+// Keywords of the options that can follow a limit. An argument after a limit
+// that isn't one of them is taken to be its quantity.
+const OPTIONS: [&str; 6] = ["algo", "maxwait", "partial", "timeout", "units", "window"];
+
+fn is_option(arg: &str) -> bool {
+    OPTIONS
+        .iter()
+        .any(|option| arg.eq_ignore_ascii_case(option))
+}
+
+// Options that can follow a command's limit, in any order.
+struct Options {
+    // Algorithm that the limit should be enforced with. GCRA is the default.
+    algo: Algo,
+
+    // Calendar period that the limit's windows should be aligned to, if any.
+    calendar: Option<CalendarWindow>,
+
+    max_wait: Option<time::Duration>,
+    partial: bool,
+    timeout: Option<time::Duration>,
+
+    // Units that times should be replied with.
+    units: Units,
+}
+
+// Splits a command's arguments into those of the limit that they start with
+// (a key, then either a limit's parameters or `POLICY <name>`, and then an
+// optional quantity) and the options that follow it. Each of the limit's
+// arguments has a fixed position, so a key or policy that happens to be named
+// like an option is never mistaken for one.
+fn split_options<'a, 'b>(args: &'a [&'b str]) -> (&'a [&'b str], &'a [&'b str]) {
+    let mut end = match args.get(2) {
+        Some(arg) if arg.eq_ignore_ascii_case("policy") => 4,
+        _ => 5,
+    };
+    if args.get(end).is_some_and(|arg| !is_option(arg)) {
+        end += 1;
+    }
+    args.split_at(end.min(args.len()))
+}
+
+// Like `split_options`, but for CL.THROTTLEMULTI's limits, which come in groups
+// of four. Only the first limit's key has a fixed position, so any later key
+// that's named like an option is taken to be one.
+fn split_multi_options<'a, 'b>(args: &'a [&'b str]) -> (&'a [&'b str], &'a [&'b str]) {
+    let mut end = 1;
+    while end + 4 <= args.len() && (end == 1 || !is_option(args[end])) {
+        end += 4;
+    }
+    if args.get(end).is_some_and(|arg| !is_option(arg)) {
+        end += 1;
+    }
+    args.split_at(end.min(args.len()))
+}
+
+// Parses the options that follow a command's limit in a single pass, so that
+// they can be given in any order. Only the given options are accepted. Returns
+// None if there's one that isn't, if one is missing its value, or if one is
+// given twice.
+fn parse_options(args: &[&str], accepted: &[&str]) -> Result<Option<Options>, CellError> {
+    let mut options = Options {
+        algo: Algo::Gcra,
+        calendar: None,
+        max_wait: None,
+        partial: false,
+        timeout: None,
+        units: config::units(),
+    };
+
+    let mut seen = Vec::with_capacity(args.len());
+    let mut args = args;
+    while let [keyword, rest @ ..] = args {
+        let keyword = keyword.to_ascii_lowercase();
+        if !accepted.contains(&keyword.as_str()) || seen.contains(&keyword) {
+            return Ok(None);
+        }
+
+        args = match (keyword.as_str(), rest) {
+            ("algo", [algo, rest @ ..]) => {
+                options.algo = parse_algo(algo)?;
+                rest
+            }
+            ("maxwait", [ms, rest @ ..]) => {
+                options.max_wait = Some(parse_milliseconds("MAXWAIT", ms)?);
+                rest
+            }
+            ("partial", rest) => {
+                options.partial = true;
+                rest
+            }
+            ("timeout", [ms, rest @ ..]) => {
+                options.timeout = Some(parse_milliseconds("TIMEOUT", ms)?);
+                rest
+            }
+            ("units", [units, rest @ ..]) => {
+                options.units = Units::parse(units)?;
+                rest
+            }
+            ("window", [calendar, offset_keyword, offset, rest @ ..])
+                if offset_keyword.eq_ignore_ascii_case("offset") =>
+            {
+                options.calendar =
+                    Some((parse_calendar(calendar)?, parse_offset(offset)?));
+                rest
+            }
+            ("window", [calendar, rest @ ..]) => {
+                options.calendar =
+                    Some((parse_calendar(calendar)?, time::UtcOffset::UTC));
+                rest
+            }
+            _ => return Ok(None),
+        };
+        seen.push(keyword);
+    }
+
+    Ok(Some(options))
+}
+
+// Parses the algorithm given to `ALGO`.
+fn parse_algo(arg: &str) -> Result<Algo, CellError> {
+    match arg.to_ascii_lowercase().as_str() {
+        "gcra" => Ok(Algo::Gcra),
+        "sliding-log" => Ok(Algo::SlidingLog),
+        "sliding-counter" => Ok(Algo::SlidingCounter),
+        _ => Err(error!(
+            "Unknown algorithm: {} (use gcra, sliding-log, or sliding-counter)",
+            arg
+        )),
+    }
+}
+
+// Parses the calendar period given to `WINDOW`.
+fn parse_calendar(arg: &str) -> Result<cell::window::Calendar, CellError> {
+    match arg.to_ascii_lowercase().as_str() {
+        "day" => Ok(cell::window::Calendar::Day),
+        "week" => Ok(cell::window::Calendar::Week),
+        "month" => Ok(cell::window::Calendar::Month),
+        _ => Err(error!("Unknown window: {} (use day, week, or month)", arg)),
+    }
+}
+
+// Parses the milliseconds given to an option like `MAXWAIT` or `TIMEOUT`,
+// which can't be negative.
+fn parse_milliseconds(option: &str, arg: &str) -> Result<time::Duration, CellError> {
+    let ms = parse_i64(arg)?;
+    if ms < 0 {
+        return Err(error!("{} can't be negative: {}", option, ms));
+    }
+    Ok(time::Duration::milliseconds(ms))
+}
+
+// Parses an offset from UTC like `+05:30` or `-08:00`.
+fn parse_offset(arg: &str) -> Result<time::UtcOffset, CellError> {
+    let invalid = || error!("Couldn't parse as UTC offset: {}", arg);
+
+    let (sign, rest) = match arg.as_bytes().first() {
+        Some(b'+') => (1, &arg[1..]),
+        Some(b'-') => (-1, &arg[1..]),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes) = rest.split_once(':').ok_or_else(invalid)?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return Err(invalid());
+    }
+
+    let hours = hours.parse::<i8>().map_err(|_| invalid())?;
+    let minutes = minutes.parse::<i8>().map_err(|_| invalid())?;
+    time::UtcOffset::from_hms(sign * hours, sign * minutes, 0).map_err(|_| invalid())
+}
+
+// Parses the arguments common to CL.THROTTLE, CL.PEEK, CL.RESERVE, CL.WAIT,
+// and CL.REFUND: a key, followed by either a limit's parameters or
+// `POLICY <name>`, and then an optional quantity. The parameters are returned
+// as given (in the same form as a policy) so that algorithms other than GCRA
+// can use them. Returns None if the number of arguments is wrong.
+fn parse_limit(
+    args: &[&str],
+) -> Result<Option<(String, policy::Policy, i64)>, CellError> {
+    parse_limit_with_default(args, Some(config::default_quantity()))
+}
+
+// Like `parse_limit`, but the quantity can only be left out if there's a
+// default to use in its place.
+fn parse_limit_with_default(
+    args: &[&str],
+    default_quantity: Option<i64>,
+) -> Result<Option<(String, policy::Policy, i64)>, CellError> {
+    let (params, rest) = match args.get(2) {
+        Some(arg) if arg.eq_ignore_ascii_case("policy") && args.len() >= 4 => {
+            let policy = policy::get(args[3])
+                .ok_or_else(|| error!("Unknown policy: {}", args[3]))?;
+            (policy, &args[4..])
+        }
+        Some(_) if args.len() >= 5 => (parse_params(&args[2..5])?, &args[5..]),
+        _ => return Ok(None),
+    };
+
+    let quantity = match (rest, default_quantity) {
+        ([], Some(n)) => n,
+        ([n], _) => parse_i64(n)?,
+        _ => return Ok(None),
+    };
+
+    Ok(Some((config::prefixed(args[1])?, params, quantity)))
+}
+
+// Parses the `<max_burst> <count per period> <period>` triple that's common to
+// all commands that take a rate limit's parameters inline.
+fn parse_params(args: &[&str]) -> Result<policy::Policy, CellError> {
+    Ok(policy::Policy {
+        max_burst: parse_i64(args[0])?,
+        count: parse_i64(args[1])?,
+        period: parse_period(args[2])?,
+    })
+}
+
+// Formats a period so that it can be parsed back by `parse_period`. Whole
+// seconds are formatted as a plain number, and anything else in milliseconds.
+fn format_period(period: time::Duration) -> String {
+    if period.subsec_nanoseconds() == 0 {
+        return period.whole_seconds().to_string();
+    }
+
+    let ns = period.whole_nanoseconds();
+    let fraction = format!("{:06}", (ns % 1_000_000).abs());
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}ms", ns / 1_000_000)
+    } else {
+        format!("{}.{}ms", ns / 1_000_000, fraction)
+    }
+}
+
+// Parses a period like `250ms`, `1.5s`, `2m`, `1h`, or `1d`. A number without
+// a unit is in seconds, which is all that periods used to be given in.
+fn parse_period(arg: &str) -> Result<time::Duration, CellError> {
+    let invalid = || error!("Couldn't parse as period: {}", arg);
+
+    let split = arg
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(arg.len());
+    let (number, unit) = arg.split_at(split);
+    let unit_ns: i128 = match unit {
+        "ms" => 1_000_000,
+        "" | "s" => 1_000_000_000,
+        "m" => 60 * 1_000_000_000,
+        "h" => 60 * 60 * 1_000_000_000,
+        "d" => 24 * 60 * 60 * 1_000_000_000,
+        _ => return Err(invalid()),
+    };
+
+    // Stay away from floating point so that something like `0.1s` is
+    // exactly 100 ms.
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty()
+        || !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+
+    let mantissa = format!("{whole}{fraction}")
+        .parse::<i128>()
+        .map_err(|_| invalid())?;
+    let ns = 10i128
+        .checked_pow(fraction.len() as u32)
+        .and_then(|divisor| Some(mantissa.checked_mul(unit_ns)? / divisor))
+        .ok_or_else(invalid)?;
+
+    i64::try_from(ns)
+        .map(time::Duration::nanoseconds)
+        .map_err(|_| invalid())
+}
+
+// Lets anyone listening know that a request was throttled, with a keyspace
+// event and a message to a channel if either is configured. The message is a
+// JSON object with the key, the quantity that was asked for, and retry_after
+// in milliseconds.
+fn notify_throttled(
+    r: &redis::Redis,
+    key: &str,
+    quantity: i64,
+    result: &cell::RateLimitResult,
+) -> Result<(), CellError> {
+    if config::throttle_events() {
+        r.notify_keyspace_event("cl.throttled", key)?;
+    }
+    if let Some(channel) = config::throttle_channel() {
+        let message = format!(
+            "{{\"key\":{},\"quantity\":{},\"retry_after\":{}}}",
+            json_string(key),
+            quantity,
+            Units::Milliseconds.round_up(result.retry_after)
+        );
+        r.publish(&channel, &message)?;
+    }
+    Ok(())
+}
+
+// Quotes a string for JSON, escaping anything that JSON requires to be.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Replies with the standard array of rate limiting results produced by
+// CL.THROTTLE and its relatives.
+fn reply_rate_limit_result(
+    r: &redis::Redis,
+    throttled: bool,
+    rate_limit_result: &cell::RateLimitResult,
+    units: Units,
+    granted: Option<i64>,
+) -> Result<(), CellError> {
+    let retry_after = units.round_up(rate_limit_result.retry_after);
+    let reset_after = units.round_up(rate_limit_result.reset_after);
+
+    // Partial grants add how much of the request was granted on the end.
+    let len = if granted.is_some() { 6 } else { 5 };
+
+    // RESP3 clients get the same values, but with names so that they don't
+    // have to remember which position is which.
+    if r.is_resp3() {
+        r.reply_map(len)?;
+        r.reply_simple_string("limited")?;
+        r.reply_bool(throttled)?;
+        r.reply_simple_string("limit")?;
+        r.reply_integer(rate_limit_result.limit)?;
+        r.reply_simple_string("remaining")?;
+        r.reply_integer(rate_limit_result.remaining)?;
+        r.reply_simple_string("retry_after")?;
+        r.reply_integer(retry_after)?;
+        r.reply_simple_string("reset_after")?;
+        r.reply_integer(reset_after)?;
+        if let Some(granted) = granted {
+            r.reply_simple_string("granted")?;
+            r.reply_integer(granted)?;
+        }
+        return Ok(());
+    }
+
+    // Reply with an array containing rate limiting results. Note that
+    // Redis' support for interesting data types is quite weak, so we have
+    // to jam a few square pegs into round holes. It's a little messy, but
+    // the interface comes out as pretty workable.
+    r.reply_array(len)?;
+    r.reply_integer(if throttled { 1 } else { 0 })?;
+    r.reply_integer(rate_limit_result.limit)?;
+    r.reply_integer(rate_limit_result.remaining)?;
+    r.reply_integer(retry_after)?;
+    r.reply_integer(reset_after)?;
+    if let Some(granted) = granted {
+        r.reply_integer(granted)?;
+    }
+
+    Ok(())
+}
+
+// A calendar period that a limit's windows are aligned to, along with the UTC
+// offset of the timezone that they start at midnight in.
+type CalendarWindow = (cell::window::Calendar, time::UtcOffset);
+
+// Algorithms that CL.THROTTLE can enforce a limit with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Algo {
+    Gcra,
+    SlidingLog,
+    SlidingCounter,
+}
+
+// Units that the times in a rate limiting reply can be given in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Units {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+}
+
+impl Units {
+    fn parse(units: &str) -> Result<Units, CellError> {
+        match units.to_ascii_lowercase().as_str() {
+            "s" => Ok(Units::Seconds),
+            "ms" => Ok(Units::Milliseconds),
+            "us" => Ok(Units::Microseconds),
+            _ => Err(error!("Unknown units: {} (use s, ms, or us)", units)),
+        }
+    }
+
+    // Converts a time to these units. If it has a partial component, put it up
+    // to the next full unit because otherwise a fast-paced caller could try
+    // again too early. A negative time is a -1 sentinel (e.g. for a
+    // retry_after when a request was allowed) and stays -1 in any units.
+    fn round_up(self, duration: time::Duration) -> i64 {
+        if duration < time::Duration::ZERO {
+            return -1;
+        }
+
+        match self {
+            Units::Seconds => round_up_seconds(duration),
+            Units::Milliseconds => div_ceil(duration.whole_nanoseconds(), 1_000_000),
+            Units::Microseconds => div_ceil(duration.whole_nanoseconds(), 1_000),
+        }
+    }
+}
+
+fn div_ceil(n: i128, d: i128) -> i64 {
+    ((n + d - 1) / d) as i64
+}
+
+// If a time has a partial component, put it up to the next full second because
+// otherwise a fast-paced caller could try again too early.
+fn round_up_seconds(duration: time::Duration) -> i64 {
+    let mut seconds = duration.whole_seconds();
+    if duration.subsec_nanoseconds() > 0 {
+        seconds += 1
+    }
+    seconds
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate time;
+
+    use crate::*;
+
+    #[test]
+    fn it_parses_periods() {
+        assert_eq!(time::Duration::seconds(60), parse_period("60").unwrap());
+        assert_eq!(
+            time::Duration::milliseconds(1500),
+            parse_period("1.5").unwrap()
+        );
+        assert_eq!(
+            time::Duration::milliseconds(250),
+            parse_period("250ms").unwrap()
+        );
+        assert_eq!(
+            time::Duration::milliseconds(1500),
+            parse_period("1.5s").unwrap()
+        );
+        assert_eq!(
+            time::Duration::milliseconds(100),
+            parse_period("0.1s").unwrap()
+        );
+        assert_eq!(time::Duration::minutes(2), parse_period("2m").unwrap());
+        assert_eq!(time::Duration::hours(1), parse_period("1h").unwrap());
+        assert_eq!(time::Duration::days(1), parse_period("1d").unwrap());
+        assert_eq!(
+            time::Duration::microseconds(500),
+            parse_period(".5ms").unwrap()
+        );
+
+        for invalid in [
+            "",
+            "s",
+            ".",
+            "-1",
+            "1.2.3",
+            "1w",
+            "1 s",
+            "1S",
+            "99999999999d",
+        ] {
+            assert_eq!(
+                format!("Store error: Couldn't parse as period: {invalid}"),
+                parse_period(invalid).unwrap_err().to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn it_parses_offsets() {
+        assert_eq!(
+            time::UtcOffset::from_hms(5, 30, 0).unwrap(),
+            parse_offset("+05:30").unwrap()
+        );
+        assert_eq!(
+            time::UtcOffset::from_hms(-8, 0, 0).unwrap(),
+            parse_offset("-08:00").unwrap()
+        );
+        assert_eq!(time::UtcOffset::UTC, parse_offset("+00:00").unwrap());
+
+        for arg in ["", "05:30", "+5:30", "+05", "+0530", "+26:00", "+05:60"] {
+            assert!(parse_offset(arg).is_err(), "{arg} should be invalid");
+        }
+    }
+
+    #[test]
+    fn it_splits_options_from_limits() {
+        let empty: &[&str] = &[];
+        assert_eq!(
+            (
+                &["cl.throttle", "foo", "1", "2", "3", "5"][..],
+                &["UNITS", "ms", "PARTIAL"][..]
+            ),
+            split_options(&[
+                "cl.throttle",
+                "foo",
+                "1",
+                "2",
+                "3",
+                "5",
+                "UNITS",
+                "ms",
+                "PARTIAL"
+            ])
+        );
+        assert_eq!(
+            (&["cl.throttle", "foo", "1", "2", "3"][..], &["partial"][..]),
+            split_options(&["cl.throttle", "foo", "1", "2", "3", "partial"])
+        );
+
+        // Policies are positional, so they can be named like options.
+        assert_eq!(
+            (
+                &["cl.throttle", "foo", "POLICY", "units"][..],
+                &["PARTIAL"][..]
+            ),
+            split_options(&["cl.throttle", "foo", "POLICY", "units", "PARTIAL"])
+        );
+        assert_eq!(
+            (&["cl.throttle", "foo", "POLICY", "partial", "2"][..], empty),
+            split_options(&["cl.throttle", "foo", "POLICY", "partial", "2"])
+        );
+
+        assert_eq!(
+            (
+                &[
+                    "cl.throttlemulti",
+                    "a",
+                    "1",
+                    "2",
+                    "3",
+                    "b",
+                    "4",
+                    "5",
+                    "6",
+                    "2"
+                ][..],
+                &["UNITS", "ms"][..]
+            ),
+            split_multi_options(&[
+                "cl.throttlemulti",
+                "a",
+                "1",
+                "2",
+                "3",
+                "b",
+                "4",
+                "5",
+                "6",
+                "2",
+                "UNITS",
+                "ms"
+            ])
+        );
+    }
+
+    #[test]
+    fn it_parses_options_in_any_order() {
+        let accepted = ["algo", "partial", "units", "window"];
+        for args in [
+            &[
+                "PARTIAL", "UNITS", "ms", "WINDOW", "week", "OFFSET", "+05:30",
+            ][..],
+            &[
+                "units", "ms", "window", "week", "offset", "+05:30", "partial",
+            ][..],
+            &[
+                "WINDOW", "week", "OFFSET", "+05:30", "PARTIAL", "UNITS", "ms",
+            ][..],
+        ] {
+            let options = parse_options(args, &accepted).unwrap().unwrap();
+            assert!(options.partial);
+            assert_eq!(Units::Milliseconds, options.units);
+            assert_eq!(Algo::Gcra, options.algo);
+            assert_eq!(
+                Some((
+                    cell::window::Calendar::Week,
+                    time::UtcOffset::from_hms(5, 30, 0).unwrap()
+                )),
+                options.calendar
+            );
+        }
+
+        let options = parse_options(&["ALGO", "sliding-log", "PARTIAL"], &accepted)
+            .unwrap()
+            .unwrap();
+        assert_eq!(Algo::SlidingLog, options.algo);
+        assert!(options.partial);
+        assert!(options.calendar.is_none());
+
+        // Options that aren't accepted, are missing their values, or are
+        // given twice aren't parsed.
+        assert!(
+            parse_options(&["MAXWAIT", "5"], &accepted)
+                .unwrap()
+                .is_none()
+        );
+        assert!(parse_options(&["UNITS"], &accepted).unwrap().is_none());
+        assert!(
+            parse_options(&["PARTIAL", "partial"], &accepted)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            parse_options(&["WINDOW", "day", "OFFSET"], &accepted)
+                .unwrap()
+                .is_none()
+        );
+        assert!(parse_options(&["UNITS", "h"], &accepted).is_err());
+    }
+
+    #[test]
+    fn it_rounds_up_times() {
+        let cases = [
+            (Units::Seconds, time::Duration::ZERO, 0),
+            (Units::Seconds, time::Duration::microseconds(500), 1),
+            (Units::Seconds, time::Duration::seconds(1), 1),
+            (Units::Seconds, time::Duration::new(1, 1), 2),
+            (Units::Milliseconds, time::Duration::nanoseconds(1), 1),
+            (Units::Milliseconds, time::Duration::milliseconds(2), 2),
+            (Units::Microseconds, time::Duration::nanoseconds(1001), 2),
+            (Units::Seconds, time::Duration::seconds(-1), -1),
+            (Units::Microseconds, time::Duration::seconds(-1), -1),
+        ];
+        for (units, duration, expected) in cases {
+            assert_eq!(expected, units.round_up(duration), "{duration}");
+        }
+    }
+
+    #[test]
+    fn it_formats_periods() {
+        for period in ["60", "250ms", "1500ms", "0.5ms", "0.000001ms"] {
+            assert_eq!(period, format_period(parse_period(period).unwrap()));
+        }
+    }
+
+    #[test]
+    fn it_quotes_json_strings() {
+        assert_eq!(r#""user123""#, json_string("user123"));
+        assert_eq!(r#""a\"b\\c""#, json_string(r#"a"b\c"#));
+        assert_eq!(r#""a\nb\u0000""#, json_string("a\nb\0"));
+    }
+}
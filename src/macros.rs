@@ -9,12 +9,12 @@ macro_rules! error {
 
 macro_rules! log_debug {
     ($logger:expr, $target:expr) => {
-        if cfg!(debug_assertions) {
+        if crate::config::debug() {
             $logger.log_debug($target)
         }
     };
     ($logger:expr, $target:expr, $($arg:tt)*) => {
-        if cfg!(debug_assertions) {
+        if crate::config::debug() {
             $logger.log_debug(format!($target, $($arg)+).as_str())
         }
     }
@@ -0,0 +1,144 @@
+// A registry of named rate limiting policies.
+//
+// Policies let clients refer to a limit by name instead of passing its
+// parameters with every command, so that a limit can be changed in one place.
+// They're held in memory, replicated as the commands that change them, and
+// persisted as auxiliary data of the module's data type in RDB files.
+
+use crate::cell;
+use crate::redis;
+use crate::redis::raw;
+use libc::c_int;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// Version of the encoding of policies in RDB auxiliary data. Redis passes us
+// the encoding version of the data type that they're saved along with.
+const ENCODING_VERSION: c_int = 0;
+
+static POLICIES: Mutex<BTreeMap<String, Policy>> = Mutex::new(BTreeMap::new());
+
+/// `Policy` holds a rate limit's parameters as they were given to
+/// `CL.POLICY.SET` (or inline to a command like `CL.THROTTLE`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Policy {
+    pub max_burst: i64,
+    pub count: i64,
+    pub period: time::Duration,
+}
+
+impl Policy {
+    pub fn quota(&self) -> cell::RateQuota {
+        cell::RateQuota {
+            max_burst: self.max_burst,
+            max_rate: cell::Rate::per_period(self.count, self.period),
+        }
+    }
+
+    /// Gets the quota for a sliding window limiter, which allows exactly
+    /// `count` requests in any `period`. The maximum burst doesn't apply.
+    pub fn window_quota(&self) -> cell::window::WindowQuota {
+        cell::window::WindowQuota {
+            limit: self.count,
+            window: self.period,
+        }
+    }
+}
+
+/// Removes the named policy. Returns whether it existed.
+pub fn delete(name: &str) -> bool {
+    POLICIES.lock().unwrap().remove(name).is_some()
+}
+
+pub fn get(name: &str) -> Option<Policy> {
+    POLICIES.lock().unwrap().get(name).copied()
+}
+
+/// Adds the named policy, replacing any existing one by the same name.
+pub fn set(name: &str, policy: Policy) {
+    POLICIES.lock().unwrap().insert(name.to_string(), policy);
+}
+
+/// Loads policies from RDB auxiliary data, replacing any that were already
+/// registered.
+pub extern "C" fn aux_load(
+    rdb: *mut raw::RedisModuleIO,
+    encver: c_int,
+    _when: c_int,
+) -> raw::Status {
+    if encver != ENCODING_VERSION {
+        return raw::Status::Err;
+    }
+
+    let io = redis::RedisIO::new(rdb);
+    let mut policies = BTreeMap::new();
+    for _ in 0..io.load_unsigned() {
+        let name = match io.load_string() {
+            Ok(name) => name,
+            Err(_) => return raw::Status::Err,
+        };
+        let policy = Policy {
+            max_burst: io.load_signed(),
+            count: io.load_signed(),
+            period: time::Duration::nanoseconds(io.load_signed()),
+        };
+        policies.insert(name, policy);
+    }
+
+    *POLICIES.lock().unwrap() = policies;
+    raw::Status::Ok
+}
+
+/// Saves all policies as RDB auxiliary data.
+pub extern "C" fn aux_save(rdb: *mut raw::RedisModuleIO, _when: c_int) {
+    let io = redis::RedisIO::new(rdb);
+    let policies = POLICIES.lock().unwrap();
+    io.save_unsigned(policies.len() as u64);
+    for (name, policy) in policies.iter() {
+        io.save_string(name);
+        io.save_signed(policy.max_burst);
+        io.save_signed(policy.count);
+        io.save_signed(policy.period.whole_nanoseconds() as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate time;
+
+    use crate::cell;
+    use crate::policy::*;
+
+    #[test]
+    fn it_manages_policies() {
+        let policy = Policy {
+            max_burst: 15,
+            count: 30,
+            period: time::Duration::seconds(60),
+        };
+
+        assert_eq!(None, get("it_manages_policies"));
+        set("it_manages_policies", policy);
+        assert_eq!(Some(policy), get("it_manages_policies"));
+
+        assert!(delete("it_manages_policies"));
+        assert!(!delete("it_manages_policies"));
+        assert_eq!(None, get("it_manages_policies"));
+    }
+
+    #[test]
+    fn it_builds_quotas() {
+        let policy = Policy {
+            max_burst: 15,
+            count: 30,
+            period: time::Duration::seconds(60),
+        };
+        assert_eq!(
+            cell::RateQuota {
+                max_burst: 15,
+                max_rate: cell::Rate::per_period(30, time::Duration::seconds(60)),
+            },
+            policy.quota()
+        );
+    }
+}
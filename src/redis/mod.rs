@@ -3,7 +3,7 @@
 pub mod raw;
 
 use crate::error::CellError;
-use libc::{c_int, c_long, c_longlong, size_t};
+use libc::{c_double, c_int, c_long, c_longlong, c_void, size_t};
 use std::ptr;
 use std::string;
 
@@ -46,6 +46,7 @@ pub trait Command {
 impl dyn Command {
     /// Provides a basic wrapper for a command's implementation that parses
     /// arguments to Rust data types and handles the OK/ERR reply back to Redis.
+    /// Every run is recorded in the module's statistics.
     pub fn harness(
         command: &dyn Command,
         ctx: *mut raw::RedisModuleCtx,
@@ -55,7 +56,10 @@ impl dyn Command {
         let r = Redis { ctx };
         let args = parse_args(argv, argc).unwrap();
         let str_args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
-        match command.run(r, str_args.as_slice()) {
+        let start = std::time::Instant::now();
+        let result = command.run(r, str_args.as_slice());
+        crate::stats::record_call(command.name(), start.elapsed(), result.is_ok());
+        match result {
             Ok(_) => raw::Status::Ok,
             Err(e) => {
                 raw::reply_with_error(ctx, format!("Cell error: {e}\0").as_ptr());
@@ -72,6 +76,97 @@ pub struct Redis {
 }
 
 impl Redis {
+    /// Wraps a context that Redis has passed to a callback (as opposed to a
+    /// command, which gets one through `Command::harness`).
+    pub fn new(ctx: *mut raw::RedisModuleCtx) -> Redis {
+        Redis { ctx }
+    }
+
+    /// Blocks the client that's running the command until it's unblocked
+    /// with `BlockedClient::unblock`. Once it is, `reply_callback` is called
+    /// to reply to it with the private data that it was unblocked with, and
+    /// `free_privdata` to free that data (even if the client's disconnected
+    /// in the meantime).
+    pub fn block_client(
+        &self,
+        reply_callback: raw::RedisModuleCmdFunc,
+        free_privdata: raw::RedisModuleFreePrivDataFunc,
+    ) -> BlockedClient {
+        BlockedClient {
+            bc_inner: raw::block_client(
+                self.ctx,
+                Some(reply_callback),
+                None,
+                Some(free_privdata),
+                0,
+            ),
+        }
+    }
+
+    /// Gets the private data that a client was unblocked with. Only valid
+    /// from within the reply callback given to `block_client`.
+    pub fn blocked_client_private_data<T>(&self) -> Option<&T> {
+        let data = raw::get_blocked_client_private_data(self.ctx) as *const T;
+        unsafe { data.as_ref() }
+    }
+
+    /// Whether the client that's running the command can be blocked. Clients
+    /// can't be blocked from within a transaction or a script.
+    pub fn can_block(&self) -> bool {
+        let flags = raw::get_context_flags(self.ctx);
+        flags
+            & (raw::REDISMODULE_CTX_FLAGS_LUA
+                | raw::REDISMODULE_CTX_FLAGS_MULTI
+                | raw::REDISMODULE_CTX_FLAGS_DENY_BLOCKING)
+            == 0
+    }
+
+    /// Whether the command being run was sent by the server itself, either
+    /// from a primary over the replication link or from the AOF while it's
+    /// being loaded, rather than by a client. Only on servers that don't flag
+    /// replicated commands is every command run on a replica counted as one,
+    /// since a client can't be told apart from the primary there.
+    pub fn is_replicated(&self) -> bool {
+        let mut server_flags =
+            raw::REDISMODULE_CTX_FLAGS_REPLICATED | raw::REDISMODULE_CTX_FLAGS_LOADING;
+        let flags_replicated = raw::get_context_flags_all()
+            .is_some_and(|all| all & raw::REDISMODULE_CTX_FLAGS_REPLICATED != 0);
+        if !flags_replicated {
+            server_flags |= raw::REDISMODULE_CTX_FLAGS_REPLICA;
+        }
+        raw::get_context_flags(self.ctx) & server_flags != 0
+    }
+
+    /// Creates a timer that calls back with the given data after a period.
+    /// The callback takes ownership of the data. If the timer couldn't be
+    /// created, the data is handed back.
+    pub fn create_timer<T>(
+        &self,
+        period: time::Duration,
+        callback: raw::RedisModuleTimerProc,
+        data: Box<T>,
+    ) -> Result<(), Box<T>> {
+        let data_inner = Box::into_raw(data);
+        match raw::create_timer(
+            self.ctx,
+            period.whole_milliseconds() as c_longlong,
+            callback,
+            data_inner as *mut c_void,
+        ) {
+            Some(_) => Ok(()),
+            None => Err(unsafe { Box::from_raw(data_inner) }),
+        }
+    }
+
+    /// Gets the ID of the currently selected database.
+    pub fn selected_db(&self) -> i32 {
+        raw::get_selected_db(self.ctx)
+    }
+
+    pub fn select_db(&self, db: i32) -> Result<(), CellError> {
+        handle_status(raw::select_db(self.ctx, db), "Could not select database")
+    }
+
     /// Calls a Redis command with arguments.
     ///
     /// WARNING: This method is not currently in use by the library. I thought I would be invoking
@@ -140,16 +235,9 @@ impl Redis {
         // Tell Redis that it's okay to replicate the command with the same
         // parameters out to replicas.
         //
-        // This is not strictly correct. Because the rate limiting function
-        // uses time as input, the replicas will end up with a slightly
-        // different result. However, given the domain of rate limiting, those
-        // results will be close enough for almost all purposes.
-        //
-        // A possible improvement is switching to `RedisModule_Replicate` and
-        // adding a new command (or maybe just new parameters) that allows
-        // injecting a timestamp. This would allow a master to transmit its
-        // exact time out to replicas which would make sure their results are
-        // identical.
+        // Note that this is only correct for deterministic commands. The rate
+        // limiting commands use time as input, so they replicate their effects
+        // with `replicate` instead.
         if let raw::Status::Err = raw::replicate_verbatim(self.ctx) {
             // Handle a possible error for hygiene, but the documentation specifically
             // states that the function always returns `REDISMODULE_OK`.
@@ -197,11 +285,17 @@ impl Redis {
     pub fn log_debug(&self, message: &str) {
         // Note that we log our debug messages as notice level in Redis. This
         // is so that they'll show up with default configuration. Our debug
-        // logging will get compiled out in a release build so this won't
-        // result in undue noise in production.
+        // logging is off by default in a release build (see the `debug`
+        // config) so this won't result in undue noise in production.
         self.log(LogLevel::Notice, message);
     }
 
+    /// Whether the client that's running the command is using the RESP3
+    /// protocol, which allows replies like maps and booleans.
+    pub fn is_resp3(&self) -> bool {
+        raw::get_context_flags(self.ctx) & raw::REDISMODULE_CTX_FLAGS_RESP3 != 0
+    }
+
     /// Opens a Redis key for read access.
     pub fn open_key(&self, key: &str) -> RedisKey {
         RedisKey::open(self.ctx, key)
@@ -212,6 +306,57 @@ impl Redis {
         RedisKeyWritable::open(self.ctx, key)
     }
 
+    /// Replicates a command with arguments to replicas and the AOF.
+    ///
+    /// Unlike `replicate_verbatim`, this allows a command to propagate its
+    /// effects rather than itself, so that replicas end up with exactly the
+    /// same state as the primary even when the command's result depends on
+    /// something like the current time.
+    pub fn replicate(&self, command: &str, args: &[&str]) -> Result<(), CellError> {
+        let redis_args: Vec<RedisString> =
+            args.iter().map(|s| self.create_string(s)).collect();
+        let raw_args: Vec<*mut raw::RedisModuleString> =
+            redis_args.iter().map(|s| s.str_inner).collect();
+
+        // Redis retains the strings that it needs, so ours are freed as
+        // usual once they go out of scope.
+        handle_status(
+            raw::replicate(
+                self.ctx,
+                format!("{command}\0").as_ptr(),
+                raw_args.as_ptr(),
+                raw_args.len(),
+            ),
+            "Could not replicate",
+        )
+    }
+
+    /// Emits a keyspace event on a key in the class of module key type events.
+    /// Subscribers only get it if notify-keyspace-events includes that class,
+    /// `d`.
+    pub fn notify_keyspace_event(&self, event: &str, key: &str) -> Result<(), CellError> {
+        let key_str = self.create_string(key);
+        handle_status(
+            raw::notify_keyspace_event(
+                self.ctx,
+                raw::REDISMODULE_NOTIFY_MODULE,
+                format!("{event}\0").as_ptr(),
+                key_str.str_inner,
+            ),
+            "Could not notify keyspace event",
+        )
+    }
+
+    /// Publishes a message to a channel's subscribers, like PUBLISH.
+    pub fn publish(&self, channel: &str, message: &str) -> Result<(), CellError> {
+        let channel_str = self.create_string(channel);
+        let message_str = self.create_string(message);
+        handle_status(
+            raw::publish_message(self.ctx, channel_str.str_inner, message_str.str_inner),
+            "Could not publish message",
+        )
+    }
+
     /// Tells Redis to replicate the command to replicas an AOF as is.
     pub fn replicate_verbatim(&self) -> Result<(), CellError> {
         // Handle a possible error for hygiene, but the documentation specifically
@@ -233,6 +378,26 @@ impl Redis {
         )
     }
 
+    /// Replies with a boolean, or with 0 or 1 on a server too old to have
+    /// them.
+    pub fn reply_bool(&self, b: bool) -> Result<(), CellError> {
+        match raw::reply_with_bool(self.ctx, b as c_int) {
+            Some(status) => handle_status(status, "Could not reply with bool"),
+            None => self.reply_integer(b as i64),
+        }
+    }
+
+    pub fn reply_double(&self, d: f64) -> Result<(), CellError> {
+        handle_status(
+            raw::reply_with_double(self.ctx, d as c_double),
+            "Could not reply with double",
+        )
+    }
+
+    pub fn reply_error(&self, message: &str) {
+        raw::reply_with_error(self.ctx, format!("{message}\0").as_ptr());
+    }
+
     pub fn reply_integer(&self, integer: i64) -> Result<(), CellError> {
         handle_status(
             raw::reply_with_long_long(self.ctx, integer as c_longlong),
@@ -240,6 +405,30 @@ impl Redis {
         )
     }
 
+    /// Tells Redis that we're about to reply with a (Redis) map.
+    ///
+    /// Used by invoking once with the expected number of entries and then
+    /// replying with a key followed by its value for each of them. Clients
+    /// using RESP2, and any client of a server too old to have maps, get an
+    /// array of twice the length instead.
+    pub fn reply_map(&self, len: i64) -> Result<(), CellError> {
+        match raw::reply_with_map(self.ctx, len as c_long) {
+            Some(status) => handle_status(status, "Could not reply with map"),
+            None => self.reply_array(len * 2),
+        }
+    }
+
+    pub fn reply_null(&self) -> Result<(), CellError> {
+        handle_status(raw::reply_with_null(self.ctx), "Could not reply with null")
+    }
+
+    pub fn reply_simple_string(&self, message: &str) -> Result<(), CellError> {
+        handle_status(
+            raw::reply_with_simple_string(self.ctx, format!("{message}\0").as_ptr()),
+            "Could not reply with simple string",
+        )
+    }
+
     pub fn reply_string(&self, message: &str) -> Result<(), CellError> {
         let redis_str = self.create_string(message);
         handle_status(
@@ -249,6 +438,43 @@ impl Redis {
     }
 }
 
+/// `BlockedClient` is a handle to a client blocked by `Redis::block_client`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BlockedClient {
+    bc_inner: *mut raw::RedisModuleBlockedClient,
+}
+
+impl BlockedClient {
+    /// Wraps a blocked client that Redis has passed to a callback.
+    pub fn new(bc_inner: *mut raw::RedisModuleBlockedClient) -> BlockedClient {
+        BlockedClient { bc_inner }
+    }
+
+    /// A value that uniquely identifies the blocked client for as long as
+    /// it's blocked.
+    pub fn id(&self) -> usize {
+        self.bc_inner as usize
+    }
+
+    /// Sets a callback for when the client disconnects while it's blocked. It
+    /// still needs to be unblocked afterwards.
+    pub fn set_disconnect_callback(&self, callback: raw::RedisModuleDisconnectFunc) {
+        raw::set_disconnect_callback(self.bc_inner, callback)
+    }
+
+    /// Unblocks the client, handing it the given private data to reply with.
+    /// The handle mustn't be used again afterwards.
+    pub fn unblock<T>(self, privdata: T) -> Result<(), CellError> {
+        handle_status(
+            raw::unblock_client(
+                self.bc_inner,
+                Box::into_raw(Box::new(privdata)) as *mut c_void,
+            ),
+            "Could not unblock client",
+        )
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum KeyMode {
     Read,
@@ -280,6 +506,10 @@ impl RedisKey {
         self.key_inner == null_key
     }
 
+    pub fn key_type(&self) -> raw::KeyType {
+        raw::key_type(self.key_inner)
+    }
+
     pub fn read(&self) -> Result<Option<String>, CellError> {
         let val = if self.is_null() {
             None
@@ -288,6 +518,16 @@ impl RedisKey {
         };
         Ok(val)
     }
+
+    /// Gets the value of a key holding a module data type, or `None` if the
+    /// key is empty. Errors if the key holds anything other than the given
+    /// type.
+    pub fn value<T>(
+        &self,
+        ty: *mut raw::RedisModuleType,
+    ) -> Result<Option<&T>, CellError> {
+        read_value(self.key_inner, ty)
+    }
 }
 
 impl Drop for RedisKey {
@@ -323,26 +563,42 @@ impl RedisKeyWritable {
         }
     }
 
+    /// Deletes the key along with its value.
+    pub fn delete(&self) -> Result<(), CellError> {
+        match raw::delete_key(self.key_inner) {
+            raw::Status::Ok => Ok(()),
+            raw::Status::Err => Err(error!("Error while deleting key")),
+        }
+    }
+
     /// Detects whether the value stored in a Redis key is empty.
     ///
     /// Note that an empty key can be reliably detected by looking for a null
     /// as you open the key in read mode, but when asking for write Redis
     /// returns a non-null pointer to allow us to write to even an empty key,
-    /// so we have to check the key's value instead.
+    /// so we have to check the key's type instead.
     pub fn is_empty(&self) -> Result<bool, CellError> {
-        match self.read()? {
-            Some(s) => match s.as_str() {
-                "" => Ok(true),
-                _ => Ok(false),
-            },
-            _ => Ok(false),
-        }
+        Ok(self.key_type() == raw::KeyType::Empty)
+    }
+
+    pub fn key_type(&self) -> raw::KeyType {
+        raw::key_type(self.key_inner)
     }
 
     pub fn read(&self) -> Result<Option<String>, CellError> {
         Ok(Some(read_key(self.key_inner)?))
     }
 
+    /// Gets the value of a key holding a module data type, or `None` if the
+    /// key is empty. Errors if the key holds anything other than the given
+    /// type.
+    pub fn value<T>(
+        &self,
+        ty: *mut raw::RedisModuleType,
+    ) -> Result<Option<&T>, CellError> {
+        read_value(self.key_inner, ty)
+    }
+
     pub fn set_expire(&self, expire: time::Duration) -> Result<(), CellError> {
         match raw::set_expire(self.key_inner, expire.whole_milliseconds() as i64) {
             raw::Status::Ok => Ok(()),
@@ -353,6 +609,44 @@ impl RedisKeyWritable {
         }
     }
 
+    /// Sets the key to a value of a module data type, handing ownership of
+    /// the value over to Redis. It'll be freed by the type's free callback.
+    ///
+    /// Note that like a `SET`, this clears any expiry on the key.
+    pub fn set_value<T>(
+        &self,
+        ty: *mut raw::RedisModuleType,
+        val: T,
+    ) -> Result<(), CellError> {
+        let val_inner = Box::into_raw(Box::new(val));
+        match raw::module_type_set_value(self.key_inner, ty, val_inner as *mut c_void) {
+            raw::Status::Ok => Ok(()),
+            raw::Status::Err => {
+                // Redis didn't take ownership, so make sure the value is
+                // dropped.
+                drop(unsafe { Box::from_raw(val_inner) });
+                Err(error!("Error while setting key value"))
+            }
+        }
+    }
+
+    /// Sets the key to expire at an absolute Unix time in milliseconds.
+    ///
+    /// Servers that are too old to support absolute expiries get a relative
+    /// one instead, which will be off from the given time by however long it
+    /// takes to get here.
+    pub fn set_expire_at(&self, unix_ms: i64) -> Result<(), CellError> {
+        match raw::set_abs_expire(self.key_inner, unix_ms as c_longlong) {
+            raw::Status::Ok => Ok(()),
+            raw::Status::Err => {
+                let now_ms = (time::OffsetDateTime::now_utc().unix_timestamp_nanos()
+                    / 1_000_000) as i64;
+                // A time that's already passed expires the key right away.
+                self.set_expire(time::Duration::milliseconds((unix_ms - now_ms).max(0)))
+            }
+        }
+    }
+
     pub fn write(&self, val: &str) -> Result<(), CellError> {
         let val_str = RedisString::create(self.ctx, val);
         match raw::string_set(self.key_inner, val_str.str_inner) {
@@ -369,6 +663,242 @@ impl Drop for RedisKeyWritable {
     }
 }
 
+/// `RedisIO` is an abstraction over the handle that Redis passes to a module
+/// data type's callbacks while it's loading or saving an RDB file, or
+/// rewriting an AOF.
+pub struct RedisIO {
+    io_inner: *mut raw::RedisModuleIO,
+}
+
+impl RedisIO {
+    pub fn new(io_inner: *mut raw::RedisModuleIO) -> RedisIO {
+        RedisIO { io_inner }
+    }
+
+    /// Emits a command that takes a key followed by integer arguments into
+    /// the AOF being rewritten.
+    pub fn emit_aof(
+        &self,
+        command: &str,
+        key: *mut raw::RedisModuleString,
+        args: &[i64],
+    ) {
+        // There's no context during a rewrite, but strings can be created
+        // without one as long as they're freed without one too.
+        let redis_args: Vec<RedisString> = args
+            .iter()
+            .map(|n| RedisString::create(ptr::null_mut(), n.to_string().as_str()))
+            .collect();
+        let raw_args: Vec<*mut raw::RedisModuleString> =
+            redis_args.iter().map(|s| s.str_inner).collect();
+
+        raw::emit_aof(
+            self.io_inner,
+            format!("{command}\0").as_ptr(),
+            key,
+            raw_args.as_ptr(),
+            raw_args.len(),
+        );
+    }
+
+    pub fn load_signed(&self) -> i64 {
+        raw::load_signed(self.io_inner)
+    }
+
+    pub fn load_string(&self) -> Result<String, CellError> {
+        let mut length: size_t = 0;
+        let bytes = raw::load_string_buffer(self.io_inner, &mut length);
+        let res = from_byte_string(bytes, length);
+        raw::free(bytes as *mut c_void);
+        Ok(res?)
+    }
+
+    pub fn load_unsigned(&self) -> u64 {
+        raw::load_unsigned(self.io_inner)
+    }
+
+    pub fn save_signed(&self, val: i64) {
+        raw::save_signed(self.io_inner, val)
+    }
+
+    pub fn save_string(&self, val: &str) {
+        raw::save_string_buffer(self.io_inner, val.as_ptr(), val.len())
+    }
+
+    pub fn save_unsigned(&self, val: u64) {
+        raw::save_unsigned(self.io_inner, val)
+    }
+}
+
+/// `RedisDigest` is an abstraction over the digest that Redis passes to a
+/// module data type's callback for `DEBUG DIGEST`.
+pub struct RedisDigest {
+    digest_inner: *mut raw::RedisModuleDigest,
+}
+
+impl RedisDigest {
+    pub fn new(digest_inner: *mut raw::RedisModuleDigest) -> RedisDigest {
+        RedisDigest { digest_inner }
+    }
+
+    pub fn add_integer(&self, val: i64) {
+        raw::digest_add_long_long(self.digest_inner, val as c_longlong)
+    }
+
+    /// Ends a sequence of added elements. Each value should be added as its
+    /// own sequence so that Redis can tell apart values that are ordered
+    /// differently.
+    pub fn end_sequence(&self) {
+        raw::digest_end_sequence(self.digest_inner)
+    }
+}
+
+/// `RedisInfo` is an abstraction over the context that Redis passes to the
+/// module's INFO callback, which fields are added to.
+pub struct RedisInfo {
+    info_inner: *mut raw::RedisModuleInfoCtx,
+}
+
+impl RedisInfo {
+    pub fn new(info_inner: *mut raw::RedisModuleInfoCtx) -> RedisInfo {
+        RedisInfo { info_inner }
+    }
+
+    /// Starts a new section. Fields added after this go into it. Redis
+    /// prefixes the section's name with the module's.
+    pub fn add_section(&self, name: &str) -> Result<(), CellError> {
+        handle_status(
+            raw::info_add_section(self.info_inner, format!("{name}\0").as_ptr()),
+            "Could not add info section",
+        )
+    }
+
+    /// Adds a field with an integer value to the current section, or to the
+    /// current dict field if one has been started.
+    pub fn add_integer(&self, field: &str, value: i64) -> Result<(), CellError> {
+        handle_status(
+            raw::info_add_field_long_long(
+                self.info_inner,
+                format!("{field}\0").as_ptr(),
+                value as c_longlong,
+            ),
+            "Could not add info field",
+        )
+    }
+
+    /// Starts a field whose value is a set of `key=value` pairs, which are
+    /// added as fields until `end_dict_field` is called.
+    pub fn begin_dict_field(&self, name: &str) -> Result<(), CellError> {
+        handle_status(
+            raw::info_begin_dict_field(self.info_inner, format!("{name}\0").as_ptr()),
+            "Could not begin info dict field",
+        )
+    }
+
+    pub fn end_dict_field(&self) -> Result<(), CellError> {
+        handle_status(
+            raw::info_end_dict_field(self.info_inner),
+            "Could not end info dict field",
+        )
+    }
+}
+
+/// `CommandFilter` is an abstraction over the context that Redis passes to a
+/// command filter, through which a command can be inspected and rewritten
+/// before it's run.
+pub struct CommandFilter {
+    fctx_inner: *mut raw::RedisModuleCommandFilterCtx,
+}
+
+impl CommandFilter {
+    pub fn new(fctx_inner: *mut raw::RedisModuleCommandFilterCtx) -> CommandFilter {
+        CommandFilter { fctx_inner }
+    }
+
+    /// Gets the argument at the given position, where the command's name is
+    /// at 0.
+    pub fn arg(&self, pos: usize) -> Result<String, CellError> {
+        let arg = raw::command_filter_arg_get(self.fctx_inner, pos as c_int);
+        if arg.is_null() {
+            return Err(error!("No filtered argument at position {}", pos));
+        }
+        Ok(manifest_redis_string(arg)?)
+    }
+
+    /// Gets the address of the client running the command, which is empty
+    /// for one connected through a Unix socket. `None` if the client isn't a
+    /// real connection, like the one that replays the AOF.
+    pub fn client_address(&self) -> Option<String> {
+        let mut info = raw::RedisModuleClientInfo {
+            version: raw::REDISMODULE_CLIENTINFO_VERSION,
+            flags: 0,
+            id: 0,
+            addr: [0; 46],
+            port: 0,
+            db: 0,
+        };
+        if raw::get_client_info_by_id(&mut info, self.client_id()) == raw::Status::Err {
+            return None;
+        }
+        let len = info
+            .addr
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(info.addr.len());
+        Some(String::from_utf8_lossy(&info.addr[..len]).into_owned())
+    }
+
+    pub fn client_id(&self) -> u64 {
+        raw::command_filter_get_client_id(self.fctx_inner)
+    }
+
+    /// Gets the name that the client running the command has given itself
+    /// with CLIENT SETNAME, if any.
+    pub fn client_name(&self) -> Option<String> {
+        take_string(raw::get_client_name_by_id(
+            ptr::null_mut(),
+            self.client_id(),
+        ))
+    }
+
+    /// Gets the ACL user that the client running the command is
+    /// authenticated as.
+    pub fn client_user_name(&self) -> Option<String> {
+        take_string(raw::get_client_user_name_by_id(
+            ptr::null_mut(),
+            self.client_id(),
+        ))
+    }
+
+    /// Replaces the whole command, including its name, with the given
+    /// arguments.
+    pub fn replace(&self, args: &[&str]) -> Result<(), CellError> {
+        for pos in (1..raw::command_filter_args_count(self.fctx_inner)).rev() {
+            handle_status(
+                raw::command_filter_arg_delete(self.fctx_inner, pos),
+                "Could not delete filtered argument",
+            )?;
+        }
+
+        // The filter takes ownership of the strings, so they're created
+        // without a context and never freed here.
+        for (pos, arg) in args.iter().enumerate() {
+            let arg = raw::create_string(
+                ptr::null_mut(),
+                format!("{arg}\0").as_ptr(),
+                arg.len(),
+            );
+            let status = if pos == 0 {
+                raw::command_filter_arg_replace(self.fctx_inner, 0, arg)
+            } else {
+                raw::command_filter_arg_insert(self.fctx_inner, pos as c_int, arg)
+            };
+            handle_status(status, "Could not replace filtered argument")?;
+        }
+        Ok(())
+    }
+}
+
 /// `RedisString` is an abstraction over a Redis string.
 ///
 /// Its primary function is to ensure the proper deallocation of resources when
@@ -426,7 +956,8 @@ fn manifest_redis_reply(
     }
 }
 
-fn manifest_redis_string(
+/// Copies the contents of a Redis string that's been passed to the module.
+pub fn manifest_redis_string(
     redis_str: *mut raw::RedisModuleString,
 ) -> Result<String, string::FromUtf8Error> {
     let mut length: size_t = 0;
@@ -434,7 +965,20 @@ fn manifest_redis_string(
     from_byte_string(bytes, length)
 }
 
-fn parse_args(
+// Copies a string that Redis has handed over ownership of and frees it, or
+// gives None if it's null.
+fn take_string(redis_str: *mut raw::RedisModuleString) -> Option<String> {
+    if redis_str.is_null() {
+        return None;
+    }
+    let s = manifest_redis_string(redis_str).ok();
+    raw::free_string(ptr::null_mut(), redis_str);
+    s
+}
+
+/// Copies the arguments that have been passed to a command, or to the module
+/// when it's loaded.
+pub fn parse_args(
     argv: *mut *mut raw::RedisModuleString,
     argc: c_int,
 ) -> Result<Vec<String>, string::FromUtf8Error> {
@@ -467,6 +1011,22 @@ fn read_key(key: *mut raw::RedisModuleKey) -> Result<String, string::FromUtf8Err
     )
 }
 
+fn read_value<'a, T>(
+    key: *mut raw::RedisModuleKey,
+    ty: *mut raw::RedisModuleType,
+) -> Result<Option<&'a T>, CellError> {
+    match raw::key_type(key) {
+        raw::KeyType::Empty => Ok(None),
+        raw::KeyType::Module if raw::module_type_get_type(key) == ty => {
+            let val = raw::module_type_get_value(key) as *const T;
+            Ok(Some(unsafe { &*val }))
+        }
+        _ => Err(error!(
+            "WRONGTYPE Operation against a key holding the wrong kind of value"
+        )),
+    }
+}
+
 fn to_raw_mode(mode: KeyMode) -> raw::KeyMode {
     match mode {
         KeyMode::Read => raw::KeyMode::READ,
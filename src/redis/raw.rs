@@ -4,12 +4,43 @@
 
 extern crate libc;
 
-use libc::c_int;
+use libc::{c_int, c_longlong, c_uint, c_void, size_t};
 
 // Rust can't link against C macros (#define) so we just redefine them here.
 // There's a ~0 chance that any of these will ever change so it's pretty safe.
 pub const REDISMODULE_APIVER_1: c_int = 1;
 
+// Version of the `RedisModuleTypeMethods` structure that we provide. Redis
+// only reads as many fields as the version says that the structure has.
+pub const REDISMODULE_TYPE_METHOD_VERSION: u64 = 2;
+
+// Context flags. See `get_context_flags`.
+pub const REDISMODULE_CTX_FLAGS_LUA: c_int = 1 << 0;
+pub const REDISMODULE_CTX_FLAGS_MULTI: c_int = 1 << 1;
+pub const REDISMODULE_CTX_FLAGS_REPLICA: c_int = 1 << 3;
+pub const REDISMODULE_CTX_FLAGS_CLUSTER: c_int = 1 << 5;
+pub const REDISMODULE_CTX_FLAGS_REPLICATED: c_int = 1 << 12;
+pub const REDISMODULE_CTX_FLAGS_LOADING: c_int = 1 << 13;
+pub const REDISMODULE_CTX_FLAGS_DENY_BLOCKING: c_int = 1 << 21;
+pub const REDISMODULE_CTX_FLAGS_RESP3: c_int = 1 << 22;
+
+// Type of the keyspace events that modules emit, which are delivered when
+// notify-keyspace-events includes `d`.
+pub const REDISMODULE_NOTIFY_MODULE: c_int = 1 << 13;
+
+// Flags for a command filter. With NOSELF, commands that the module runs
+// itself through `call` aren't filtered.
+pub const REDISMODULE_CMDFILTER_NOSELF: c_int = 1 << 0;
+
+// Version of the `RedisModuleClientInfo` structure that we provide.
+pub const REDISMODULE_CLIENTINFO_VERSION: u64 = 1;
+
+// Flags for a module config. See `register_string_config` and friends.
+pub const REDISMODULE_CONFIG_DEFAULT: c_uint = 0;
+
+// When auxiliary data is saved relative to the keyspace in an RDB file.
+pub const REDISMODULE_AUX_BEFORE_RDB: c_int = 1 << 0;
+
 bitflags! {
     pub struct KeyMode: c_int {
         const READ = 1;
@@ -17,6 +48,19 @@ bitflags! {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub enum KeyType {
+    Empty = 0,
+    String = 1,
+    List = 2,
+    Hash = 3,
+    Set = 4,
+    ZSet = 5,
+    Module = 6,
+    Stream = 7,
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum ReplyType {
     Unknown = -1,
@@ -34,14 +78,38 @@ pub enum Status {
     Err = 1,
 }
 
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct RedisModuleBlockedClient;
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct RedisModuleCallReply;
 
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct RedisModuleCommandFilter;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct RedisModuleCommandFilterCtx;
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct RedisModuleCtx;
 
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct RedisModuleDigest;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct RedisModuleInfoCtx;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct RedisModuleIO;
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct RedisModuleKey;
@@ -50,19 +118,145 @@ pub struct RedisModuleKey;
 #[repr(C)]
 pub struct RedisModuleString;
 
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct RedisModuleType;
+
 pub type RedisModuleCmdFunc = extern "C" fn(
     ctx: *mut RedisModuleCtx,
     argv: *mut *mut RedisModuleString,
     argc: c_int,
 ) -> Status;
 
+pub type RedisModuleCommandFilterFunc =
+    extern "C" fn(fctx: *mut RedisModuleCommandFilterCtx);
+
+pub type RedisModuleConfigGetBoolFunc =
+    extern "C" fn(name: *const u8, privdata: *mut c_void) -> c_int;
+
+pub type RedisModuleConfigGetEnumFunc =
+    extern "C" fn(name: *const u8, privdata: *mut c_void) -> c_int;
+
+pub type RedisModuleConfigGetNumericFunc =
+    extern "C" fn(name: *const u8, privdata: *mut c_void) -> c_longlong;
+
+pub type RedisModuleConfigGetStringFunc =
+    extern "C" fn(name: *const u8, privdata: *mut c_void) -> *mut RedisModuleString;
+
+pub type RedisModuleConfigSetBoolFunc = extern "C" fn(
+    name: *const u8,
+    val: c_int,
+    privdata: *mut c_void,
+    err: *mut *mut RedisModuleString,
+) -> Status;
+
+pub type RedisModuleConfigSetEnumFunc = extern "C" fn(
+    name: *const u8,
+    val: c_int,
+    privdata: *mut c_void,
+    err: *mut *mut RedisModuleString,
+) -> Status;
+
+pub type RedisModuleConfigSetNumericFunc = extern "C" fn(
+    name: *const u8,
+    val: c_longlong,
+    privdata: *mut c_void,
+    err: *mut *mut RedisModuleString,
+) -> Status;
+
+pub type RedisModuleConfigSetStringFunc = extern "C" fn(
+    name: *const u8,
+    val: *mut RedisModuleString,
+    privdata: *mut c_void,
+    err: *mut *mut RedisModuleString,
+) -> Status;
+
+pub type RedisModuleConfigApplyFunc = extern "C" fn(
+    ctx: *mut RedisModuleCtx,
+    privdata: *mut c_void,
+    err: *mut *mut RedisModuleString,
+) -> Status;
+
+pub type RedisModuleDisconnectFunc =
+    extern "C" fn(ctx: *mut RedisModuleCtx, bc: *mut RedisModuleBlockedClient);
+
+pub type RedisModuleFreePrivDataFunc =
+    extern "C" fn(ctx: *mut RedisModuleCtx, privdata: *mut c_void);
+
+pub type RedisModuleInfoFunc =
+    extern "C" fn(ctx: *mut RedisModuleInfoCtx, for_crash_report: c_int);
+
+pub type RedisModuleTimerProc =
+    extern "C" fn(ctx: *mut RedisModuleCtx, data: *mut c_void);
+
+pub type RedisModuleTypeLoadFunc =
+    extern "C" fn(rdb: *mut RedisModuleIO, encver: c_int) -> *mut c_void;
+
+pub type RedisModuleTypeSaveFunc =
+    extern "C" fn(rdb: *mut RedisModuleIO, value: *mut c_void);
+
+pub type RedisModuleTypeRewriteFunc = extern "C" fn(
+    aof: *mut RedisModuleIO,
+    key: *mut RedisModuleString,
+    value: *mut c_void,
+);
+
+pub type RedisModuleTypeMemUsageFunc = extern "C" fn(value: *const c_void) -> size_t;
+
+pub type RedisModuleTypeDigestFunc =
+    extern "C" fn(digest: *mut RedisModuleDigest, value: *mut c_void);
+
+pub type RedisModuleTypeFreeFunc = extern "C" fn(value: *mut c_void);
+
+pub type RedisModuleTypeAuxLoadFunc =
+    extern "C" fn(rdb: *mut RedisModuleIO, encver: c_int, when: c_int) -> Status;
+
+pub type RedisModuleTypeAuxSaveFunc = extern "C" fn(rdb: *mut RedisModuleIO, when: c_int);
+
+// Information about a client that's filled in by `get_client_info_by_id`. The
+// layout must match Redis' own for the version given in `version`.
+#[repr(C)]
+pub struct RedisModuleClientInfo {
+    pub version: u64,
+    pub flags: u64,
+    pub id: u64,
+    pub addr: [u8; 46],
+    pub port: u16,
+    pub db: u16,
+}
+
+// The set of callbacks that implement a module data type. The layout must
+// match Redis' own for the version given in `version`.
+#[repr(C)]
+pub struct RedisModuleTypeMethods {
+    pub version: u64,
+    pub rdb_load: Option<RedisModuleTypeLoadFunc>,
+    pub rdb_save: Option<RedisModuleTypeSaveFunc>,
+    pub aof_rewrite: Option<RedisModuleTypeRewriteFunc>,
+    pub mem_usage: Option<RedisModuleTypeMemUsageFunc>,
+    pub digest: Option<RedisModuleTypeDigestFunc>,
+    pub free: Option<RedisModuleTypeFreeFunc>,
+    pub aux_load: Option<RedisModuleTypeAuxLoadFunc>,
+    pub aux_save: Option<RedisModuleTypeAuxSaveFunc>,
+    pub aux_save_triggers: c_int,
+}
+
 #[cfg(not(feature = "valkey"))]
 mod inner {
     use super::{
-        KeyMode, RedisModuleCallReply, RedisModuleCmdFunc, RedisModuleCtx,
-        RedisModuleKey, RedisModuleString, ReplyType, Status,
+        KeyMode, KeyType, RedisModuleBlockedClient, RedisModuleCallReply,
+        RedisModuleClientInfo, RedisModuleCmdFunc, RedisModuleCommandFilter,
+        RedisModuleCommandFilterCtx, RedisModuleCommandFilterFunc,
+        RedisModuleConfigApplyFunc, RedisModuleConfigGetBoolFunc,
+        RedisModuleConfigGetEnumFunc, RedisModuleConfigGetNumericFunc,
+        RedisModuleConfigGetStringFunc, RedisModuleConfigSetBoolFunc,
+        RedisModuleConfigSetEnumFunc, RedisModuleConfigSetNumericFunc,
+        RedisModuleConfigSetStringFunc, RedisModuleCtx, RedisModuleDigest,
+        RedisModuleDisconnectFunc, RedisModuleFreePrivDataFunc, RedisModuleIO,
+        RedisModuleInfoCtx, RedisModuleInfoFunc, RedisModuleKey, RedisModuleString,
+        RedisModuleTimerProc, RedisModuleType, RedisModuleTypeMethods, ReplyType, Status,
     };
-    use libc::{c_int, c_long, c_longlong, size_t};
+    use libc::{c_double, c_int, c_long, c_longlong, c_uint, c_void, size_t};
 
     pub fn init(
         ctx: *mut RedisModuleCtx,
@@ -73,10 +267,82 @@ mod inner {
         unsafe { Export_RedisModule_Init(ctx, modulename, module_version, api_version) }
     }
 
+    // Blocks the client that's running the command in the given context.
+    // With a timeout of zero, the client is blocked until it's unblocked.
+    pub fn block_client(
+        ctx: *mut RedisModuleCtx,
+        reply_callback: Option<RedisModuleCmdFunc>,
+        timeout_callback: Option<RedisModuleCmdFunc>,
+        free_privdata: Option<RedisModuleFreePrivDataFunc>,
+        timeout_ms: c_longlong,
+    ) -> *mut RedisModuleBlockedClient {
+        unsafe {
+            RedisModule_BlockClient(
+                ctx,
+                reply_callback,
+                timeout_callback,
+                free_privdata,
+                timeout_ms,
+            )
+        }
+    }
+
     pub fn call_reply_type(reply: *mut RedisModuleCallReply) -> ReplyType {
         unsafe { RedisModule_CallReplyType(reply) }
     }
 
+    pub fn create_data_type(
+        ctx: *mut RedisModuleCtx,
+        name: *const u8,
+        encver: c_int,
+        typemethods: *const RedisModuleTypeMethods,
+    ) -> *mut RedisModuleType {
+        unsafe { RedisModule_CreateDataType(ctx, name, encver, typemethods) }
+    }
+
+    // Creates a timer that calls back after the given period. The API is
+    // missing on older servers, in which case this returns None.
+    pub fn create_timer(
+        ctx: *mut RedisModuleCtx,
+        period: c_longlong,
+        callback: RedisModuleTimerProc,
+        data: *mut c_void,
+    ) -> Option<u64> {
+        unsafe { RedisModule_CreateTimer }
+            .map(|create_timer| create_timer(ctx, period, callback, data))
+    }
+
+    // Adds an integer to a digest. The API is missing on older servers, in
+    // which case nothing is added.
+    pub fn digest_add_long_long(md: *mut RedisModuleDigest, ll: c_longlong) {
+        if let Some(digest_add_long_long) = unsafe { RedisModule_DigestAddLongLong } {
+            digest_add_long_long(md, ll)
+        }
+    }
+
+    pub fn digest_end_sequence(md: *mut RedisModuleDigest) {
+        if let Some(digest_end_sequence) = unsafe { RedisModule_DigestEndSequence } {
+            digest_end_sequence(md)
+        }
+    }
+
+    // Emits a command with a key and arguments into the AOF during a rewrite.
+    pub fn emit_aof(
+        io: *mut RedisModuleIO,
+        cmdname: *const u8,
+        key: *mut RedisModuleString,
+        argv: *const *mut RedisModuleString,
+        argc: size_t,
+    ) {
+        unsafe {
+            RedisModule_EmitAOF(io, cmdname, c"sv".as_ptr().cast(), key, argv, argc)
+        }
+    }
+
+    pub fn free(ptr: *mut c_void) {
+        unsafe { RedisModule_Free(ptr) }
+    }
+
     pub fn free_call_reply(reply: *mut RedisModuleCallReply) {
         unsafe {
             RedisModule_FreeCallReply(reply);
@@ -98,6 +364,70 @@ mod inner {
         unsafe { RedisModule_CloseKey(kp) }
     }
 
+    // The command filter APIs are missing on older servers, but then filters
+    // can't be registered there, so they're never called.
+    pub fn command_filter_arg_delete(
+        fctx: *mut RedisModuleCommandFilterCtx,
+        pos: c_int,
+    ) -> Status {
+        match unsafe { RedisModule_CommandFilterArgDelete } {
+            Some(command_filter_arg_delete) => command_filter_arg_delete(fctx, pos),
+            None => Status::Err,
+        }
+    }
+
+    pub fn command_filter_arg_get(
+        fctx: *mut RedisModuleCommandFilterCtx,
+        pos: c_int,
+    ) -> *mut RedisModuleString {
+        match unsafe { RedisModule_CommandFilterArgGet } {
+            Some(command_filter_arg_get) => command_filter_arg_get(fctx, pos),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    // Inserts an argument into a filtered command, which takes ownership of
+    // it. It must not be freed afterwards.
+    pub fn command_filter_arg_insert(
+        fctx: *mut RedisModuleCommandFilterCtx,
+        pos: c_int,
+        arg: *mut RedisModuleString,
+    ) -> Status {
+        match unsafe { RedisModule_CommandFilterArgInsert } {
+            Some(command_filter_arg_insert) => command_filter_arg_insert(fctx, pos, arg),
+            None => Status::Err,
+        }
+    }
+
+    // Replaces an argument of a filtered command, which takes ownership of
+    // the new one. It must not be freed afterwards.
+    pub fn command_filter_arg_replace(
+        fctx: *mut RedisModuleCommandFilterCtx,
+        pos: c_int,
+        arg: *mut RedisModuleString,
+    ) -> Status {
+        match unsafe { RedisModule_CommandFilterArgReplace } {
+            Some(command_filter_arg_replace) => {
+                command_filter_arg_replace(fctx, pos, arg)
+            }
+            None => Status::Err,
+        }
+    }
+
+    pub fn command_filter_args_count(fctx: *mut RedisModuleCommandFilterCtx) -> c_int {
+        match unsafe { RedisModule_CommandFilterArgsCount } {
+            Some(command_filter_args_count) => command_filter_args_count(fctx),
+            None => 0,
+        }
+    }
+
+    pub fn command_filter_get_client_id(fctx: *mut RedisModuleCommandFilterCtx) -> u64 {
+        match unsafe { RedisModule_CommandFilterGetClientId } {
+            Some(command_filter_get_client_id) => command_filter_get_client_id(fctx),
+            None => 0,
+        }
+    }
+
     pub fn create_command(
         ctx: *mut RedisModuleCtx,
         name: *const u8,
@@ -122,18 +452,192 @@ mod inner {
         unsafe { RedisModule_CreateString(ctx, ptr, len) }
     }
 
+    // Removes a key and its value. The key must be open for writing.
+    pub fn delete_key(key: *mut RedisModuleKey) -> Status {
+        unsafe { RedisModule_DeleteKey(key) }
+    }
+
     pub fn free_string(ctx: *mut RedisModuleCtx, str: *mut RedisModuleString) {
         unsafe { RedisModule_FreeString(ctx, str) }
     }
 
+    pub fn get_blocked_client_private_data(ctx: *mut RedisModuleCtx) -> *mut c_void {
+        unsafe { RedisModule_GetBlockedClientPrivateData(ctx) }
+    }
+
+    // Fills in information about the client with the given ID. Returns an
+    // error if there's no such client, or if the API is missing.
+    pub fn get_client_info_by_id(ci: *mut RedisModuleClientInfo, id: u64) -> Status {
+        match unsafe { RedisModule_GetClientInfoById } {
+            Some(get_client_info_by_id) => get_client_info_by_id(ci as *mut c_void, id),
+            None => Status::Err,
+        }
+    }
+
+    // Gets the name that the client with the given ID has set for itself, or
+    // null if it hasn't set one (or the API is missing). The string must be
+    // freed by the caller.
+    pub fn get_client_name_by_id(
+        ctx: *mut RedisModuleCtx,
+        id: u64,
+    ) -> *mut RedisModuleString {
+        match unsafe { RedisModule_GetClientNameById } {
+            Some(get_client_name_by_id) => get_client_name_by_id(ctx, id),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    // Gets the name of the ACL user that the client with the given ID is
+    // authenticated as, or null if the API is missing. The string must be
+    // freed by the caller.
+    pub fn get_client_user_name_by_id(
+        ctx: *mut RedisModuleCtx,
+        id: u64,
+    ) -> *mut RedisModuleString {
+        match unsafe { RedisModule_GetClientUserNameById } {
+            Some(get_client_user_name_by_id) => get_client_user_name_by_id(ctx, id),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    // Gets flags describing the current context. The API is missing on older
+    // servers, in which case no flags are set.
+    pub fn get_context_flags(ctx: *mut RedisModuleCtx) -> c_int {
+        match unsafe { RedisModule_GetContextFlags } {
+            Some(get_context_flags) => get_context_flags(ctx),
+            None => 0,
+        }
+    }
+
+    // Gets every context flag that the server supports, or `None` on servers
+    // too old to say.
+    pub fn get_context_flags_all() -> Option<c_int> {
+        unsafe { RedisModule_GetContextFlagsAll }
+            .map(|get_context_flags_all| get_context_flags_all())
+    }
+
     pub fn get_selected_db(ctx: *mut RedisModuleCtx) -> c_int {
         unsafe { RedisModule_GetSelectedDb(ctx) }
     }
 
+    // The INFO APIs are missing on older servers, but then so is the
+    // callback that they're used from, so they're never called there.
+    pub fn info_add_field_long_long(
+        ctx: *mut RedisModuleInfoCtx,
+        field: *const u8,
+        value: c_longlong,
+    ) -> Status {
+        match unsafe { RedisModule_InfoAddFieldLongLong } {
+            Some(info_add_field_long_long) => info_add_field_long_long(ctx, field, value),
+            None => Status::Err,
+        }
+    }
+
+    pub fn info_add_section(ctx: *mut RedisModuleInfoCtx, name: *const u8) -> Status {
+        match unsafe { RedisModule_InfoAddSection } {
+            Some(info_add_section) => info_add_section(ctx, name),
+            None => Status::Err,
+        }
+    }
+
+    pub fn info_begin_dict_field(
+        ctx: *mut RedisModuleInfoCtx,
+        name: *const u8,
+    ) -> Status {
+        match unsafe { RedisModule_InfoBeginDictField } {
+            Some(info_begin_dict_field) => info_begin_dict_field(ctx, name),
+            None => Status::Err,
+        }
+    }
+
+    pub fn info_end_dict_field(ctx: *mut RedisModuleInfoCtx) -> Status {
+        match unsafe { RedisModule_InfoEndDictField } {
+            Some(info_end_dict_field) => info_end_dict_field(ctx),
+            None => Status::Err,
+        }
+    }
+
+    // Whether the server has the module config API, which was added in Redis
+    // 7.0.
+    pub fn has_config_api() -> bool {
+        unsafe { RedisModule_LoadConfigs }.is_some()
+    }
+
+    // Whether the server has the command filter APIs, including the one to
+    // find out which client is running a filtered command that was added in
+    // Redis 7.2.
+    pub fn has_command_filter_api() -> bool {
+        unsafe { RedisModule_RegisterCommandFilter }.is_some()
+            && unsafe { RedisModule_CommandFilterGetClientId }.is_some()
+    }
+
+    pub fn key_type(kp: *mut RedisModuleKey) -> KeyType {
+        unsafe { RedisModule_KeyType(kp) }
+    }
+
+    // Applies the values of the module's configs from the server's config
+    // file or MODULE LOADEX arguments, or their defaults. It must be called
+    // once all of them have been registered. The API is missing on older
+    // servers, in which case this returns an error.
+    pub fn load_configs(ctx: *mut RedisModuleCtx) -> Status {
+        match unsafe { RedisModule_LoadConfigs } {
+            Some(load_configs) => load_configs(ctx),
+            None => Status::Err,
+        }
+    }
+
+    pub fn load_signed(io: *mut RedisModuleIO) -> i64 {
+        unsafe { RedisModule_LoadSigned(io) }
+    }
+
+    // Loads a string saved with `save_string_buffer`. The returned buffer is
+    // owned by the caller and must be released with `free`.
+    pub fn load_string_buffer(io: *mut RedisModuleIO, len: *mut size_t) -> *mut u8 {
+        unsafe { RedisModule_LoadStringBuffer(io, len) }
+    }
+
+    pub fn load_unsigned(io: *mut RedisModuleIO) -> u64 {
+        unsafe { RedisModule_LoadUnsigned(io) }
+    }
+
     pub fn log(ctx: *mut RedisModuleCtx, level: *const u8, fmt: *const u8) {
         unsafe { RedisModule_Log(ctx, level, fmt) }
     }
 
+    pub fn module_type_get_type(key: *mut RedisModuleKey) -> *mut RedisModuleType {
+        unsafe { RedisModule_ModuleTypeGetType(key) }
+    }
+
+    pub fn module_type_get_value(key: *mut RedisModuleKey) -> *mut c_void {
+        unsafe { RedisModule_ModuleTypeGetValue(key) }
+    }
+
+    // Sets a key to a value of a module data type. Any existing value is freed
+    // and, like the SET command, the key's expiry is cleared.
+    pub fn module_type_set_value(
+        key: *mut RedisModuleKey,
+        mt: *mut RedisModuleType,
+        value: *mut c_void,
+    ) -> Status {
+        unsafe { RedisModule_ModuleTypeSetValue(key, mt, value) }
+    }
+
+    // Emits a keyspace event on a key. The API is missing on older servers,
+    // in which case this returns an error.
+    pub fn notify_keyspace_event(
+        ctx: *mut RedisModuleCtx,
+        event_type: c_int,
+        event: *const u8,
+        key: *mut RedisModuleString,
+    ) -> Status {
+        match unsafe { RedisModule_NotifyKeyspaceEvent } {
+            Some(notify_keyspace_event) => {
+                notify_keyspace_event(ctx, event_type, event, key)
+            }
+            None => Status::Err,
+        }
+    }
+
     pub fn open_key(
         ctx: *mut RedisModuleCtx,
         keyname: *mut RedisModuleString,
@@ -142,15 +646,183 @@ mod inner {
         unsafe { RedisModule_OpenKey(ctx, keyname, mode) }
     }
 
+    // Replicates a command to replicas and the AOF. Arguments are passed as
+    // a vector of Redis strings (the "v" format specifier), which saves us
+    // from fixing their number like `call1` and friends have to.
+    // Publishes a message to a Pub/Sub channel. The API is missing on older
+    // servers, in which case this returns an error.
+    pub fn publish_message(
+        ctx: *mut RedisModuleCtx,
+        channel: *mut RedisModuleString,
+        message: *mut RedisModuleString,
+    ) -> Status {
+        match unsafe { RedisModule_PublishMessage } {
+            Some(publish_message) => publish_message(ctx, channel, message),
+            None => Status::Err,
+        }
+    }
+
+    pub fn replicate(
+        ctx: *mut RedisModuleCtx,
+        cmdname: *const u8,
+        argv: *const *mut RedisModuleString,
+        argc: size_t,
+    ) -> Status {
+        unsafe { RedisModule_Replicate(ctx, cmdname, c"v".as_ptr().cast(), argv, argc) }
+    }
+
     // Causes a command to be replicated exactly as invoked on replicas.
     pub fn replicate_verbatim(ctx: *mut RedisModuleCtx) -> Status {
         unsafe { RedisModule_ReplicateVerbatim(ctx) }
     }
 
+    // Registers configs that can be read and changed with CONFIG GET/SET as
+    // `<module name>.<name>`. The APIs are missing on older servers, in which
+    // case these return an error.
+    pub fn register_bool_config(
+        ctx: *mut RedisModuleCtx,
+        name: *const u8,
+        default_val: c_int,
+        flags: c_uint,
+        getfn: RedisModuleConfigGetBoolFunc,
+        setfn: RedisModuleConfigSetBoolFunc,
+    ) -> Status {
+        match unsafe { RedisModule_RegisterBoolConfig } {
+            Some(register_bool_config) => register_bool_config(
+                ctx,
+                name,
+                default_val,
+                flags,
+                getfn,
+                setfn,
+                None,
+                std::ptr::null_mut(),
+            ),
+            None => Status::Err,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn register_enum_config(
+        ctx: *mut RedisModuleCtx,
+        name: *const u8,
+        default_val: c_int,
+        flags: c_uint,
+        enum_values: *const *const u8,
+        int_values: *const c_int,
+        num_enum_vals: c_int,
+        getfn: RedisModuleConfigGetEnumFunc,
+        setfn: RedisModuleConfigSetEnumFunc,
+    ) -> Status {
+        match unsafe { RedisModule_RegisterEnumConfig } {
+            Some(register_enum_config) => register_enum_config(
+                ctx,
+                name,
+                default_val,
+                flags,
+                enum_values,
+                int_values,
+                num_enum_vals,
+                getfn,
+                setfn,
+                None,
+                std::ptr::null_mut(),
+            ),
+            None => Status::Err,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn register_numeric_config(
+        ctx: *mut RedisModuleCtx,
+        name: *const u8,
+        default_val: c_longlong,
+        flags: c_uint,
+        min: c_longlong,
+        max: c_longlong,
+        getfn: RedisModuleConfigGetNumericFunc,
+        setfn: RedisModuleConfigSetNumericFunc,
+    ) -> Status {
+        match unsafe { RedisModule_RegisterNumericConfig } {
+            Some(register_numeric_config) => register_numeric_config(
+                ctx,
+                name,
+                default_val,
+                flags,
+                min,
+                max,
+                getfn,
+                setfn,
+                None,
+                std::ptr::null_mut(),
+            ),
+            None => Status::Err,
+        }
+    }
+
+    pub fn register_string_config(
+        ctx: *mut RedisModuleCtx,
+        name: *const u8,
+        default_val: *const u8,
+        flags: c_uint,
+        getfn: RedisModuleConfigGetStringFunc,
+        setfn: RedisModuleConfigSetStringFunc,
+    ) -> Status {
+        match unsafe { RedisModule_RegisterStringConfig } {
+            Some(register_string_config) => register_string_config(
+                ctx,
+                name,
+                default_val,
+                flags,
+                getfn,
+                setfn,
+                None,
+                std::ptr::null_mut(),
+            ),
+            None => Status::Err,
+        }
+    }
+
+    // Registers a callback that's called with every command before it's run.
+    // Returns null if the API is missing.
+    pub fn register_command_filter(
+        ctx: *mut RedisModuleCtx,
+        cb: RedisModuleCommandFilterFunc,
+        flags: c_int,
+    ) -> *mut RedisModuleCommandFilter {
+        match unsafe { RedisModule_RegisterCommandFilter } {
+            Some(register_command_filter) => register_command_filter(ctx, cb, flags),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    // Registers a callback that adds the module's own sections to INFO. The
+    // API is missing on older servers, in which case this returns an error.
+    pub fn register_info_func(
+        ctx: *mut RedisModuleCtx,
+        cb: RedisModuleInfoFunc,
+    ) -> Status {
+        match unsafe { RedisModule_RegisterInfoFunc } {
+            Some(register_info_func) => register_info_func(ctx, cb),
+            None => Status::Err,
+        }
+    }
+
     pub fn reply_with_array(ctx: *mut RedisModuleCtx, len: c_long) -> Status {
         unsafe { RedisModule_ReplyWithArray(ctx, len) }
     }
 
+    // Replies with a boolean. The API is missing on servers older than 7, in
+    // which case this returns None.
+    pub fn reply_with_bool(ctx: *mut RedisModuleCtx, b: c_int) -> Option<Status> {
+        unsafe { RedisModule_ReplyWithBool }
+            .map(|reply_with_bool| reply_with_bool(ctx, b))
+    }
+
+    pub fn reply_with_double(ctx: *mut RedisModuleCtx, d: c_double) -> Status {
+        unsafe { RedisModule_ReplyWithDouble(ctx, d) }
+    }
+
     pub fn reply_with_error(ctx: *mut RedisModuleCtx, err: *const u8) {
         unsafe { RedisModule_ReplyWithError(ctx, err) }
     }
@@ -159,6 +831,20 @@ mod inner {
         unsafe { RedisModule_ReplyWithLongLong(ctx, ll) }
     }
 
+    // Replies with a map. The API is missing on servers older than 7, in
+    // which case this returns None.
+    pub fn reply_with_map(ctx: *mut RedisModuleCtx, len: c_long) -> Option<Status> {
+        unsafe { RedisModule_ReplyWithMap }.map(|reply_with_map| reply_with_map(ctx, len))
+    }
+
+    pub fn reply_with_null(ctx: *mut RedisModuleCtx) -> Status {
+        unsafe { RedisModule_ReplyWithNull(ctx) }
+    }
+
+    pub fn reply_with_simple_string(ctx: *mut RedisModuleCtx, msg: *const u8) -> Status {
+        unsafe { RedisModule_ReplyWithSimpleString(ctx, msg) }
+    }
+
     pub fn reply_with_string(
         ctx: *mut RedisModuleCtx,
         str: *mut RedisModuleString,
@@ -166,6 +852,44 @@ mod inner {
         unsafe { RedisModule_ReplyWithString(ctx, str) }
     }
 
+    pub fn save_signed(io: *mut RedisModuleIO, value: i64) {
+        unsafe { RedisModule_SaveSigned(io, value) }
+    }
+
+    pub fn save_string_buffer(io: *mut RedisModuleIO, str: *const u8, len: size_t) {
+        unsafe { RedisModule_SaveStringBuffer(io, str, len) }
+    }
+
+    pub fn save_unsigned(io: *mut RedisModuleIO, value: u64) {
+        unsafe { RedisModule_SaveUnsigned(io, value) }
+    }
+
+    pub fn select_db(ctx: *mut RedisModuleCtx, newid: c_int) -> Status {
+        unsafe { RedisModule_SelectDb(ctx, newid) }
+    }
+
+    // Sets a key to expire at an absolute Unix time in milliseconds. The API
+    // is missing on older servers, in which case this returns an error.
+    pub fn set_abs_expire(key: *mut RedisModuleKey, expire: c_longlong) -> Status {
+        match unsafe { RedisModule_SetAbsExpire } {
+            Some(set_abs_expire) => set_abs_expire(key, expire),
+            None => Status::Err,
+        }
+    }
+
+    // Sets a callback for when a blocked client disconnects. The API is
+    // missing on older servers, in which case this does nothing.
+    pub fn set_disconnect_callback(
+        bc: *mut RedisModuleBlockedClient,
+        callback: RedisModuleDisconnectFunc,
+    ) {
+        if let Some(set_disconnect_callback) =
+            unsafe { RedisModule_SetDisconnectCallback }
+        {
+            set_disconnect_callback(bc, callback)
+        }
+    }
+
     // Sets the expiry on a key.
     //
     // Expire is in milliseconds.
@@ -189,6 +913,13 @@ mod inner {
         unsafe { RedisModule_StringSet(key, str) }
     }
 
+    pub fn unblock_client(
+        bc: *mut RedisModuleBlockedClient,
+        privdata: *mut c_void,
+    ) -> Status {
+        unsafe { RedisModule_UnblockClient(bc, privdata) }
+    }
+
     // Redis doesn't make this easy for us by exporting a library, so instead what
     // we do is bake redismodule.h's symbols into a library of our construction
     // during build and link against that. See build.rs for details.
@@ -202,6 +933,15 @@ mod inner {
             api_version: c_int,
         ) -> Status;
 
+        static RedisModule_BlockClient: extern "C" fn(
+            ctx: *mut RedisModuleCtx,
+            reply_callback: Option<RedisModuleCmdFunc>,
+            timeout_callback: Option<RedisModuleCmdFunc>,
+            free_privdata: Option<RedisModuleFreePrivDataFunc>,
+            timeout_ms: c_longlong,
+        )
+            -> *mut RedisModuleBlockedClient;
+
         static RedisModule_Call: extern "C" fn(
             ctx: *mut RedisModuleCtx,
             cmdname: *const u8,
@@ -220,6 +960,39 @@ mod inner {
 
         static RedisModule_CloseKey: extern "C" fn(kp: *mut RedisModuleKey);
 
+        static RedisModule_CommandFilterArgDelete: Option<
+            extern "C" fn(fctx: *mut RedisModuleCommandFilterCtx, pos: c_int) -> Status,
+        >;
+
+        static RedisModule_CommandFilterArgGet: Option<
+            extern "C" fn(
+                fctx: *mut RedisModuleCommandFilterCtx,
+                pos: c_int,
+            ) -> *mut RedisModuleString,
+        >;
+
+        static RedisModule_CommandFilterArgInsert: Option<
+            extern "C" fn(
+                fctx: *mut RedisModuleCommandFilterCtx,
+                pos: c_int,
+                arg: *mut RedisModuleString,
+            ) -> Status,
+        >;
+
+        static RedisModule_CommandFilterArgReplace: Option<
+            extern "C" fn(
+                fctx: *mut RedisModuleCommandFilterCtx,
+                pos: c_int,
+                arg: *mut RedisModuleString,
+            ) -> Status,
+        >;
+
+        static RedisModule_CommandFilterArgsCount:
+            Option<extern "C" fn(fctx: *mut RedisModuleCommandFilterCtx) -> c_int>;
+
+        static RedisModule_CommandFilterGetClientId:
+            Option<extern "C" fn(fctx: *mut RedisModuleCommandFilterCtx) -> u64>;
+
         static RedisModule_CreateCommand: extern "C" fn(
             ctx: *mut RedisModuleCtx,
             name: *const u8,
@@ -230,46 +1003,270 @@ mod inner {
             keystep: c_int,
         ) -> Status;
 
+        static RedisModule_CreateDataType: extern "C" fn(
+            ctx: *mut RedisModuleCtx,
+            name: *const u8,
+            encver: c_int,
+            typemethods: *const RedisModuleTypeMethods,
+        ) -> *mut RedisModuleType;
+
         static RedisModule_CreateString: extern "C" fn(
             ctx: *mut RedisModuleCtx,
             ptr: *const u8,
             len: size_t,
         ) -> *mut RedisModuleString;
 
+        static RedisModule_CreateTimer: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                period: c_longlong,
+                callback: RedisModuleTimerProc,
+                data: *mut c_void,
+            ) -> u64,
+        >;
+
+        static RedisModule_DeleteKey: extern "C" fn(key: *mut RedisModuleKey) -> Status;
+
+        static RedisModule_DigestAddLongLong:
+            Option<extern "C" fn(md: *mut RedisModuleDigest, ll: c_longlong)>;
+
+        static RedisModule_DigestEndSequence:
+            Option<extern "C" fn(md: *mut RedisModuleDigest)>;
+
+        static RedisModule_EmitAOF: unsafe extern "C" fn(
+            io: *mut RedisModuleIO,
+            cmdname: *const u8,
+            fmt: *const u8,
+            ...
+        );
+
+        static RedisModule_Free: extern "C" fn(ptr: *mut c_void);
+
         static RedisModule_FreeCallReply: extern "C" fn(reply: *mut RedisModuleCallReply);
 
         static RedisModule_FreeString:
             extern "C" fn(ctx: *mut RedisModuleCtx, str: *mut RedisModuleString);
 
+        static RedisModule_GetBlockedClientPrivateData:
+            extern "C" fn(ctx: *mut RedisModuleCtx) -> *mut c_void;
+
+        static RedisModule_GetClientInfoById:
+            Option<extern "C" fn(ci: *mut c_void, id: u64) -> Status>;
+
+        static RedisModule_GetClientNameById: Option<
+            extern "C" fn(ctx: *mut RedisModuleCtx, id: u64) -> *mut RedisModuleString,
+        >;
+
+        static RedisModule_GetClientUserNameById: Option<
+            extern "C" fn(ctx: *mut RedisModuleCtx, id: u64) -> *mut RedisModuleString,
+        >;
+
+        static RedisModule_GetContextFlags:
+            Option<extern "C" fn(ctx: *mut RedisModuleCtx) -> c_int>;
+
+        static RedisModule_GetContextFlagsAll: Option<extern "C" fn() -> c_int>;
+
         static RedisModule_GetSelectedDb:
             extern "C" fn(ctx: *mut RedisModuleCtx) -> c_int;
 
+        static RedisModule_InfoAddFieldLongLong: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleInfoCtx,
+                field: *const u8,
+                value: c_longlong,
+            ) -> Status,
+        >;
+
+        static RedisModule_InfoAddSection: Option<
+            extern "C" fn(ctx: *mut RedisModuleInfoCtx, name: *const u8) -> Status,
+        >;
+
+        static RedisModule_InfoBeginDictField: Option<
+            extern "C" fn(ctx: *mut RedisModuleInfoCtx, name: *const u8) -> Status,
+        >;
+
+        static RedisModule_InfoEndDictField:
+            Option<extern "C" fn(ctx: *mut RedisModuleInfoCtx) -> Status>;
+
+        static RedisModule_KeyType: extern "C" fn(kp: *mut RedisModuleKey) -> KeyType;
+
+        static RedisModule_LoadConfigs:
+            Option<extern "C" fn(ctx: *mut RedisModuleCtx) -> Status>;
+
+        static RedisModule_LoadSigned: extern "C" fn(io: *mut RedisModuleIO) -> i64;
+
+        static RedisModule_LoadStringBuffer:
+            extern "C" fn(io: *mut RedisModuleIO, len: *mut size_t) -> *mut u8;
+
+        static RedisModule_LoadUnsigned: extern "C" fn(io: *mut RedisModuleIO) -> u64;
+
         static RedisModule_Log:
             extern "C" fn(ctx: *mut RedisModuleCtx, level: *const u8, fmt: *const u8);
 
+        static RedisModule_ModuleTypeGetType:
+            extern "C" fn(key: *mut RedisModuleKey) -> *mut RedisModuleType;
+
+        static RedisModule_ModuleTypeGetValue:
+            extern "C" fn(key: *mut RedisModuleKey) -> *mut c_void;
+
+        static RedisModule_ModuleTypeSetValue: extern "C" fn(
+            key: *mut RedisModuleKey,
+            mt: *mut RedisModuleType,
+            value: *mut c_void,
+        ) -> Status;
+
+        static RedisModule_NotifyKeyspaceEvent: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                event_type: c_int,
+                event: *const u8,
+                key: *mut RedisModuleString,
+            ) -> Status,
+        >;
+
         static RedisModule_OpenKey: extern "C" fn(
             ctx: *mut RedisModuleCtx,
             keyname: *mut RedisModuleString,
             mode: KeyMode,
         ) -> *mut RedisModuleKey;
 
+        static RedisModule_PublishMessage: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                channel: *mut RedisModuleString,
+                message: *mut RedisModuleString,
+            ) -> Status,
+        >;
+
+        static RedisModule_RegisterBoolConfig: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                name: *const u8,
+                default_val: c_int,
+                flags: c_uint,
+                getfn: RedisModuleConfigGetBoolFunc,
+                setfn: RedisModuleConfigSetBoolFunc,
+                applyfn: Option<RedisModuleConfigApplyFunc>,
+                privdata: *mut c_void,
+            ) -> Status,
+        >;
+
+        static RedisModule_RegisterCommandFilter: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                cb: RedisModuleCommandFilterFunc,
+                flags: c_int,
+            ) -> *mut RedisModuleCommandFilter,
+        >;
+
+        static RedisModule_RegisterEnumConfig: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                name: *const u8,
+                default_val: c_int,
+                flags: c_uint,
+                enum_values: *const *const u8,
+                int_values: *const c_int,
+                num_enum_vals: c_int,
+                getfn: RedisModuleConfigGetEnumFunc,
+                setfn: RedisModuleConfigSetEnumFunc,
+                applyfn: Option<RedisModuleConfigApplyFunc>,
+                privdata: *mut c_void,
+            ) -> Status,
+        >;
+
+        static RedisModule_RegisterInfoFunc: Option<
+            extern "C" fn(ctx: *mut RedisModuleCtx, cb: RedisModuleInfoFunc) -> Status,
+        >;
+
+        static RedisModule_RegisterNumericConfig: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                name: *const u8,
+                default_val: c_longlong,
+                flags: c_uint,
+                min: c_longlong,
+                max: c_longlong,
+                getfn: RedisModuleConfigGetNumericFunc,
+                setfn: RedisModuleConfigSetNumericFunc,
+                applyfn: Option<RedisModuleConfigApplyFunc>,
+                privdata: *mut c_void,
+            ) -> Status,
+        >;
+
+        static RedisModule_RegisterStringConfig: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                name: *const u8,
+                default_val: *const u8,
+                flags: c_uint,
+                getfn: RedisModuleConfigGetStringFunc,
+                setfn: RedisModuleConfigSetStringFunc,
+                applyfn: Option<RedisModuleConfigApplyFunc>,
+                privdata: *mut c_void,
+            ) -> Status,
+        >;
+
+        static RedisModule_Replicate: unsafe extern "C" fn(
+            ctx: *mut RedisModuleCtx,
+            cmdname: *const u8,
+            fmt: *const u8,
+            ...
+        ) -> Status;
+
         static RedisModule_ReplicateVerbatim:
             extern "C" fn(ctx: *mut RedisModuleCtx) -> Status;
 
         static RedisModule_ReplyWithArray:
             extern "C" fn(ctx: *mut RedisModuleCtx, len: c_long) -> Status;
 
+        static RedisModule_ReplyWithBool:
+            Option<extern "C" fn(ctx: *mut RedisModuleCtx, b: c_int) -> Status>;
+
+        static RedisModule_ReplyWithDouble:
+            extern "C" fn(ctx: *mut RedisModuleCtx, d: c_double) -> Status;
+
         static RedisModule_ReplyWithError:
             extern "C" fn(ctx: *mut RedisModuleCtx, err: *const u8);
 
         static RedisModule_ReplyWithLongLong:
             extern "C" fn(ctx: *mut RedisModuleCtx, ll: c_longlong) -> Status;
 
+        static RedisModule_ReplyWithMap:
+            Option<extern "C" fn(ctx: *mut RedisModuleCtx, len: c_long) -> Status>;
+
+        static RedisModule_ReplyWithNull:
+            extern "C" fn(ctx: *mut RedisModuleCtx) -> Status;
+
+        static RedisModule_ReplyWithSimpleString:
+            extern "C" fn(ctx: *mut RedisModuleCtx, msg: *const u8) -> Status;
+
         static RedisModule_ReplyWithString: extern "C" fn(
             ctx: *mut RedisModuleCtx,
             str: *mut RedisModuleString,
         ) -> Status;
 
+        static RedisModule_SaveSigned: extern "C" fn(io: *mut RedisModuleIO, value: i64);
+
+        static RedisModule_SaveStringBuffer:
+            extern "C" fn(io: *mut RedisModuleIO, str: *const u8, len: size_t);
+
+        static RedisModule_SaveUnsigned:
+            extern "C" fn(io: *mut RedisModuleIO, value: u64);
+
+        static RedisModule_SelectDb:
+            extern "C" fn(ctx: *mut RedisModuleCtx, newid: c_int) -> Status;
+
+        static RedisModule_SetAbsExpire:
+            Option<extern "C" fn(key: *mut RedisModuleKey, expire: c_longlong) -> Status>;
+
+        static RedisModule_SetDisconnectCallback: Option<
+            extern "C" fn(
+                bc: *mut RedisModuleBlockedClient,
+                callback: RedisModuleDisconnectFunc,
+            ),
+        >;
+
         static RedisModule_SetExpire:
             extern "C" fn(key: *mut RedisModuleKey, expire: c_longlong) -> Status;
 
@@ -286,6 +1283,11 @@ mod inner {
             key: *mut RedisModuleKey,
             str: *mut RedisModuleString,
         ) -> Status;
+
+        static RedisModule_UnblockClient: extern "C" fn(
+            bc: *mut RedisModuleBlockedClient,
+            privdata: *mut c_void,
+        ) -> Status;
     }
 
     pub mod call1 {
@@ -370,10 +1372,19 @@ mod inner {
 #[cfg(feature = "valkey")]
 mod inner {
     use super::{
-        KeyMode, RedisModuleCallReply, RedisModuleCmdFunc, RedisModuleCtx,
-        RedisModuleKey, RedisModuleString, ReplyType, Status,
+        KeyMode, KeyType, RedisModuleBlockedClient, RedisModuleCallReply,
+        RedisModuleClientInfo, RedisModuleCmdFunc, RedisModuleCommandFilter,
+        RedisModuleCommandFilterCtx, RedisModuleCommandFilterFunc,
+        RedisModuleConfigApplyFunc, RedisModuleConfigGetBoolFunc,
+        RedisModuleConfigGetEnumFunc, RedisModuleConfigGetNumericFunc,
+        RedisModuleConfigGetStringFunc, RedisModuleConfigSetBoolFunc,
+        RedisModuleConfigSetEnumFunc, RedisModuleConfigSetNumericFunc,
+        RedisModuleConfigSetStringFunc, RedisModuleCtx, RedisModuleDigest,
+        RedisModuleDisconnectFunc, RedisModuleFreePrivDataFunc, RedisModuleIO,
+        RedisModuleInfoCtx, RedisModuleInfoFunc, RedisModuleKey, RedisModuleString,
+        RedisModuleTimerProc, RedisModuleType, RedisModuleTypeMethods, ReplyType, Status,
     };
-    use libc::{c_int, c_long, c_longlong, size_t};
+    use libc::{c_double, c_int, c_long, c_longlong, c_uint, c_void, size_t};
 
     pub fn init(
         ctx: *mut RedisModuleCtx,
@@ -384,10 +1395,82 @@ mod inner {
         unsafe { Export_ValkeyModule_Init(ctx, modulename, module_version, api_version) }
     }
 
+    // Blocks the client that's running the command in the given context.
+    // With a timeout of zero, the client is blocked until it's unblocked.
+    pub fn block_client(
+        ctx: *mut RedisModuleCtx,
+        reply_callback: Option<RedisModuleCmdFunc>,
+        timeout_callback: Option<RedisModuleCmdFunc>,
+        free_privdata: Option<RedisModuleFreePrivDataFunc>,
+        timeout_ms: c_longlong,
+    ) -> *mut RedisModuleBlockedClient {
+        unsafe {
+            ValkeyModule_BlockClient(
+                ctx,
+                reply_callback,
+                timeout_callback,
+                free_privdata,
+                timeout_ms,
+            )
+        }
+    }
+
     pub fn call_reply_type(reply: *mut RedisModuleCallReply) -> ReplyType {
         unsafe { ValkeyModule_CallReplyType(reply) }
     }
 
+    pub fn create_data_type(
+        ctx: *mut RedisModuleCtx,
+        name: *const u8,
+        encver: c_int,
+        typemethods: *const RedisModuleTypeMethods,
+    ) -> *mut RedisModuleType {
+        unsafe { ValkeyModule_CreateDataType(ctx, name, encver, typemethods) }
+    }
+
+    // Creates a timer that calls back after the given period. The API is
+    // missing on older servers, in which case this returns None.
+    pub fn create_timer(
+        ctx: *mut RedisModuleCtx,
+        period: c_longlong,
+        callback: RedisModuleTimerProc,
+        data: *mut c_void,
+    ) -> Option<u64> {
+        unsafe { ValkeyModule_CreateTimer }
+            .map(|create_timer| create_timer(ctx, period, callback, data))
+    }
+
+    // Adds an integer to a digest. The API is missing on older servers, in
+    // which case nothing is added.
+    pub fn digest_add_long_long(md: *mut RedisModuleDigest, ll: c_longlong) {
+        if let Some(digest_add_long_long) = unsafe { ValkeyModule_DigestAddLongLong } {
+            digest_add_long_long(md, ll)
+        }
+    }
+
+    pub fn digest_end_sequence(md: *mut RedisModuleDigest) {
+        if let Some(digest_end_sequence) = unsafe { ValkeyModule_DigestEndSequence } {
+            digest_end_sequence(md)
+        }
+    }
+
+    // Emits a command with a key and arguments into the AOF during a rewrite.
+    pub fn emit_aof(
+        io: *mut RedisModuleIO,
+        cmdname: *const u8,
+        key: *mut RedisModuleString,
+        argv: *const *mut RedisModuleString,
+        argc: size_t,
+    ) {
+        unsafe {
+            ValkeyModule_EmitAOF(io, cmdname, c"sv".as_ptr().cast(), key, argv, argc)
+        }
+    }
+
+    pub fn free(ptr: *mut c_void) {
+        unsafe { ValkeyModule_Free(ptr) }
+    }
+
     pub fn free_call_reply(reply: *mut RedisModuleCallReply) {
         unsafe {
             ValkeyModule_FreeCallReply(reply);
@@ -409,6 +1492,70 @@ mod inner {
         unsafe { ValkeyModule_CloseKey(kp) }
     }
 
+    // The command filter APIs are missing on older servers, but then filters
+    // can't be registered there, so they're never called.
+    pub fn command_filter_arg_delete(
+        fctx: *mut RedisModuleCommandFilterCtx,
+        pos: c_int,
+    ) -> Status {
+        match unsafe { ValkeyModule_CommandFilterArgDelete } {
+            Some(command_filter_arg_delete) => command_filter_arg_delete(fctx, pos),
+            None => Status::Err,
+        }
+    }
+
+    pub fn command_filter_arg_get(
+        fctx: *mut RedisModuleCommandFilterCtx,
+        pos: c_int,
+    ) -> *mut RedisModuleString {
+        match unsafe { ValkeyModule_CommandFilterArgGet } {
+            Some(command_filter_arg_get) => command_filter_arg_get(fctx, pos),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    // Inserts an argument into a filtered command, which takes ownership of
+    // it. It must not be freed afterwards.
+    pub fn command_filter_arg_insert(
+        fctx: *mut RedisModuleCommandFilterCtx,
+        pos: c_int,
+        arg: *mut RedisModuleString,
+    ) -> Status {
+        match unsafe { ValkeyModule_CommandFilterArgInsert } {
+            Some(command_filter_arg_insert) => command_filter_arg_insert(fctx, pos, arg),
+            None => Status::Err,
+        }
+    }
+
+    // Replaces an argument of a filtered command, which takes ownership of
+    // the new one. It must not be freed afterwards.
+    pub fn command_filter_arg_replace(
+        fctx: *mut RedisModuleCommandFilterCtx,
+        pos: c_int,
+        arg: *mut RedisModuleString,
+    ) -> Status {
+        match unsafe { ValkeyModule_CommandFilterArgReplace } {
+            Some(command_filter_arg_replace) => {
+                command_filter_arg_replace(fctx, pos, arg)
+            }
+            None => Status::Err,
+        }
+    }
+
+    pub fn command_filter_args_count(fctx: *mut RedisModuleCommandFilterCtx) -> c_int {
+        match unsafe { ValkeyModule_CommandFilterArgsCount } {
+            Some(command_filter_args_count) => command_filter_args_count(fctx),
+            None => 0,
+        }
+    }
+
+    pub fn command_filter_get_client_id(fctx: *mut RedisModuleCommandFilterCtx) -> u64 {
+        match unsafe { ValkeyModule_CommandFilterGetClientId } {
+            Some(command_filter_get_client_id) => command_filter_get_client_id(fctx),
+            None => 0,
+        }
+    }
+
     pub fn create_command(
         ctx: *mut RedisModuleCtx,
         name: *const u8,
@@ -433,18 +1580,192 @@ mod inner {
         unsafe { ValkeyModule_CreateString(ctx, ptr, len) }
     }
 
+    // Removes a key and its value. The key must be open for writing.
+    pub fn delete_key(key: *mut RedisModuleKey) -> Status {
+        unsafe { ValkeyModule_DeleteKey(key) }
+    }
+
     pub fn free_string(ctx: *mut RedisModuleCtx, str: *mut RedisModuleString) {
         unsafe { ValkeyModule_FreeString(ctx, str) }
     }
 
+    pub fn get_blocked_client_private_data(ctx: *mut RedisModuleCtx) -> *mut c_void {
+        unsafe { ValkeyModule_GetBlockedClientPrivateData(ctx) }
+    }
+
+    // Fills in information about the client with the given ID. Returns an
+    // error if there's no such client, or if the API is missing.
+    pub fn get_client_info_by_id(ci: *mut RedisModuleClientInfo, id: u64) -> Status {
+        match unsafe { ValkeyModule_GetClientInfoById } {
+            Some(get_client_info_by_id) => get_client_info_by_id(ci as *mut c_void, id),
+            None => Status::Err,
+        }
+    }
+
+    // Gets the name that the client with the given ID has set for itself, or
+    // null if it hasn't set one (or the API is missing). The string must be
+    // freed by the caller.
+    pub fn get_client_name_by_id(
+        ctx: *mut RedisModuleCtx,
+        id: u64,
+    ) -> *mut RedisModuleString {
+        match unsafe { ValkeyModule_GetClientNameById } {
+            Some(get_client_name_by_id) => get_client_name_by_id(ctx, id),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    // Gets the name of the ACL user that the client with the given ID is
+    // authenticated as, or null if the API is missing. The string must be
+    // freed by the caller.
+    pub fn get_client_user_name_by_id(
+        ctx: *mut RedisModuleCtx,
+        id: u64,
+    ) -> *mut RedisModuleString {
+        match unsafe { ValkeyModule_GetClientUserNameById } {
+            Some(get_client_user_name_by_id) => get_client_user_name_by_id(ctx, id),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    // Gets flags describing the current context. The API is missing on older
+    // servers, in which case no flags are set.
+    pub fn get_context_flags(ctx: *mut RedisModuleCtx) -> c_int {
+        match unsafe { ValkeyModule_GetContextFlags } {
+            Some(get_context_flags) => get_context_flags(ctx),
+            None => 0,
+        }
+    }
+
+    // Gets every context flag that the server supports, or `None` on servers
+    // too old to say.
+    pub fn get_context_flags_all() -> Option<c_int> {
+        unsafe { ValkeyModule_GetContextFlagsAll }
+            .map(|get_context_flags_all| get_context_flags_all())
+    }
+
     pub fn get_selected_db(ctx: *mut RedisModuleCtx) -> c_int {
         unsafe { ValkeyModule_GetSelectedDb(ctx) }
     }
 
+    // The INFO APIs are missing on older servers, but then so is the
+    // callback that they're used from, so they're never called there.
+    pub fn info_add_field_long_long(
+        ctx: *mut RedisModuleInfoCtx,
+        field: *const u8,
+        value: c_longlong,
+    ) -> Status {
+        match unsafe { ValkeyModule_InfoAddFieldLongLong } {
+            Some(info_add_field_long_long) => info_add_field_long_long(ctx, field, value),
+            None => Status::Err,
+        }
+    }
+
+    pub fn info_add_section(ctx: *mut RedisModuleInfoCtx, name: *const u8) -> Status {
+        match unsafe { ValkeyModule_InfoAddSection } {
+            Some(info_add_section) => info_add_section(ctx, name),
+            None => Status::Err,
+        }
+    }
+
+    pub fn info_begin_dict_field(
+        ctx: *mut RedisModuleInfoCtx,
+        name: *const u8,
+    ) -> Status {
+        match unsafe { ValkeyModule_InfoBeginDictField } {
+            Some(info_begin_dict_field) => info_begin_dict_field(ctx, name),
+            None => Status::Err,
+        }
+    }
+
+    pub fn info_end_dict_field(ctx: *mut RedisModuleInfoCtx) -> Status {
+        match unsafe { ValkeyModule_InfoEndDictField } {
+            Some(info_end_dict_field) => info_end_dict_field(ctx),
+            None => Status::Err,
+        }
+    }
+
+    // Whether the server has the module config API, which was added in Redis
+    // 7.0.
+    pub fn has_config_api() -> bool {
+        unsafe { ValkeyModule_LoadConfigs }.is_some()
+    }
+
+    // Whether the server has the command filter APIs, including the one to
+    // find out which client is running a filtered command that was added in
+    // Redis 7.2.
+    pub fn has_command_filter_api() -> bool {
+        unsafe { ValkeyModule_RegisterCommandFilter }.is_some()
+            && unsafe { ValkeyModule_CommandFilterGetClientId }.is_some()
+    }
+
+    pub fn key_type(kp: *mut RedisModuleKey) -> KeyType {
+        unsafe { ValkeyModule_KeyType(kp) }
+    }
+
+    // Applies the values of the module's configs from the server's config
+    // file or MODULE LOADEX arguments, or their defaults. It must be called
+    // once all of them have been registered. The API is missing on older
+    // servers, in which case this returns an error.
+    pub fn load_configs(ctx: *mut RedisModuleCtx) -> Status {
+        match unsafe { ValkeyModule_LoadConfigs } {
+            Some(load_configs) => load_configs(ctx),
+            None => Status::Err,
+        }
+    }
+
+    pub fn load_signed(io: *mut RedisModuleIO) -> i64 {
+        unsafe { ValkeyModule_LoadSigned(io) }
+    }
+
+    // Loads a string saved with `save_string_buffer`. The returned buffer is
+    // owned by the caller and must be released with `free`.
+    pub fn load_string_buffer(io: *mut RedisModuleIO, len: *mut size_t) -> *mut u8 {
+        unsafe { ValkeyModule_LoadStringBuffer(io, len) }
+    }
+
+    pub fn load_unsigned(io: *mut RedisModuleIO) -> u64 {
+        unsafe { ValkeyModule_LoadUnsigned(io) }
+    }
+
     pub fn log(ctx: *mut RedisModuleCtx, level: *const u8, fmt: *const u8) {
         unsafe { ValkeyModule_Log(ctx, level, fmt) }
     }
 
+    pub fn module_type_get_type(key: *mut RedisModuleKey) -> *mut RedisModuleType {
+        unsafe { ValkeyModule_ModuleTypeGetType(key) }
+    }
+
+    pub fn module_type_get_value(key: *mut RedisModuleKey) -> *mut c_void {
+        unsafe { ValkeyModule_ModuleTypeGetValue(key) }
+    }
+
+    // Sets a key to a value of a module data type. Any existing value is freed
+    // and, like the SET command, the key's expiry is cleared.
+    pub fn module_type_set_value(
+        key: *mut RedisModuleKey,
+        mt: *mut RedisModuleType,
+        value: *mut c_void,
+    ) -> Status {
+        unsafe { ValkeyModule_ModuleTypeSetValue(key, mt, value) }
+    }
+
+    // Emits a keyspace event on a key. The API is missing on older servers,
+    // in which case this returns an error.
+    pub fn notify_keyspace_event(
+        ctx: *mut RedisModuleCtx,
+        event_type: c_int,
+        event: *const u8,
+        key: *mut RedisModuleString,
+    ) -> Status {
+        match unsafe { ValkeyModule_NotifyKeyspaceEvent } {
+            Some(notify_keyspace_event) => {
+                notify_keyspace_event(ctx, event_type, event, key)
+            }
+            None => Status::Err,
+        }
+    }
+
     pub fn open_key(
         ctx: *mut RedisModuleCtx,
         keyname: *mut RedisModuleString,
@@ -453,15 +1774,183 @@ mod inner {
         unsafe { ValkeyModule_OpenKey(ctx, keyname, mode) }
     }
 
+    // Replicates a command to replicas and the AOF. Arguments are passed as
+    // a vector of Redis strings (the "v" format specifier), which saves us
+    // from fixing their number like `call1` and friends have to.
+    // Publishes a message to a Pub/Sub channel. The API is missing on older
+    // servers, in which case this returns an error.
+    pub fn publish_message(
+        ctx: *mut RedisModuleCtx,
+        channel: *mut RedisModuleString,
+        message: *mut RedisModuleString,
+    ) -> Status {
+        match unsafe { ValkeyModule_PublishMessage } {
+            Some(publish_message) => publish_message(ctx, channel, message),
+            None => Status::Err,
+        }
+    }
+
+    pub fn replicate(
+        ctx: *mut RedisModuleCtx,
+        cmdname: *const u8,
+        argv: *const *mut RedisModuleString,
+        argc: size_t,
+    ) -> Status {
+        unsafe { ValkeyModule_Replicate(ctx, cmdname, c"v".as_ptr().cast(), argv, argc) }
+    }
+
     // Causes a command to be replicated exactly as invoked on replicas.
     pub fn replicate_verbatim(ctx: *mut RedisModuleCtx) -> Status {
         unsafe { ValkeyModule_ReplicateVerbatim(ctx) }
     }
 
+    // Registers configs that can be read and changed with CONFIG GET/SET as
+    // `<module name>.<name>`. The APIs are missing on older servers, in which
+    // case these return an error.
+    pub fn register_bool_config(
+        ctx: *mut RedisModuleCtx,
+        name: *const u8,
+        default_val: c_int,
+        flags: c_uint,
+        getfn: RedisModuleConfigGetBoolFunc,
+        setfn: RedisModuleConfigSetBoolFunc,
+    ) -> Status {
+        match unsafe { ValkeyModule_RegisterBoolConfig } {
+            Some(register_bool_config) => register_bool_config(
+                ctx,
+                name,
+                default_val,
+                flags,
+                getfn,
+                setfn,
+                None,
+                std::ptr::null_mut(),
+            ),
+            None => Status::Err,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn register_enum_config(
+        ctx: *mut RedisModuleCtx,
+        name: *const u8,
+        default_val: c_int,
+        flags: c_uint,
+        enum_values: *const *const u8,
+        int_values: *const c_int,
+        num_enum_vals: c_int,
+        getfn: RedisModuleConfigGetEnumFunc,
+        setfn: RedisModuleConfigSetEnumFunc,
+    ) -> Status {
+        match unsafe { ValkeyModule_RegisterEnumConfig } {
+            Some(register_enum_config) => register_enum_config(
+                ctx,
+                name,
+                default_val,
+                flags,
+                enum_values,
+                int_values,
+                num_enum_vals,
+                getfn,
+                setfn,
+                None,
+                std::ptr::null_mut(),
+            ),
+            None => Status::Err,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn register_numeric_config(
+        ctx: *mut RedisModuleCtx,
+        name: *const u8,
+        default_val: c_longlong,
+        flags: c_uint,
+        min: c_longlong,
+        max: c_longlong,
+        getfn: RedisModuleConfigGetNumericFunc,
+        setfn: RedisModuleConfigSetNumericFunc,
+    ) -> Status {
+        match unsafe { ValkeyModule_RegisterNumericConfig } {
+            Some(register_numeric_config) => register_numeric_config(
+                ctx,
+                name,
+                default_val,
+                flags,
+                min,
+                max,
+                getfn,
+                setfn,
+                None,
+                std::ptr::null_mut(),
+            ),
+            None => Status::Err,
+        }
+    }
+
+    pub fn register_string_config(
+        ctx: *mut RedisModuleCtx,
+        name: *const u8,
+        default_val: *const u8,
+        flags: c_uint,
+        getfn: RedisModuleConfigGetStringFunc,
+        setfn: RedisModuleConfigSetStringFunc,
+    ) -> Status {
+        match unsafe { ValkeyModule_RegisterStringConfig } {
+            Some(register_string_config) => register_string_config(
+                ctx,
+                name,
+                default_val,
+                flags,
+                getfn,
+                setfn,
+                None,
+                std::ptr::null_mut(),
+            ),
+            None => Status::Err,
+        }
+    }
+
+    // Registers a callback that's called with every command before it's run.
+    // Returns null if the API is missing.
+    pub fn register_command_filter(
+        ctx: *mut RedisModuleCtx,
+        cb: RedisModuleCommandFilterFunc,
+        flags: c_int,
+    ) -> *mut RedisModuleCommandFilter {
+        match unsafe { ValkeyModule_RegisterCommandFilter } {
+            Some(register_command_filter) => register_command_filter(ctx, cb, flags),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    // Registers a callback that adds the module's own sections to INFO. The
+    // API is missing on older servers, in which case this returns an error.
+    pub fn register_info_func(
+        ctx: *mut RedisModuleCtx,
+        cb: RedisModuleInfoFunc,
+    ) -> Status {
+        match unsafe { ValkeyModule_RegisterInfoFunc } {
+            Some(register_info_func) => register_info_func(ctx, cb),
+            None => Status::Err,
+        }
+    }
+
     pub fn reply_with_array(ctx: *mut RedisModuleCtx, len: c_long) -> Status {
         unsafe { ValkeyModule_ReplyWithArray(ctx, len) }
     }
 
+    // Replies with a boolean. The API is missing on servers older than 7, in
+    // which case this returns None.
+    pub fn reply_with_bool(ctx: *mut RedisModuleCtx, b: c_int) -> Option<Status> {
+        unsafe { ValkeyModule_ReplyWithBool }
+            .map(|reply_with_bool| reply_with_bool(ctx, b))
+    }
+
+    pub fn reply_with_double(ctx: *mut RedisModuleCtx, d: c_double) -> Status {
+        unsafe { ValkeyModule_ReplyWithDouble(ctx, d) }
+    }
+
     pub fn reply_with_error(ctx: *mut RedisModuleCtx, err: *const u8) {
         unsafe { ValkeyModule_ReplyWithError(ctx, err) }
     }
@@ -470,6 +1959,21 @@ mod inner {
         unsafe { ValkeyModule_ReplyWithLongLong(ctx, ll) }
     }
 
+    // Replies with a map. The API is missing on servers older than 7, in
+    // which case this returns None.
+    pub fn reply_with_map(ctx: *mut RedisModuleCtx, len: c_long) -> Option<Status> {
+        unsafe { ValkeyModule_ReplyWithMap }
+            .map(|reply_with_map| reply_with_map(ctx, len))
+    }
+
+    pub fn reply_with_null(ctx: *mut RedisModuleCtx) -> Status {
+        unsafe { ValkeyModule_ReplyWithNull(ctx) }
+    }
+
+    pub fn reply_with_simple_string(ctx: *mut RedisModuleCtx, msg: *const u8) -> Status {
+        unsafe { ValkeyModule_ReplyWithSimpleString(ctx, msg) }
+    }
+
     pub fn reply_with_string(
         ctx: *mut RedisModuleCtx,
         str: *mut RedisModuleString,
@@ -477,6 +1981,44 @@ mod inner {
         unsafe { ValkeyModule_ReplyWithString(ctx, str) }
     }
 
+    pub fn save_signed(io: *mut RedisModuleIO, value: i64) {
+        unsafe { ValkeyModule_SaveSigned(io, value) }
+    }
+
+    pub fn save_string_buffer(io: *mut RedisModuleIO, str: *const u8, len: size_t) {
+        unsafe { ValkeyModule_SaveStringBuffer(io, str, len) }
+    }
+
+    pub fn save_unsigned(io: *mut RedisModuleIO, value: u64) {
+        unsafe { ValkeyModule_SaveUnsigned(io, value) }
+    }
+
+    pub fn select_db(ctx: *mut RedisModuleCtx, newid: c_int) -> Status {
+        unsafe { ValkeyModule_SelectDb(ctx, newid) }
+    }
+
+    // Sets a key to expire at an absolute Unix time in milliseconds. The API
+    // is missing on older servers, in which case this returns an error.
+    pub fn set_abs_expire(key: *mut RedisModuleKey, expire: c_longlong) -> Status {
+        match unsafe { ValkeyModule_SetAbsExpire } {
+            Some(set_abs_expire) => set_abs_expire(key, expire),
+            None => Status::Err,
+        }
+    }
+
+    // Sets a callback for when a blocked client disconnects. The API is
+    // missing on older servers, in which case this does nothing.
+    pub fn set_disconnect_callback(
+        bc: *mut RedisModuleBlockedClient,
+        callback: RedisModuleDisconnectFunc,
+    ) {
+        if let Some(set_disconnect_callback) =
+            unsafe { ValkeyModule_SetDisconnectCallback }
+        {
+            set_disconnect_callback(bc, callback)
+        }
+    }
+
     // Sets the expiry on a key.
     //
     // Expire is in milliseconds.
@@ -500,6 +2042,13 @@ mod inner {
         unsafe { ValkeyModule_StringSet(key, str) }
     }
 
+    pub fn unblock_client(
+        bc: *mut RedisModuleBlockedClient,
+        privdata: *mut c_void,
+    ) -> Status {
+        unsafe { ValkeyModule_UnblockClient(bc, privdata) }
+    }
+
     // Redis doesn't make this easy for us by exporting a library, so instead what
     // we do is bake redismodule.h's symbols into a library of our construction
     // during build and link against that. See build.rs for details.
@@ -513,6 +2062,15 @@ mod inner {
             api_version: c_int,
         ) -> Status;
 
+        static ValkeyModule_BlockClient: extern "C" fn(
+            ctx: *mut RedisModuleCtx,
+            reply_callback: Option<RedisModuleCmdFunc>,
+            timeout_callback: Option<RedisModuleCmdFunc>,
+            free_privdata: Option<RedisModuleFreePrivDataFunc>,
+            timeout_ms: c_longlong,
+        )
+            -> *mut RedisModuleBlockedClient;
+
         static ValkeyModule_Call: extern "C" fn(
             ctx: *mut RedisModuleCtx,
             cmdname: *const u8,
@@ -531,6 +2089,39 @@ mod inner {
 
         static ValkeyModule_CloseKey: extern "C" fn(kp: *mut RedisModuleKey);
 
+        static ValkeyModule_CommandFilterArgDelete: Option<
+            extern "C" fn(fctx: *mut RedisModuleCommandFilterCtx, pos: c_int) -> Status,
+        >;
+
+        static ValkeyModule_CommandFilterArgGet: Option<
+            extern "C" fn(
+                fctx: *mut RedisModuleCommandFilterCtx,
+                pos: c_int,
+            ) -> *mut RedisModuleString,
+        >;
+
+        static ValkeyModule_CommandFilterArgInsert: Option<
+            extern "C" fn(
+                fctx: *mut RedisModuleCommandFilterCtx,
+                pos: c_int,
+                arg: *mut RedisModuleString,
+            ) -> Status,
+        >;
+
+        static ValkeyModule_CommandFilterArgReplace: Option<
+            extern "C" fn(
+                fctx: *mut RedisModuleCommandFilterCtx,
+                pos: c_int,
+                arg: *mut RedisModuleString,
+            ) -> Status,
+        >;
+
+        static ValkeyModule_CommandFilterArgsCount:
+            Option<extern "C" fn(fctx: *mut RedisModuleCommandFilterCtx) -> c_int>;
+
+        static ValkeyModule_CommandFilterGetClientId:
+            Option<extern "C" fn(fctx: *mut RedisModuleCommandFilterCtx) -> u64>;
+
         static ValkeyModule_CreateCommand: extern "C" fn(
             ctx: *mut RedisModuleCtx,
             name: *const u8,
@@ -541,6 +2132,14 @@ mod inner {
             keystep: c_int,
         ) -> Status;
 
+        static ValkeyModule_CreateDataType: extern "C" fn(
+            ctx: *mut RedisModuleCtx,
+            name: *const u8,
+            encver: c_int,
+            typemethods: *const RedisModuleTypeMethods,
+        )
+            -> *mut RedisModuleType;
+
         static ValkeyModule_CreateString: extern "C" fn(
             ctx: *mut RedisModuleCtx,
             ptr: *const u8,
@@ -548,41 +2147,258 @@ mod inner {
         )
             -> *mut RedisModuleString;
 
+        static ValkeyModule_CreateTimer: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                period: c_longlong,
+                callback: RedisModuleTimerProc,
+                data: *mut c_void,
+            ) -> u64,
+        >;
+
+        static ValkeyModule_DeleteKey: extern "C" fn(key: *mut RedisModuleKey) -> Status;
+
+        static ValkeyModule_DigestAddLongLong:
+            Option<extern "C" fn(md: *mut RedisModuleDigest, ll: c_longlong)>;
+
+        static ValkeyModule_DigestEndSequence:
+            Option<extern "C" fn(md: *mut RedisModuleDigest)>;
+
+        static ValkeyModule_EmitAOF: unsafe extern "C" fn(
+            io: *mut RedisModuleIO,
+            cmdname: *const u8,
+            fmt: *const u8,
+            ...
+        );
+
+        static ValkeyModule_Free: extern "C" fn(ptr: *mut c_void);
+
         static ValkeyModule_FreeCallReply:
             extern "C" fn(reply: *mut RedisModuleCallReply);
 
         static ValkeyModule_FreeString:
             extern "C" fn(ctx: *mut RedisModuleCtx, str: *mut RedisModuleString);
 
+        static ValkeyModule_GetBlockedClientPrivateData:
+            extern "C" fn(ctx: *mut RedisModuleCtx) -> *mut c_void;
+
+        static ValkeyModule_GetClientInfoById:
+            Option<extern "C" fn(ci: *mut c_void, id: u64) -> Status>;
+
+        static ValkeyModule_GetClientNameById: Option<
+            extern "C" fn(ctx: *mut RedisModuleCtx, id: u64) -> *mut RedisModuleString,
+        >;
+
+        static ValkeyModule_GetClientUserNameById: Option<
+            extern "C" fn(ctx: *mut RedisModuleCtx, id: u64) -> *mut RedisModuleString,
+        >;
+
+        static ValkeyModule_GetContextFlags:
+            Option<extern "C" fn(ctx: *mut RedisModuleCtx) -> c_int>;
+
+        static ValkeyModule_GetContextFlagsAll: Option<extern "C" fn() -> c_int>;
+
         static ValkeyModule_GetSelectedDb:
             extern "C" fn(ctx: *mut RedisModuleCtx) -> c_int;
 
+        static ValkeyModule_InfoAddFieldLongLong: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleInfoCtx,
+                field: *const u8,
+                value: c_longlong,
+            ) -> Status,
+        >;
+
+        static ValkeyModule_InfoAddSection: Option<
+            extern "C" fn(ctx: *mut RedisModuleInfoCtx, name: *const u8) -> Status,
+        >;
+
+        static ValkeyModule_InfoBeginDictField: Option<
+            extern "C" fn(ctx: *mut RedisModuleInfoCtx, name: *const u8) -> Status,
+        >;
+
+        static ValkeyModule_InfoEndDictField:
+            Option<extern "C" fn(ctx: *mut RedisModuleInfoCtx) -> Status>;
+
+        static ValkeyModule_KeyType: extern "C" fn(kp: *mut RedisModuleKey) -> KeyType;
+
+        static ValkeyModule_LoadConfigs:
+            Option<extern "C" fn(ctx: *mut RedisModuleCtx) -> Status>;
+
+        static ValkeyModule_LoadSigned: extern "C" fn(io: *mut RedisModuleIO) -> i64;
+
+        static ValkeyModule_LoadStringBuffer:
+            extern "C" fn(io: *mut RedisModuleIO, len: *mut size_t) -> *mut u8;
+
+        static ValkeyModule_LoadUnsigned: extern "C" fn(io: *mut RedisModuleIO) -> u64;
+
         static ValkeyModule_Log:
             extern "C" fn(ctx: *mut RedisModuleCtx, level: *const u8, fmt: *const u8);
 
+        static ValkeyModule_ModuleTypeGetType:
+            extern "C" fn(key: *mut RedisModuleKey) -> *mut RedisModuleType;
+
+        static ValkeyModule_ModuleTypeGetValue:
+            extern "C" fn(key: *mut RedisModuleKey) -> *mut c_void;
+
+        static ValkeyModule_ModuleTypeSetValue: extern "C" fn(
+            key: *mut RedisModuleKey,
+            mt: *mut RedisModuleType,
+            value: *mut c_void,
+        ) -> Status;
+
+        static ValkeyModule_NotifyKeyspaceEvent: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                event_type: c_int,
+                event: *const u8,
+                key: *mut RedisModuleString,
+            ) -> Status,
+        >;
+
         static ValkeyModule_OpenKey: extern "C" fn(
             ctx: *mut RedisModuleCtx,
             keyname: *mut RedisModuleString,
             mode: KeyMode,
         ) -> *mut RedisModuleKey;
 
+        static ValkeyModule_PublishMessage: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                channel: *mut RedisModuleString,
+                message: *mut RedisModuleString,
+            ) -> Status,
+        >;
+
+        static ValkeyModule_RegisterBoolConfig: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                name: *const u8,
+                default_val: c_int,
+                flags: c_uint,
+                getfn: RedisModuleConfigGetBoolFunc,
+                setfn: RedisModuleConfigSetBoolFunc,
+                applyfn: Option<RedisModuleConfigApplyFunc>,
+                privdata: *mut c_void,
+            ) -> Status,
+        >;
+
+        static ValkeyModule_RegisterCommandFilter: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                cb: RedisModuleCommandFilterFunc,
+                flags: c_int,
+            ) -> *mut RedisModuleCommandFilter,
+        >;
+
+        static ValkeyModule_RegisterEnumConfig: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                name: *const u8,
+                default_val: c_int,
+                flags: c_uint,
+                enum_values: *const *const u8,
+                int_values: *const c_int,
+                num_enum_vals: c_int,
+                getfn: RedisModuleConfigGetEnumFunc,
+                setfn: RedisModuleConfigSetEnumFunc,
+                applyfn: Option<RedisModuleConfigApplyFunc>,
+                privdata: *mut c_void,
+            ) -> Status,
+        >;
+
+        static ValkeyModule_RegisterInfoFunc: Option<
+            extern "C" fn(ctx: *mut RedisModuleCtx, cb: RedisModuleInfoFunc) -> Status,
+        >;
+
+        static ValkeyModule_RegisterNumericConfig: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                name: *const u8,
+                default_val: c_longlong,
+                flags: c_uint,
+                min: c_longlong,
+                max: c_longlong,
+                getfn: RedisModuleConfigGetNumericFunc,
+                setfn: RedisModuleConfigSetNumericFunc,
+                applyfn: Option<RedisModuleConfigApplyFunc>,
+                privdata: *mut c_void,
+            ) -> Status,
+        >;
+
+        static ValkeyModule_RegisterStringConfig: Option<
+            extern "C" fn(
+                ctx: *mut RedisModuleCtx,
+                name: *const u8,
+                default_val: *const u8,
+                flags: c_uint,
+                getfn: RedisModuleConfigGetStringFunc,
+                setfn: RedisModuleConfigSetStringFunc,
+                applyfn: Option<RedisModuleConfigApplyFunc>,
+                privdata: *mut c_void,
+            ) -> Status,
+        >;
+
+        static ValkeyModule_Replicate: unsafe extern "C" fn(
+            ctx: *mut RedisModuleCtx,
+            cmdname: *const u8,
+            fmt: *const u8,
+            ...
+        ) -> Status;
+
         static ValkeyModule_ReplicateVerbatim:
             extern "C" fn(ctx: *mut RedisModuleCtx) -> Status;
 
         static ValkeyModule_ReplyWithArray:
             extern "C" fn(ctx: *mut RedisModuleCtx, len: c_long) -> Status;
 
+        static ValkeyModule_ReplyWithBool:
+            Option<extern "C" fn(ctx: *mut RedisModuleCtx, b: c_int) -> Status>;
+
+        static ValkeyModule_ReplyWithDouble:
+            extern "C" fn(ctx: *mut RedisModuleCtx, d: c_double) -> Status;
+
         static ValkeyModule_ReplyWithError:
             extern "C" fn(ctx: *mut RedisModuleCtx, err: *const u8);
 
         static ValkeyModule_ReplyWithLongLong:
             extern "C" fn(ctx: *mut RedisModuleCtx, ll: c_longlong) -> Status;
 
+        static ValkeyModule_ReplyWithMap:
+            Option<extern "C" fn(ctx: *mut RedisModuleCtx, len: c_long) -> Status>;
+
+        static ValkeyModule_ReplyWithNull:
+            extern "C" fn(ctx: *mut RedisModuleCtx) -> Status;
+
+        static ValkeyModule_ReplyWithSimpleString:
+            extern "C" fn(ctx: *mut RedisModuleCtx, msg: *const u8) -> Status;
+
         static ValkeyModule_ReplyWithString: extern "C" fn(
             ctx: *mut RedisModuleCtx,
             str: *mut RedisModuleString,
         ) -> Status;
 
+        static ValkeyModule_SaveSigned: extern "C" fn(io: *mut RedisModuleIO, value: i64);
+
+        static ValkeyModule_SaveStringBuffer:
+            extern "C" fn(io: *mut RedisModuleIO, str: *const u8, len: size_t);
+
+        static ValkeyModule_SaveUnsigned:
+            extern "C" fn(io: *mut RedisModuleIO, value: u64);
+
+        static ValkeyModule_SelectDb:
+            extern "C" fn(ctx: *mut RedisModuleCtx, newid: c_int) -> Status;
+
+        static ValkeyModule_SetAbsExpire:
+            Option<extern "C" fn(key: *mut RedisModuleKey, expire: c_longlong) -> Status>;
+
+        static ValkeyModule_SetDisconnectCallback: Option<
+            extern "C" fn(
+                bc: *mut RedisModuleBlockedClient,
+                callback: RedisModuleDisconnectFunc,
+            ),
+        >;
+
         static ValkeyModule_SetExpire:
             extern "C" fn(key: *mut RedisModuleKey, expire: c_longlong) -> Status;
 
@@ -599,6 +2415,11 @@ mod inner {
             key: *mut RedisModuleKey,
             str: *mut RedisModuleString,
         ) -> Status;
+
+        static ValkeyModule_UnblockClient: extern "C" fn(
+            bc: *mut RedisModuleBlockedClient,
+            privdata: *mut c_void,
+        ) -> Status;
     }
 
     pub mod call1 {
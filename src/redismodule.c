@@ -1,8 +1,96 @@
 #include "redismodule.h"
 
+typedef struct RedisModuleCommandFilter RedisModuleCommandFilter;
+typedef struct RedisModuleCommandFilterCtx RedisModuleCommandFilterCtx;
+typedef void (*RedisModuleCommandFilterFunc)(RedisModuleCommandFilterCtx *filter);
+typedef struct RedisModuleInfoCtx RedisModuleInfoCtx;
+typedef void (*RedisModuleInfoFunc)(RedisModuleInfoCtx *ctx, int for_crash_report);
+typedef RedisModuleString *(*RedisModuleConfigGetStringFunc)(const char *name, void *privdata);
+typedef long long (*RedisModuleConfigGetNumericFunc)(const char *name, void *privdata);
+typedef int (*RedisModuleConfigGetBoolFunc)(const char *name, void *privdata);
+typedef int (*RedisModuleConfigGetEnumFunc)(const char *name, void *privdata);
+typedef int (*RedisModuleConfigSetStringFunc)(const char *name, RedisModuleString *val, void *privdata, RedisModuleString **err);
+typedef int (*RedisModuleConfigSetNumericFunc)(const char *name, long long val, void *privdata, RedisModuleString **err);
+typedef int (*RedisModuleConfigSetBoolFunc)(const char *name, int val, void *privdata, RedisModuleString **err);
+typedef int (*RedisModuleConfigSetEnumFunc)(const char *name, int val, void *privdata, RedisModuleString **err);
+typedef int (*RedisModuleConfigApplyFunc)(RedisModuleCtx *ctx, void *privdata, RedisModuleString **err);
+
+// The vendored redismodule.h predates a number of module APIs that we use.
+// Declare the ones that are missing here so that they get symbols in the same
+// way as the ones from the header, and look them up during initialization.
+void (*RedisModule_DigestAddLongLong)(RedisModuleDigest *md, long long ll);
+void (*RedisModule_DigestEndSequence)(RedisModuleDigest *md);
+int (*RedisModule_SetAbsExpire)(RedisModuleKey *key, long long expire);
+int (*RedisModule_GetContextFlags)(RedisModuleCtx *ctx);
+int (*RedisModule_GetContextFlagsAll)(void);
+int (*RedisModule_ReplyWithMap)(RedisModuleCtx *ctx, long len);
+int (*RedisModule_ReplyWithBool)(RedisModuleCtx *ctx, int b);
+uint64_t (*RedisModule_CreateTimer)(RedisModuleCtx *ctx, long long period, void (*callback)(RedisModuleCtx *ctx, void *data), void *data);
+void (*RedisModule_SetDisconnectCallback)(RedisModuleBlockedClient *bc, void (*callback)(RedisModuleCtx *ctx, RedisModuleBlockedClient *bc));
+int (*RedisModule_RegisterInfoFunc)(RedisModuleCtx *ctx, RedisModuleInfoFunc cb);
+int (*RedisModule_InfoAddSection)(RedisModuleInfoCtx *ctx, const char *name);
+int (*RedisModule_InfoBeginDictField)(RedisModuleInfoCtx *ctx, const char *name);
+int (*RedisModule_InfoEndDictField)(RedisModuleInfoCtx *ctx);
+int (*RedisModule_InfoAddFieldLongLong)(RedisModuleInfoCtx *ctx, const char *field, long long value);
+int (*RedisModule_RegisterBoolConfig)(RedisModuleCtx *ctx, const char *name, int default_val, unsigned int flags, RedisModuleConfigGetBoolFunc getfn, RedisModuleConfigSetBoolFunc setfn, RedisModuleConfigApplyFunc applyfn, void *privdata);
+int (*RedisModule_RegisterEnumConfig)(RedisModuleCtx *ctx, const char *name, int default_val, unsigned int flags, const char **enum_values, const int *int_values, int num_enum_vals, RedisModuleConfigGetEnumFunc getfn, RedisModuleConfigSetEnumFunc setfn, RedisModuleConfigApplyFunc applyfn, void *privdata);
+int (*RedisModule_RegisterNumericConfig)(RedisModuleCtx *ctx, const char *name, long long default_val, unsigned int flags, long long min, long long max, RedisModuleConfigGetNumericFunc getfn, RedisModuleConfigSetNumericFunc setfn, RedisModuleConfigApplyFunc applyfn, void *privdata);
+int (*RedisModule_RegisterStringConfig)(RedisModuleCtx *ctx, const char *name, const char *default_val, unsigned int flags, RedisModuleConfigGetStringFunc getfn, RedisModuleConfigSetStringFunc setfn, RedisModuleConfigApplyFunc applyfn, void *privdata);
+int (*RedisModule_LoadConfigs)(RedisModuleCtx *ctx);
+RedisModuleCommandFilter *(*RedisModule_RegisterCommandFilter)(RedisModuleCtx *ctx, RedisModuleCommandFilterFunc cb, int flags);
+int (*RedisModule_CommandFilterArgsCount)(RedisModuleCommandFilterCtx *fctx);
+RedisModuleString *(*RedisModule_CommandFilterArgGet)(RedisModuleCommandFilterCtx *fctx, int pos);
+int (*RedisModule_CommandFilterArgInsert)(RedisModuleCommandFilterCtx *fctx, int pos, RedisModuleString *arg);
+int (*RedisModule_CommandFilterArgReplace)(RedisModuleCommandFilterCtx *fctx, int pos, RedisModuleString *arg);
+int (*RedisModule_CommandFilterArgDelete)(RedisModuleCommandFilterCtx *fctx, int pos);
+unsigned long long (*RedisModule_CommandFilterGetClientId)(RedisModuleCommandFilterCtx *fctx);
+int (*RedisModule_GetClientInfoById)(void *ci, uint64_t id);
+RedisModuleString *(*RedisModule_GetClientNameById)(RedisModuleCtx *ctx, uint64_t id);
+RedisModuleString *(*RedisModule_GetClientUserNameById)(RedisModuleCtx *ctx, uint64_t id);
+int (*RedisModule_NotifyKeyspaceEvent)(RedisModuleCtx *ctx, int type, const char *event, RedisModuleString *key);
+int (*RedisModule_PublishMessage)(RedisModuleCtx *ctx, RedisModuleString *channel, RedisModuleString *message);
+
 // RedisModule_Init is defined as a static function and so won't be exported as
 // a symbol. Export a version under a slightly different name so that we can
 // get access to it from Rust.
 int Export_RedisModule_Init(RedisModuleCtx *ctx, const char *name, int ver, int apiver) {
-    return RedisModule_Init(ctx, name, ver, apiver);
+    if (RedisModule_Init(ctx, name, ver, apiver) == REDISMODULE_ERR) {
+        return REDISMODULE_ERR;
+    }
+
+    // Note that a lookup fails quietly for an API that the running server
+    // doesn't have, leaving its pointer null.
+    REDISMODULE_GET_API(DigestAddLongLong);
+    REDISMODULE_GET_API(DigestEndSequence);
+    REDISMODULE_GET_API(SetAbsExpire);
+    REDISMODULE_GET_API(GetContextFlags);
+    REDISMODULE_GET_API(GetContextFlagsAll);
+    REDISMODULE_GET_API(ReplyWithMap);
+    REDISMODULE_GET_API(ReplyWithBool);
+    REDISMODULE_GET_API(CreateTimer);
+    REDISMODULE_GET_API(SetDisconnectCallback);
+    REDISMODULE_GET_API(RegisterInfoFunc);
+    REDISMODULE_GET_API(InfoAddSection);
+    REDISMODULE_GET_API(InfoBeginDictField);
+    REDISMODULE_GET_API(InfoEndDictField);
+    REDISMODULE_GET_API(InfoAddFieldLongLong);
+    REDISMODULE_GET_API(RegisterBoolConfig);
+    REDISMODULE_GET_API(RegisterEnumConfig);
+    REDISMODULE_GET_API(RegisterNumericConfig);
+    REDISMODULE_GET_API(RegisterStringConfig);
+    REDISMODULE_GET_API(LoadConfigs);
+    REDISMODULE_GET_API(RegisterCommandFilter);
+    REDISMODULE_GET_API(CommandFilterArgsCount);
+    REDISMODULE_GET_API(CommandFilterArgGet);
+    REDISMODULE_GET_API(CommandFilterArgInsert);
+    REDISMODULE_GET_API(CommandFilterArgReplace);
+    REDISMODULE_GET_API(CommandFilterArgDelete);
+    REDISMODULE_GET_API(CommandFilterGetClientId);
+    REDISMODULE_GET_API(GetClientInfoById);
+    REDISMODULE_GET_API(GetClientNameById);
+    REDISMODULE_GET_API(GetClientUserNameById);
+    REDISMODULE_GET_API(NotifyKeyspaceEvent);
+    REDISMODULE_GET_API(PublishMessage);
+
+    return REDISMODULE_OK;
 }
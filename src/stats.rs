@@ -0,0 +1,219 @@
+// Statistics about what the module has been doing.
+//
+// Counters are kept of the commands that the module runs and of how their
+// rate limiting decisions went, along with a histogram of each command's
+// latency. They're reported in the module's section of INFO and by CL.STATS.
+// They're held in memory only, so they start from zero along with the server.
+
+use crate::cell;
+use crate::error::CellError;
+use crate::redis;
+use crate::redis::raw;
+use libc::c_int;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static CALLS: AtomicU64 = AtomicU64::new(0);
+static ALLOWED: AtomicU64 = AtomicU64::new(0);
+static THROTTLED: AtomicU64 = AtomicU64::new(0);
+static FILTER_ALLOWED: AtomicU64 = AtomicU64::new(0);
+static FILTER_REJECTED: AtomicU64 = AtomicU64::new(0);
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+
+static LATENCIES: Mutex<BTreeMap<&'static str, Histogram>> = Mutex::new(BTreeMap::new());
+
+/// Upper bounds of the buckets of a latency histogram in microseconds. Calls
+/// slower than the last bound are counted in one more bucket after these.
+pub const LATENCY_BOUNDS_US: [u64; 5] = [10, 100, 1_000, 10_000, 100_000];
+
+/// `Histogram` counts how many calls to a command took how long.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Histogram {
+    pub buckets: [u64; LATENCY_BOUNDS_US.len() + 1],
+    pub count: u64,
+    pub total_us: u64,
+}
+
+impl Histogram {
+    fn record(&mut self, elapsed_us: u64) {
+        let bucket = LATENCY_BOUNDS_US
+            .iter()
+            .position(|bound| elapsed_us <= *bound)
+            .unwrap_or(LATENCY_BOUNDS_US.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total_us = self.total_us.saturating_add(elapsed_us);
+    }
+
+    /// Gets the count in each bucket along with a label for it made from its
+    /// upper bound, like `le_100` (or `le_inf` for the last one).
+    pub fn labeled_buckets(&self) -> impl Iterator<Item = (String, u64)> + '_ {
+        LATENCY_BOUNDS_US
+            .iter()
+            .map(|bound| format!("le_{bound}"))
+            .chain(std::iter::once("le_inf".to_string()))
+            .zip(self.buckets.iter().copied())
+    }
+}
+
+/// `Stats` is a snapshot of the module's statistics.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    /// Number of times that any of the module's commands has been run.
+    pub calls: u64,
+
+    /// Number of requests that were allowed by a limit.
+    pub allowed: u64,
+
+    /// Number of requests that were throttled by a limit.
+    pub throttled: u64,
+
+    /// Number of times that a command filter rule let a command through.
+    pub filter_allowed: u64,
+
+    /// Number of times that a command filter rule rejected a command.
+    pub filter_rejected: u64,
+
+    /// Number of times that a limiter had to retry writing its state because
+    /// the key changed underneath it.
+    pub cas_retries: u64,
+
+    /// Number of commands that replied with an error.
+    pub errors: u64,
+
+    /// Latency of each command that has been run, by name.
+    pub latencies: BTreeMap<&'static str, Histogram>,
+}
+
+pub fn get() -> Stats {
+    Stats {
+        calls: CALLS.load(Ordering::Relaxed),
+        allowed: ALLOWED.load(Ordering::Relaxed),
+        throttled: THROTTLED.load(Ordering::Relaxed),
+        filter_allowed: FILTER_ALLOWED.load(Ordering::Relaxed),
+        filter_rejected: FILTER_REJECTED.load(Ordering::Relaxed),
+        cas_retries: cell::CAS_RETRIES.load(Ordering::Relaxed),
+        errors: ERRORS.load(Ordering::Relaxed),
+        latencies: LATENCIES.lock().unwrap().clone(),
+    }
+}
+
+/// Records a run of the named command, which took `elapsed` and either
+/// succeeded or replied with an error.
+pub fn record_call(command: &'static str, elapsed: std::time::Duration, ok: bool) {
+    CALLS.fetch_add(1, Ordering::Relaxed);
+    if !ok {
+        ERRORS.fetch_add(1, Ordering::Relaxed);
+    }
+    LATENCIES
+        .lock()
+        .unwrap()
+        .entry(command)
+        .or_default()
+        .record(elapsed.as_micros() as u64);
+}
+
+/// Records the decision made about a request by a limit.
+pub fn record_result(throttled: bool) {
+    if throttled {
+        THROTTLED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        ALLOWED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Records the decision made about a command by the command filter's rules.
+/// These are kept apart from the decisions of limits that are called
+/// explicitly, so that the two can be told apart.
+pub fn record_filter_result(rejected: bool) {
+    if rejected {
+        FILTER_REJECTED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        FILTER_ALLOWED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Sets every counter back to zero and forgets all latencies.
+pub fn reset() {
+    CALLS.store(0, Ordering::Relaxed);
+    ALLOWED.store(0, Ordering::Relaxed);
+    THROTTLED.store(0, Ordering::Relaxed);
+    FILTER_ALLOWED.store(0, Ordering::Relaxed);
+    FILTER_REJECTED.store(0, Ordering::Relaxed);
+    cell::CAS_RETRIES.store(0, Ordering::Relaxed);
+    ERRORS.store(0, Ordering::Relaxed);
+    LATENCIES.lock().unwrap().clear();
+}
+
+/// Registers the callback that adds the module's statistics to INFO. Servers
+/// that are too old to support this still get them from CL.STATS, so failing
+/// to register isn't an error.
+pub fn register(ctx: *mut raw::RedisModuleCtx) {
+    let _ = raw::register_info_func(ctx, info);
+}
+
+extern "C" fn info(ctx: *mut raw::RedisModuleInfoCtx, _for_crash_report: c_int) {
+    // There's nobody to report an error to from here, and INFO goes on
+    // without our sections.
+    let _ = add_info(&redis::RedisInfo::new(ctx));
+}
+
+fn add_info(info: &redis::RedisInfo) -> Result<(), CellError> {
+    let stats = get();
+
+    info.add_section("stats")?;
+    info.add_integer("calls", stats.calls as i64)?;
+    info.add_integer("allowed", stats.allowed as i64)?;
+    info.add_integer("throttled", stats.throttled as i64)?;
+    info.add_integer("filter_allowed", stats.filter_allowed as i64)?;
+    info.add_integer("filter_rejected", stats.filter_rejected as i64)?;
+    info.add_integer("cas_retries", stats.cas_retries as i64)?;
+    info.add_integer("errors", stats.errors as i64)?;
+
+    info.add_section("latency")?;
+    for (command, histogram) in &stats.latencies {
+        info.begin_dict_field(command)?;
+        for (label, count) in histogram.labeled_buckets() {
+            info.add_integer(&label, count as i64)?;
+        }
+        info.add_integer("count", histogram.count as i64)?;
+        info.add_integer("total_us", histogram.total_us as i64)?;
+        info.end_dict_field()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::*;
+
+    #[test]
+    fn it_buckets_latencies() {
+        let mut histogram = Histogram::default();
+        for elapsed_us in [0, 10, 11, 5_000, 100_000, 100_001] {
+            histogram.record(elapsed_us);
+        }
+
+        assert_eq!([2, 1, 0, 1, 1, 1], histogram.buckets);
+        assert_eq!(6, histogram.count);
+        assert_eq!(205_022, histogram.total_us);
+
+        let labels: Vec<String> = histogram
+            .labeled_buckets()
+            .map(|(label, _)| label)
+            .collect();
+        assert_eq!(
+            vec![
+                "le_10",
+                "le_100",
+                "le_1000",
+                "le_10000",
+                "le_100000",
+                "le_inf"
+            ],
+            labels
+        );
+    }
+}
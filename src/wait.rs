@@ -0,0 +1,208 @@
+// Parks clients of CL.WAIT until their request can be allowed.
+//
+// A waiting client is blocked, and a timer is set for when the limiter should
+// next have capacity for it. When the timer fires, the request is tried again
+// from within the timer's callback. If it's allowed, the client is unblocked
+// and replied to with the result, and if not, the timer is set again until the
+// client's timeout runs out.
+
+use crate::Units;
+use crate::cell;
+use crate::cell::store;
+use crate::error::CellError;
+use crate::redis;
+use crate::redis::raw;
+use crate::stats;
+use libc::{c_int, c_void};
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+// Blocked clients that have disconnected while waiting, by ID. They're
+// dropped from the set once their timer next fires and unblocks them.
+static DISCONNECTED: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+/// `Waiter` is a request that's waiting for capacity along with everything
+/// needed to try it again from a timer.
+pub struct Waiter {
+    pub key: String,
+    pub quota: cell::RateQuota,
+    pub quantity: i64,
+    pub units: Units,
+
+    /// When to give up waiting, or None to wait indefinitely.
+    pub deadline: Option<time::OffsetDateTime>,
+
+    /// How long the waiter was allowed to wait, used for the timeout error.
+    pub timeout: time::Duration,
+}
+
+// What a waiting client is unblocked with, and so what it's replied with.
+enum Outcome {
+    Result {
+        throttled: bool,
+        result: cell::RateLimitResult,
+        units: Units,
+    },
+    Error(String),
+}
+
+/// Blocks the client running the command until the waiter's request is
+/// allowed or it times out. `limited` is the result of the request's first
+/// attempt, which is replied with if the client can't be parked.
+pub fn block(
+    r: &redis::Redis,
+    waiter: Waiter,
+    limited: cell::RateLimitResult,
+) -> Result<(), CellError> {
+    let client = r.block_client(reply, free_outcome);
+    client.set_disconnect_callback(disconnect);
+
+    let db = r.selected_db();
+    let delay = next_delay(&waiter, &limited);
+    let units = waiter.units;
+    if r.create_timer(delay, retry, Box::new(Parked { client, db, waiter }))
+        .is_err()
+    {
+        // Without a timer there'd be no way to wake the client up again, so
+        // give it the result that it would've got from CL.THROTTLE.
+        stats::record_result(true);
+        client.unblock(Outcome::Result {
+            throttled: true,
+            result: limited,
+            units,
+        })?;
+    }
+    Ok(())
+}
+
+// A waiter along with the client that's parked on it. This is what's handed
+// from one timer to the next.
+struct Parked {
+    client: redis::BlockedClient,
+    db: i32,
+    waiter: Waiter,
+}
+
+// How long to wait before trying again: until the limiter says that there'll
+// be capacity, but never past the deadline.
+fn next_delay(waiter: &Waiter, result: &cell::RateLimitResult) -> time::Duration {
+    // Timers only have millisecond resolution, so round up to make sure that
+    // the next attempt isn't too early.
+    let mut delay = time::Duration::milliseconds(
+        Units::Milliseconds.round_up(result.retry_after).max(0),
+    );
+    if let Some(deadline) = waiter.deadline {
+        let left = deadline - time::OffsetDateTime::now_utc();
+        delay = delay.min(left.max(time::Duration::ZERO));
+    }
+    delay
+}
+
+extern "C" fn retry(ctx: *mut raw::RedisModuleCtx, data: *mut c_void) {
+    let parked = unsafe { Box::from_raw(data as *mut Parked) };
+    let r = redis::Redis::new(ctx);
+    let client = parked.client;
+
+    // There's nobody left to reply to, but the client still has to be
+    // unblocked for Redis to free it.
+    if DISCONNECTED.lock().unwrap().remove(&client.id()) {
+        let _ = client.unblock(Outcome::Error("Client disconnected".to_string()));
+        return;
+    }
+
+    let outcome = match try_again(&r, &parked) {
+        Ok((true, result)) => {
+            let timed_out = parked
+                .waiter
+                .deadline
+                .is_some_and(|deadline| time::OffsetDateTime::now_utc() >= deadline);
+            if !timed_out {
+                let delay = next_delay(&parked.waiter, &result);
+                match r.create_timer(delay, retry, parked) {
+                    Ok(()) => return,
+                    Err(parked) => Outcome::Result {
+                        throttled: true,
+                        result,
+                        units: parked.waiter.units,
+                    },
+                }
+            } else {
+                stats::record_result(true);
+                let e = error!(
+                    "Timed out after {}ms waiting for capacity",
+                    parked.waiter.timeout.whole_milliseconds()
+                );
+                Outcome::Error(format!("Cell error: {e}"))
+            }
+        }
+        Ok((false, result)) => Outcome::Result {
+            throttled: false,
+            result,
+            units: parked.waiter.units,
+        },
+        Err(e) => Outcome::Error(format!("Cell error: {e}")),
+    };
+
+    // Only the final decision on the request is recorded, rather than one
+    // for every attempt.
+    if let Outcome::Result { throttled, .. } = outcome {
+        stats::record_result(throttled);
+    }
+
+    if let Err(e) = client.unblock(outcome) {
+        log_debug!(r, "Couldn't unblock waiting client: {}", e);
+    }
+}
+
+// Tries the waiter's request against the database that the client had
+// selected when it started waiting. Anything written is replicated by the
+// store in the same way as it would be for CL.THROTTLE.
+fn try_again(
+    r: &redis::Redis,
+    parked: &Parked,
+) -> Result<(bool, cell::RateLimitResult), CellError> {
+    r.select_db(parked.db)?;
+
+    let waiter = &parked.waiter;
+    let mut store = store::InternalRedisStore::new(r);
+    store.set_quota(&waiter.key, &waiter.quota);
+    let mut limiter = cell::RateLimiter::new(&mut store, &waiter.quota);
+    limiter.rate_limit(&waiter.key, waiter.quantity)
+}
+
+extern "C" fn reply(
+    ctx: *mut raw::RedisModuleCtx,
+    _argv: *mut *mut raw::RedisModuleString,
+    _argc: c_int,
+) -> raw::Status {
+    let r = redis::Redis::new(ctx);
+    match r.blocked_client_private_data::<Outcome>() {
+        Some(Outcome::Result {
+            throttled,
+            result,
+            units,
+        }) => {
+            match crate::reply_rate_limit_result(&r, *throttled, result, *units, None) {
+                Ok(()) => raw::Status::Ok,
+                Err(_) => raw::Status::Err,
+            }
+        }
+        Some(Outcome::Error(message)) => {
+            r.reply_error(message);
+            raw::Status::Ok
+        }
+        None => raw::Status::Err,
+    }
+}
+
+extern "C" fn free_outcome(_ctx: *mut raw::RedisModuleCtx, privdata: *mut c_void) {
+    drop(unsafe { Box::from_raw(privdata as *mut Outcome) });
+}
+
+extern "C" fn disconnect(
+    _ctx: *mut raw::RedisModuleCtx,
+    bc: *mut raw::RedisModuleBlockedClient,
+) {
+    let client = redis::BlockedClient::new(bc);
+    DISCONNECTED.lock().unwrap().insert(client.id());
+}
@@ -67,6 +67,1064 @@ async fn it_works() {
     assert_eq!(*reset_after, Value::Int(2));
 }
 
+#[tokio::test]
+async fn it_replies_in_requested_units() {
+    let (_container, mut client) = utils::setup().await;
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(0) // with max burst
+        .arg(50) // regenerate 50 tokens
+        .arg(1) // every second
+        .arg("UNITS")
+        .arg("ms");
+
+    // allowed, with the limit resetting after 20ms rather than a whole second
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[3], Value::Int(-1)); // still -1 for allowed
+    assert_eq!(res[4], Value::Int(20));
+
+    // throttled, with a retry in no more than 20ms
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(1));
+    let Value::Int(retry_after_ms) = res[3] else {
+        unreachable!("retry_after is always an integer");
+    };
+    assert!(retry_after_ms > 0 && retry_after_ms <= 20);
+}
+
+#[tokio::test]
+async fn it_accepts_options_in_any_order() {
+    let (_container, mut client) = utils::setup().await;
+
+    // a policy can be named like an option
+    let _: () = redis::cmd("CL.POLICY.SET")
+        .arg("units")
+        .arg(0)
+        .arg(50)
+        .arg(1)
+        .query_async(&mut client)
+        .await
+        .unwrap();
+
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLE")
+        .arg("user123")
+        .arg("POLICY")
+        .arg("units")
+        .arg("UNITS")
+        .arg("ms")
+        .arg("ALGO")
+        .arg("gcra");
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[4], Value::Int(20));
+}
+
+#[tokio::test]
+async fn it_accepts_periods_with_units() {
+    let (_container, mut client) = utils::setup().await;
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(0) // with max burst
+        .arg(5) // regenerate 5 tokens
+        .arg("250ms") // every quarter second
+        .arg("UNITS")
+        .arg("ms");
+
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[4], Value::Int(50)); // one token every 50ms
+}
+
+#[tokio::test]
+async fn it_replies_with_a_map_over_resp3() {
+    let (_container, mut client) = utils::setup().await;
+    let mut hello = Cmd::new();
+    hello.arg("HELLO").arg(3);
+    client.send_packed_command(&hello).await.unwrap();
+
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(15)
+        .arg(30)
+        .arg(60);
+    let res = client.send_packed_command(&cmd).await.unwrap();
+    let Value::Map(entries) = res else {
+        panic!("expected a map, got: {res:?}");
+    };
+    let entries: Vec<(String, Value)> = entries
+        .into_iter()
+        .map(|(k, v)| (redis::from_redis_value(&k).unwrap(), v))
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            ("limited".to_string(), Value::Boolean(false)),
+            ("limit".to_string(), Value::Int(16)),
+            ("remaining".to_string(), Value::Int(15)),
+            ("retry_after".to_string(), Value::Int(-1)),
+            ("reset_after".to_string(), Value::Int(2)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn it_reserves() {
+    let (_container, mut client) = utils::setup().await;
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.RESERVE")
+        .arg("user123")
+        .arg(0) // with max burst
+        .arg(1) // regenerate 1 token
+        .arg(10) // every 10 seconds
+        .arg(1)
+        .arg("MAXWAIT")
+        .arg(15_000);
+
+    // the first request can go right away ...
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[3], Value::Int(0));
+
+    // ... the next one is scheduled rather than rejected ...
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[3], Value::Int(10));
+
+    // ... and the one after that would have to wait too long
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(1));
+    assert_eq!(res[3], Value::Int(20));
+}
+
+#[tokio::test]
+async fn it_waits() {
+    let (_container, mut client) = utils::setup().await;
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.WAIT")
+        .arg("user123")
+        .arg(0) // with max burst
+        .arg(5) // regenerate 5 tokens
+        .arg(1) // every second
+        .arg("TIMEOUT")
+        .arg(1_000);
+
+    // the first request is allowed right away ...
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+
+    // ... the next one is held until it can be allowed instead of being
+    // rejected ...
+    let start = std::time::Instant::now();
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[3], Value::Int(-1));
+    assert!(start.elapsed() >= std::time::Duration::from_millis(150));
+
+    // ... and one that can't be allowed before the timeout is an error
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.WAIT")
+        .arg("user456")
+        .arg(0)
+        .arg(1)
+        .arg(60)
+        .arg("TIMEOUT")
+        .arg(100);
+    client.send_packed_command(&cmd).await.unwrap();
+    let err = client.send_packed_command(&cmd).await.unwrap_err();
+    assert!(err.to_string().contains("Timed out after 100ms"));
+}
+
+#[tokio::test]
+async fn it_throttles_with_sliding_windows() {
+    let (_container, mut client) = utils::setup().await;
+
+    for algo in ["sliding-log", "sliding-counter"] {
+        let mut cmd = Cmd::new();
+        cmd.arg("CL.THROTTLE")
+            .arg(algo)
+            .arg(0) // max burst is ignored
+            .arg(3) // 3 requests
+            .arg(60) // in any 60 seconds
+            .arg("ALGO")
+            .arg(algo);
+
+        // exactly the count is allowed ...
+        for remaining in (0..3).rev() {
+            let res = client
+                .send_packed_command(&cmd)
+                .await
+                .unwrap()
+                .into_sequence()
+                .unwrap();
+            assert_eq!(res[0], Value::Int(0));
+            assert_eq!(res[1], Value::Int(3));
+            assert_eq!(res[2], Value::Int(remaining));
+        }
+
+        // ... and no more
+        let res = client
+            .send_packed_command(&cmd)
+            .await
+            .unwrap()
+            .into_sequence()
+            .unwrap();
+        assert_eq!(res[0], Value::Int(1));
+
+        let key_type: String = redis::cmd("TYPE")
+            .arg(algo)
+            .query_async(&mut client)
+            .await
+            .unwrap();
+        assert_eq!(key_type, "cell-wndw");
+    }
+}
+
+#[tokio::test]
+async fn it_throttles_per_calendar_window() {
+    let (_container, mut client) = utils::setup().await;
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(0) // max burst is ignored
+        .arg(2) // 2 requests
+        .arg(1) // period is ignored too
+        .arg("WINDOW")
+        .arg("day")
+        .arg("OFFSET")
+        .arg("+05:30");
+
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[1], Value::Int(2));
+    assert_eq!(res[2], Value::Int(1));
+
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+
+    // limited until the day is over at UTC+5:30, which is also when the
+    // window resets
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(1));
+    assert_eq!(res[3], res[4]);
+    let Value::Int(reset_after) = res[4] else {
+        panic!("reset_after should be an integer")
+    };
+    assert!(reset_after > 0 && reset_after <= 86_400);
+}
+
+#[tokio::test]
+async fn it_refunds() {
+    let (_container, mut client) = utils::setup().await;
+    let mut throttle = Cmd::new();
+    throttle
+        .arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(9) // with max burst
+        .arg(10) // regenerate 10 tokens
+        .arg(60) // every minute
+        .arg(10); // and take all of them
+
+    let res = client
+        .send_packed_command(&throttle)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[2], Value::Int(0));
+
+    // giving some back ...
+    let remaining: i64 = redis::cmd("CL.REFUND")
+        .arg("user123")
+        .arg(9)
+        .arg(10)
+        .arg(60)
+        .arg(4)
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 4);
+
+    // ... means that they can be used again right away
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(9)
+        .arg(10)
+        .arg(60)
+        .arg(4);
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[2], Value::Int(0));
+
+    // the quantity can't be left out
+    let res: redis::RedisResult<i64> = redis::cmd("CL.REFUND")
+        .arg("user123")
+        .arg(9)
+        .arg(10)
+        .arg(60)
+        .query_async(&mut client)
+        .await;
+    assert!(res.is_err());
+
+    // a refund to a limiter that doesn't exist doesn't create it
+    let remaining: i64 = redis::cmd("CL.REFUND")
+        .arg("user456")
+        .arg(9)
+        .arg(10)
+        .arg(60)
+        .arg(4)
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 10);
+    let exists: i64 = redis::cmd("EXISTS")
+        .arg("user456")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(exists, 0);
+}
+
+#[tokio::test]
+async fn it_grants_partially() {
+    let (_container, mut client) = utils::setup().await;
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(9) // with max burst
+        .arg(10) // regenerate 10 tokens
+        .arg(60) // every minute
+        .arg(7);
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[2], Value::Int(3));
+
+    // asking for more than is left grants what there is ...
+    let mut partial = Cmd::new();
+    partial
+        .arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(9)
+        .arg(10)
+        .arg(60)
+        .arg(5)
+        .arg("PARTIAL");
+    let res = client
+        .send_packed_command(&partial)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res.len(), 6);
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[2], Value::Int(0));
+    assert_eq!(res[5], Value::Int(3));
+
+    // ... and once there's nothing left the request is limited
+    let res = client
+        .send_packed_command(&partial)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(1));
+    assert_eq!(res[5], Value::Int(0));
+}
+
+#[tokio::test]
+async fn it_grants_partially_with_options_after_partial() {
+    let (_container, mut client) = utils::setup().await;
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(9)
+        .arg(10)
+        .arg(60_000)
+        .arg(15)
+        .arg("PARTIAL")
+        .arg("UNITS")
+        .arg("ms");
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res.len(), 6);
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[2], Value::Int(0));
+    assert_eq!(res[5], Value::Int(10));
+}
+
+#[tokio::test]
+async fn it_peeks() {
+    let (_container, mut client) = utils::setup().await;
+    let mut peek = Cmd::new();
+    peek.arg("CL.PEEK").arg("user123").arg(1).arg(1).arg(60);
+    let mut throttle = Cmd::new();
+    throttle
+        .arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(1)
+        .arg(1)
+        .arg(60);
+
+    // peeking repeatedly doesn't consume anything ...
+    for _ in 0..3 {
+        let res = client
+            .send_packed_command(&peek)
+            .await
+            .unwrap()
+            .into_sequence()
+            .unwrap();
+        assert_eq!(res[0], Value::Int(0)); // i.e. would be allowed
+        assert_eq!(res[2], Value::Int(1)); // remaining after the request
+    }
+
+    // ... but throttling does, which peeking then reflects
+    client.send_packed_command(&throttle).await.unwrap();
+    let res = client
+        .send_packed_command(&peek)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[2], Value::Int(0));
+}
+
+#[tokio::test]
+async fn it_throttles_multiple_limits() {
+    let (_container, mut client) = utils::setup().await;
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLEMULTI")
+        .arg("user123-minute") // a tight limit ...
+        .arg(0)
+        .arg(1)
+        .arg(60)
+        .arg("user123-day") // ... and a loose one
+        .arg(9)
+        .arg(10)
+        .arg(86400);
+
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0)); // allowed
+    assert_eq!(res[1], Value::Int(0)); // per minute limit is binding
+    assert_eq!(res[3], Value::Int(0)); // nothing remaining in it
+
+    // the tight limit denies the next request ...
+    let res = client
+        .send_packed_command(&cmd)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(1));
+    assert_eq!(res[1], Value::Int(0));
+    assert_eq!(res[4], Value::Int(60));
+
+    // ... without charging the loose one
+    let mut peek = Cmd::new();
+    peek.arg("CL.PEEK")
+        .arg("user123-day")
+        .arg(9)
+        .arg(10)
+        .arg(86400)
+        .arg(0);
+    let res = client
+        .send_packed_command(&peek)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[2], Value::Int(9));
+}
+
+#[tokio::test]
+async fn it_throttles_with_policies() {
+    let (_container, mut client) = utils::setup().await;
+    let _: () = redis::cmd("CL.POLICY.SET")
+        .arg("login")
+        .arg(1)
+        .arg(1)
+        .arg(60)
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    let policy: Vec<i64> = redis::cmd("CL.POLICY.GET")
+        .arg("login")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(policy, vec![1, 1, 60]);
+
+    // the policy stands in for the limit's parameters ...
+    let mut throttle = Cmd::new();
+    throttle
+        .arg("CL.THROTTLE")
+        .arg("user123")
+        .arg("POLICY")
+        .arg("login");
+    let res = client
+        .send_packed_command(&throttle)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[0], Value::Int(0));
+    assert_eq!(res[1], Value::Int(2)); // burst + 1
+    assert_eq!(res[2], Value::Int(1));
+
+    // ... until it's deleted
+    let deleted: i64 = redis::cmd("CL.POLICY.DEL")
+        .arg("login")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+    assert!(client.send_packed_command(&throttle).await.is_err());
+}
+
+#[tokio::test]
+async fn it_reports_stats() {
+    let (_container, mut client) = utils::setup().await;
+    let stat = |stats: &[Value], name: &str| {
+        stats
+            .chunks(2)
+            .find(|pair| pair[0] == Value::SimpleString(name.to_string()))
+            .map(|pair| pair[1].clone())
+            .unwrap()
+    };
+
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(0) // with max burst
+        .arg(1) // allow 1 request
+        .arg(60); // every minute
+    for _ in 0..2 {
+        client.send_packed_command(&cmd).await.unwrap();
+    }
+
+    // one of the two requests was allowed and the other throttled ...
+    let stats: Vec<Value> = redis::cmd("CL.STATS")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(stat(&stats, "calls"), Value::Int(2));
+    assert_eq!(stat(&stats, "allowed"), Value::Int(1));
+    assert_eq!(stat(&stats, "throttled"), Value::Int(1));
+    assert_eq!(stat(&stats, "errors"), Value::Int(0));
+    let latency = stat(&stats, "latency").into_sequence().unwrap();
+    let histogram = stat(&latency, "cl.throttle").into_sequence().unwrap();
+    assert_eq!(stat(&histogram, "count"), Value::Int(2));
+
+    // ... which INFO reports too ...
+    let info: String = redis::cmd("INFO")
+        .arg("everything")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert!(info.contains("redis-cell_throttled:1"));
+    assert!(info.contains("redis-cell_latency"));
+
+    // ... until they're reset
+    let _: () = redis::cmd("CL.STATS")
+        .arg("RESET")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    let stats: Vec<Value> = redis::cmd("CL.STATS")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(stat(&stats, "allowed"), Value::Int(0));
+    assert_eq!(stat(&stats, "throttled"), Value::Int(0));
+}
+
+#[tokio::test]
+async fn it_is_configurable() {
+    let (_container, mut client) = utils::setup().await;
+    let _: () = redis::cmd("CONFIG")
+        .arg("SET")
+        .arg("redis-cell.key-prefix")
+        .arg("limits:")
+        .arg("redis-cell.default-quantity")
+        .arg(2)
+        .query_async(&mut client)
+        .await
+        .unwrap();
+
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(1) // with max burst
+        .arg(30) // allow 30 requests
+        .arg(60); // every minute
+    let res: Vec<i64> = cmd.query_async(&mut client).await.unwrap();
+
+    // the default quantity of 2 has used up the whole burst ...
+    assert_eq!(res[0], 0);
+    assert_eq!(res[2], 0);
+
+    // ... and the limiter's state is stored under the prefixed key
+    let exists: i64 = redis::cmd("EXISTS")
+        .arg("limits:user123")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(exists, 1);
+
+    // out of range values are rejected
+    let res: Result<(), _> = redis::cmd("CONFIG")
+        .arg("SET")
+        .arg("redis-cell.max-cas-attempts")
+        .arg(0)
+        .query_async(&mut client)
+        .await;
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn it_notifies_when_throttled() {
+    let (container, mut client) = utils::setup().await;
+    let _: () = redis::cmd("CONFIG")
+        .arg("SET")
+        .arg("redis-cell.throttle-channel")
+        .arg("throttled")
+        .arg("redis-cell.throttle-events")
+        .arg("yes")
+        .arg("notify-keyspace-events")
+        .arg("Kd")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+
+    let mut conn = utils::connect(&container).await;
+    let mut pubsub = conn.as_pubsub();
+    pubsub
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    pubsub.subscribe("throttled").unwrap();
+    pubsub.subscribe("__keyspace@0__:user123").unwrap();
+
+    let mut cmd = Cmd::new();
+    cmd.arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(0) // with max burst
+        .arg(1) // allow 1 request
+        .arg(60) // every minute
+        .arg(2); // but ask for 2
+    let res: Vec<i64> = cmd.query_async(&mut client).await.unwrap();
+    assert_eq!(res[0], 1);
+
+    // the throttled request shows up as a keyspace event ...
+    let msg = pubsub.get_message().unwrap();
+    assert_eq!(msg.get_channel_name(), "__keyspace@0__:user123");
+    assert_eq!(msg.get_payload::<String>().unwrap(), "cl.throttled");
+
+    // ... and as a message on the channel
+    let msg = pubsub.get_message().unwrap();
+    assert_eq!(msg.get_channel_name(), "throttled");
+    assert_eq!(
+        msg.get_payload::<String>().unwrap(),
+        r#"{"key":"user123","quantity":2,"retry_after":-1}"#
+    );
+}
+
+#[tokio::test]
+async fn it_filters_commands() {
+    let (_container, mut client) = utils::setup().await;
+    let _: () = redis::cmd("CL.FILTER.SET")
+        .arg("noisy-keys")
+        .arg("KEY*")
+        .arg("USER")
+        .arg(0) // with max burst
+        .arg(1) // allow 1 call
+        .arg(60) // every minute
+        .query_async(&mut client)
+        .await
+        .unwrap();
+
+    // the first call to KEYS goes through ...
+    let keys: Vec<String> = redis::cmd("KEYS")
+        .arg("*")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert!(keys.is_empty());
+
+    // ... but the next is throttled, while other commands aren't ...
+    let err = redis::cmd("KEYS")
+        .arg("*")
+        .query_async::<Vec<String>>(&mut client)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Some("THROTTLED"));
+    let _: () = redis::cmd("SET")
+        .arg("foo")
+        .arg("bar")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+
+    // ... which are counted apart from the module's own limits ...
+    let stats: Vec<Value> = redis::cmd("CL.STATS")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    let stat = |name: &str| {
+        stats
+            .chunks(2)
+            .find(|pair| pair[0] == Value::SimpleString(name.to_string()))
+            .map(|pair| pair[1].clone())
+            .unwrap()
+    };
+    assert_eq!(stat("filter_allowed"), Value::Int(1));
+    assert_eq!(stat("filter_rejected"), Value::Int(1));
+    assert_eq!(stat("allowed"), Value::Int(0));
+    assert_eq!(stat("throttled"), Value::Int(0));
+
+    // ... until the rule is removed
+    let rules: Vec<Value> = redis::cmd("CL.FILTER.LIST")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(rules.len(), 1);
+    let deleted: i64 = redis::cmd("CL.FILTER.DEL")
+        .arg("noisy-keys")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+    let keys: Vec<String> = redis::cmd("KEYS")
+        .arg("*")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(keys, vec!["foo".to_string()]);
+}
+
+#[tokio::test]
+async fn it_limits_concurrency() {
+    let (_container, mut client) = utils::setup().await;
+    let mut acquire = Cmd::new();
+    acquire
+        .arg("CL.ACQUIRE")
+        .arg("user123")
+        .arg(2) // at most two in flight
+        .arg("30s"); // leases expire on their own after 30 seconds
+
+    // two leases can be held at once ...
+    let first: Option<i64> = acquire.query_async(&mut client).await.unwrap();
+    let second: Option<i64> = acquire.query_async(&mut client).await.unwrap();
+    assert!(first.is_some());
+    assert!(second > first);
+
+    // ... but not a third ...
+    let third: Option<i64> = acquire.query_async(&mut client).await.unwrap();
+    assert_eq!(third, None);
+
+    // ... until one of them is released
+    let released: i64 = redis::cmd("CL.RELEASE")
+        .arg("user123")
+        .arg(first.unwrap())
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(released, 1);
+    let third: Option<i64> = acquire.query_async(&mut client).await.unwrap();
+    assert!(third.is_some());
+
+    // leases are stored as a module type of their own
+    let key_type: String = redis::cmd("TYPE")
+        .arg("user123")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(key_type, "cell-sema");
+}
+
+#[tokio::test]
+async fn it_expires_leases() {
+    let (_container, mut client) = utils::setup().await;
+    let mut acquire = Cmd::new();
+    acquire.arg("CL.ACQUIRE").arg("user123").arg(1).arg("100ms");
+
+    let first: Option<i64> = acquire.query_async(&mut client).await.unwrap();
+    assert!(first.is_some());
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // the first lease was never released, but it's expired (along with the
+    // key), so a new one can be acquired
+    let exists: i64 = redis::cmd("EXISTS")
+        .arg("user123")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(exists, 0);
+    let second: Option<i64> = acquire.query_async(&mut client).await.unwrap();
+    assert!(second.is_some());
+}
+
+#[tokio::test]
+async fn it_stores_state_as_native_type() {
+    let (_container, mut client) = utils::setup().await;
+    let mut throttle = Cmd::new();
+    throttle
+        .arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(1)
+        .arg(1)
+        .arg(60);
+    client.send_packed_command(&throttle).await.unwrap();
+
+    // the limiter's state is a module type rather than a plain string ...
+    let key_type: String = redis::cmd("TYPE")
+        .arg("user123")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(key_type, "cell-gcra");
+
+    // ... that Redis knows the size of ...
+    let usage: i64 = redis::cmd("MEMORY")
+        .arg("USAGE")
+        .arg("user123")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert!(usage > 0);
+
+    // ... and that survives a round trip through DUMP and RESTORE
+    let dump: Vec<u8> = redis::cmd("DUMP")
+        .arg("user123")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    let _: () = redis::cmd("RESTORE")
+        .arg("user456")
+        .arg(0)
+        .arg(dump)
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    let mut peek = Cmd::new();
+    peek.arg("CL.PEEK").arg("user456").arg(1).arg(1).arg(60);
+    let res = client
+        .send_packed_command(&peek)
+        .await
+        .unwrap()
+        .into_sequence()
+        .unwrap();
+    assert_eq!(res[2], Value::Int(0)); // charged just like the original
+}
+
+#[tokio::test]
+async fn it_replicates_exact_state() {
+    let (primary, mut primary_client) = utils::setup().await;
+    let (_replica, mut replica_client) = utils::setup_replica(&primary).await;
+
+    let mut throttle = Cmd::new();
+    throttle
+        .arg("CL.THROTTLE")
+        .arg("user123")
+        .arg(5)
+        .arg(10)
+        .arg(60);
+    for _ in 0..3 {
+        primary_client.send_packed_command(&throttle).await.unwrap();
+    }
+
+    // wait for the writes to reach the replica ...
+    let acked: i64 = redis::cmd("WAIT")
+        .arg(1)
+        .arg(5000)
+        .query_async(&mut primary_client)
+        .await
+        .unwrap();
+    assert_eq!(acked, 1);
+
+    // ... where both the state and its expiry should be identical, rather
+    // than recomputed with the replica's own clock
+    for command in ["DUMP", "PEXPIRETIME"] {
+        let mut cmd = Cmd::new();
+        cmd.arg(command).arg("user123");
+        let on_primary = primary_client.send_packed_command(&cmd).await.unwrap();
+        let on_replica = replica_client.send_packed_command(&cmd).await.unwrap();
+        assert_ne!(on_primary, Value::Nil);
+        assert_eq!(on_primary, on_replica);
+    }
+}
+
+#[cfg(feature = "remote-store")]
+#[tokio::test]
+async fn it_rate_limits_with_a_remote_store() {
+    use redis_cell::cell::store::RemoteRedisStore;
+    use redis_cell::cell::{Rate, RateLimiter, RateQuota};
+
+    let (container, mut client) = utils::setup().await;
+    let mut store = RemoteRedisStore::new(utils::connect(&container).await);
+    let quota = RateQuota {
+        max_burst: 1,
+        max_rate: Rate::per_period(1, time::Duration::minutes(1)),
+    };
+    let mut limiter = RateLimiter::new(&mut store, &quota);
+
+    for remaining in [1, 0] {
+        let (limited, result) = limiter.rate_limit("user123", 1).unwrap();
+        assert!(!limited);
+        assert_eq!(result.remaining, remaining);
+    }
+    let (limited, result) = limiter.rate_limit("user123", 1).unwrap();
+    assert!(limited);
+    assert!(result.retry_after > time::Duration::seconds(59));
+
+    // the state is a plain string with an expiry, which the module reads too
+    let key_type: String = redis::cmd("TYPE")
+        .arg("user123")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(key_type, "string");
+    let pttl: i64 = redis::cmd("PTTL")
+        .arg("user123")
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert!(pttl > 60_000 && pttl <= 120_000);
+    let res: Vec<i64> = redis::cmd("CL.THROTTLE")
+        .arg("user123")
+        .arg(1)
+        .arg(1)
+        .arg(60)
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(res[0], 1);
+}
+
+#[cfg(all(feature = "remote-store", feature = "async"))]
+#[tokio::test]
+async fn it_rate_limits_with_an_async_remote_store() {
+    use redis_cell::cell::aio::AsyncRateLimiter;
+    use redis_cell::cell::store::AsyncRemoteRedisStore;
+    use redis_cell::cell::{Rate, RateQuota};
+
+    let (container, mut client) = utils::setup().await;
+    let port = container.get_host_port_ipv4(6379).await.unwrap();
+    let conn = redis::Client::open(("localhost", port))
+        .unwrap()
+        .get_multiplexed_async_connection()
+        .await
+        .unwrap();
+    let quota = RateQuota {
+        max_burst: 1,
+        max_rate: Rate::per_period(1, time::Duration::minutes(1)),
+    };
+    let mut limiter = AsyncRateLimiter::new(AsyncRemoteRedisStore::new(conn), &quota);
+
+    for remaining in [1, 0] {
+        let (limited, result) = limiter.rate_limit("user123", 1).await.unwrap();
+        assert!(!limited);
+        assert_eq!(result.remaining, remaining);
+    }
+    let (limited, result) = limiter.peek("user123", 1).await.unwrap();
+    assert!(limited);
+    assert!(result.retry_after > time::Duration::seconds(59));
+
+    // limiters are shared with the module, like the synchronous store's
+    let res: Vec<i64> = redis::cmd("CL.THROTTLE")
+        .arg("user123")
+        .arg(1)
+        .arg(1)
+        .arg(60)
+        .query_async(&mut client)
+        .await
+        .unwrap();
+    assert_eq!(res[0], 1);
+}
+
 mod utils {
     use redis::aio::ConnectionManager;
     use std::sync::LazyLock;
@@ -94,6 +1152,43 @@ mod utils {
             .unwrap_or(1000)
     });
 
+    // Opens a plain connection to the given instance, like one for
+    // subscribing to channels.
+    pub(super) async fn connect(
+        container: &ContainerAsync<GenericImage>,
+    ) -> redis::Connection {
+        let port = container.get_host_port_ipv4(6379).await.unwrap();
+        let client = redis::Client::open(("localhost", port)).unwrap();
+        client.get_connection().unwrap()
+    }
+
+    // Starts another instance and makes it a replica of the given one, waiting
+    // until it's caught up before returning.
+    pub(super) async fn setup_replica(
+        primary: &ContainerAsync<GenericImage>,
+    ) -> (ContainerAsync<GenericImage>, ConnectionManager) {
+        let primary_ip = primary.get_bridge_ip_address().await.unwrap();
+        let (container, mut client) = setup().await;
+        let _: () = redis::cmd("REPLICAOF")
+            .arg(primary_ip.to_string())
+            .arg(6379)
+            .query_async(&mut client)
+            .await
+            .unwrap();
+        loop {
+            let info: String = redis::cmd("INFO")
+                .arg("replication")
+                .query_async(&mut client)
+                .await
+                .unwrap();
+            if info.contains("master_link_status:up") {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        (container, client)
+    }
+
     pub(super) async fn setup() -> (ContainerAsync<GenericImage>, ConnectionManager) {
         let image_name = if cfg!(feature = "valkey") {
             "valkey-cell"